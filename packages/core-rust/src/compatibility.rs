@@ -11,10 +11,11 @@ pub fn validate_manifest(manifest: JsValue, host: JsValue) -> Result<JsValue, Js
     let mut errors: Vec<Value> = Vec::new();
     let mut warnings: Vec<Value> = Vec::new();
     let mut isolated_stores: Vec<Value> = Vec::new();
+    let mut migrations: Vec<Value> = Vec::new();
 
     validate_abi(&manifest, &host, &mut errors);
     validate_dependencies(&manifest, &host, &mut errors, &mut warnings);
-    validate_stores(&manifest, &host, &mut errors, &mut warnings, &mut isolated_stores);
+    validate_stores(&manifest, &host, &mut errors, &mut warnings, &mut isolated_stores, &mut migrations);
     validate_accelerators(&manifest, &host, &mut errors, &mut warnings);
 
     to_js(&ValidationResult {
@@ -22,6 +23,7 @@ pub fn validate_manifest(manifest: JsValue, host: JsValue) -> Result<JsValue, Js
         errors,
         warnings,
         isolated_stores,
+        migrations,
     })
 }
 
@@ -32,6 +34,7 @@ struct ValidationResult {
     warnings: Vec<Value>,
     #[serde(rename = "isolatedStores")]
     isolated_stores: Vec<Value>,
+    migrations: Vec<Value>,
 }
 
 fn validate_abi(manifest: &Value, host: &Value, errors: &mut Vec<Value>) {
@@ -96,6 +99,7 @@ fn validate_stores(
     errors: &mut Vec<Value>,
     warnings: &mut Vec<Value>,
     isolated_stores: &mut Vec<Value>,
+    migrations: &mut Vec<Value>,
 ) {
     for store in manifest.get("stores").and_then(Value::as_array).cloned().unwrap_or_default() {
         let store_id = store.get("storeId").and_then(Value::as_str).unwrap_or("");
@@ -110,6 +114,20 @@ fn validate_stores(
         match host_store_schema(host, store_id) {
             Some((provided_schema_id, provided_version))
                 if provided_schema_id == schema_id && version_satisfies(&provided_version, required) => {}
+            Some((provided_schema_id, provided_version)) if policy == "migrate" && provided_schema_id == schema_id => {
+                migrations.push(serde_json::json!({
+                    "storeId": store_id,
+                    "schemaId": schema_id,
+                    "fromVersion": provided_version,
+                    "targetVersion": required,
+                }));
+                warnings.push(validation_issue(
+                    "STORE_SCHEMA_MIGRATION_REQUIRED",
+                    &format!("Store {store_id} must migrate from {provided_version} to {required}"),
+                    "warning",
+                    store_id,
+                ));
+            }
             Some((_provided_schema_id, provided_version)) if policy == "isolate" => {
                 isolated_stores.push(Value::String(store_id.to_string()));
                 warnings.push(validation_issue(
@@ -243,7 +261,7 @@ fn host_accelerator(host: &Value, kind: &str) -> Option<Value> {
     })
 }
 
-fn version_satisfies(provided: &str, required: &str) -> bool {
+pub(crate) fn version_satisfies(provided: &str, required: &str) -> bool {
     if required.is_empty() || required == "*" {
         return true;
     }
@@ -274,6 +292,13 @@ fn parse_version(version: &str) -> Option<(i32, i32, i32)> {
     Some((numbers.next()??, numbers.next()??, numbers.next().flatten().unwrap_or(0)))
 }
 
+pub(crate) fn compare_versions(left: &str, right: &str) -> std::cmp::Ordering {
+    match (parse_version(left), parse_version(right)) {
+        (Some(left), Some(right)) => compare_version(left, right).cmp(&0),
+        _ => left.cmp(right),
+    }
+}
+
 fn compare_version(left: (i32, i32, i32), right: (i32, i32, i32)) -> i32 {
     let major = left.0 - right.0;
     if major != 0 {
@@ -358,13 +383,42 @@ mod tests {
         let mut warnings = vec![];
         let mut isolated = vec![];
 
-        validate_stores(&manifest, &host, &mut errors, &mut warnings, &mut isolated);
+        validate_stores(&manifest, &host, &mut errors, &mut warnings, &mut isolated, &mut vec![]);
 
         assert!(errors.is_empty());
         assert_eq!(warnings[0]["code"], "STORE_SCHEMA_ISOLATED");
         assert_eq!(isolated, vec![Value::String("orders".to_string())]);
     }
 
+    #[test]
+    fn schema_conflict_with_migrate_policy_requests_migration() {
+        let manifest = json!({
+            "stores": [
+                {
+                    "storeId": "orders",
+                    "schemaId": "orders-state",
+                    "schemaVersion": "^2.0.0",
+                    "conflictPolicy": "migrate"
+                }
+            ]
+        });
+        let host = json!({
+            "stores": [
+                { "storeId": "orders", "schemaId": "orders-state", "schemaVersion": "1.2.0" }
+            ]
+        });
+        let mut errors = vec![];
+        let mut warnings = vec![];
+        let mut migrations = vec![];
+
+        validate_stores(&manifest, &host, &mut errors, &mut warnings, &mut vec![], &mut migrations);
+
+        assert!(errors.is_empty());
+        assert_eq!(warnings[0]["code"], "STORE_SCHEMA_MIGRATION_REQUIRED");
+        assert_eq!(migrations[0]["fromVersion"], "1.2.0");
+        assert_eq!(migrations[0]["targetVersion"], "^2.0.0");
+    }
+
     #[test]
     fn accelerator_capability_missing_is_error() {
         let manifest = json!({
@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use wasm_bindgen::prelude::*;
 
+use crate::runtime::RuntimeFilter;
 use crate::{from_js, js_error, next_id, to_js};
 
 thread_local! {
@@ -11,7 +12,7 @@ thread_local! {
 }
 
 #[derive(Clone, Serialize, Deserialize)]
-struct WasmContainer {
+pub(crate) struct WasmContainer {
     id: String,
     name: String,
     status: String,
@@ -156,6 +157,37 @@ pub fn cleanup_containers() {
     });
 }
 
+pub(crate) fn export_runtime_containers(filter: &RuntimeFilter) -> Result<BTreeMap<String, Value>, String> {
+    CONTAINERS.with(|containers| {
+        containers
+            .borrow()
+            .iter()
+            .filter(|(container_id, _)| filter.allows(container_id))
+            .map(|(container_id, container)| {
+                let entry = serde_json::to_value(container).map_err(|error| error.to_string())?;
+                Ok((container_id.clone(), entry))
+            })
+            .collect()
+    })
+}
+
+pub(crate) fn decode_runtime_containers(entries: BTreeMap<String, Value>) -> Result<Vec<(String, WasmContainer)>, String> {
+    entries
+        .into_iter()
+        .map(|(container_id, entry)| {
+            let container = serde_json::from_value(entry)
+                .map_err(|error| format!("Invalid container {container_id}: {error}"))?;
+            Ok((container_id, container))
+        })
+        .collect()
+}
+
+pub(crate) fn install_runtime_containers(entries: Vec<(String, WasmContainer)>) {
+    CONTAINERS.with(|containers| {
+        containers.borrow_mut().extend(entries);
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,318 @@
+use imbl::ordmap::DiffItem;
+use serde_json::{json, Value};
+use std::rc::Rc;
+
+use crate::store_tree::StateTree;
+
+/// Applies an RFC 6902 patch document in place.
+///
+/// Operations run in order against `state`; on error the document may be
+/// partially modified, so callers apply patches to a working copy and only
+/// commit it when every operation succeeded.
+pub(crate) fn apply_patch(state: &mut StateTree, operations: &[Value]) -> Result<(), String> {
+    for (index, operation) in operations.iter().enumerate() {
+        apply_operation(state, operation)
+            .map_err(|error| format!("PATCH operation {index} failed: {error}"))?;
+    }
+    Ok(())
+}
+
+/// Computes an RFC 6902 patch that turns `before` into `after`.
+///
+/// Objects and arrays are walked recursively so unchanged subtrees produce no
+/// operations; array length changes are expressed as trailing removes/adds.
+/// Subtrees the two states still share are skipped without being visited, so
+/// diffing a state against an earlier clone costs O(changed).
+pub(crate) fn diff(before: &StateTree, after: &StateTree) -> Vec<Value> {
+    let mut operations = Vec::new();
+    diff_into(before, after, "", &mut operations);
+    operations
+}
+
+/// Escapes a reference token for use inside a JSON Pointer.
+pub(crate) fn escape_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+fn diff_into(before: &StateTree, after: &StateTree, path: &str, operations: &mut Vec<Value>) {
+    match (before, after) {
+        (StateTree::Object(before_object), StateTree::Object(after_object)) => {
+            let mut added = Vec::new();
+            for item in before_object.diff(after_object) {
+                match item {
+                    DiffItem::Update {
+                        old: (key, before_value),
+                        new: (_, after_value),
+                    } => diff_into(before_value, after_value, &format!("{path}/{}", escape_token(key)), operations),
+                    DiffItem::Remove(key, _) => {
+                        operations.push(json!({ "op": "remove", "path": format!("{path}/{}", escape_token(key)) }));
+                    }
+                    DiffItem::Add(key, after_value) => {
+                        let child = format!("{path}/{}", escape_token(key));
+                        added.push(json!({ "op": "add", "path": child, "value": after_value }));
+                    }
+                }
+            }
+            operations.extend(added);
+        }
+        (StateTree::Array(before_array), StateTree::Array(after_array)) => {
+            if before_array.ptr_eq(after_array) {
+                return;
+            }
+            let common = before_array.len().min(after_array.len());
+            for (index, (before_item, after_item)) in before_array.iter().zip(after_array.iter()).enumerate() {
+                diff_into(before_item, after_item, &format!("{path}/{index}"), operations);
+            }
+            for index in (common..before_array.len()).rev() {
+                operations.push(json!({ "op": "remove", "path": format!("{path}/{index}") }));
+            }
+            for (index, value) in after_array.iter().enumerate().skip(common) {
+                operations.push(json!({ "op": "add", "path": format!("{path}/{index}"), "value": value }));
+            }
+        }
+        _ if before != after => operations.push(json!({ "op": "replace", "path": path, "value": after })),
+        _ => {}
+    }
+}
+
+/// Parses an RFC 6901 JSON Pointer into unescaped reference tokens.
+pub(crate) fn parse_pointer(pointer: &str) -> Result<Vec<String>, String> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(format!("JSON Pointer must start with '/': {pointer}"));
+    };
+
+    rest.split('/').map(|token| unescape_token(token, pointer)).collect()
+}
+
+fn unescape_token(token: &str, pointer: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(token.len());
+    let mut characters = token.chars();
+    while let Some(character) = characters.next() {
+        if character != '~' {
+            unescaped.push(character);
+            continue;
+        }
+        match characters.next() {
+            Some('0') => unescaped.push('~'),
+            Some('1') => unescaped.push('/'),
+            _ => return Err(format!("Invalid '~' escape in JSON Pointer: {pointer}")),
+        }
+    }
+    Ok(unescaped)
+}
+
+fn apply_operation(state: &mut StateTree, operation: &Value) -> Result<(), String> {
+    let op = operation
+        .get("op")
+        .and_then(Value::as_str)
+        .ok_or("operation requires op")?;
+    let path = operation
+        .get("path")
+        .and_then(Value::as_str)
+        .ok_or("operation requires path")?;
+    let tokens = parse_pointer(path)?;
+
+    match op {
+        "add" => add(state, &tokens, operation_value(operation)?.into()),
+        "remove" => remove(state, &tokens).map(|_| ()),
+        "replace" => {
+            let target = pointer_get_mut(state, &tokens).ok_or_else(|| format!("path not found: {path}"))?;
+            *target = operation_value(operation)?.into();
+            Ok(())
+        }
+        "move" => {
+            let from = operation_from(operation)?;
+            let from_tokens = parse_pointer(from)?;
+            if tokens.len() > from_tokens.len() && tokens.starts_with(&from_tokens) {
+                return Err(format!("cannot move {from} into its own child {path}"));
+            }
+            let value = remove(state, &from_tokens)?;
+            add(state, &tokens, value)
+        }
+        "copy" => {
+            let from = operation_from(operation)?;
+            let value = pointer_get(state, &parse_pointer(from)?)
+                .cloned()
+                .ok_or_else(|| format!("path not found: {from}"))?;
+            add(state, &tokens, value)
+        }
+        "test" => {
+            let expected = operation.get("value").ok_or("test requires value")?;
+            let actual = pointer_get(state, &tokens).ok_or_else(|| format!("path not found: {path}"))?;
+            if actual != expected {
+                return Err(format!("test failed at {path}"));
+            }
+            Ok(())
+        }
+        other => Err(format!("unsupported op: {other}")),
+    }
+}
+
+fn operation_value(operation: &Value) -> Result<Value, String> {
+    operation
+        .get("value")
+        .cloned()
+        .ok_or_else(|| "operation requires value".to_string())
+}
+
+fn operation_from(operation: &Value) -> Result<&str, String> {
+    operation
+        .get("from")
+        .and_then(Value::as_str)
+        .ok_or_else(|| "operation requires from".to_string())
+}
+
+fn pointer_get<'a>(state: &'a StateTree, tokens: &[String]) -> Option<&'a StateTree> {
+    tokens.iter().try_fold(state, |current, token| match current {
+        StateTree::Object(object) => object.get(token.as_str()),
+        StateTree::Array(array) => array.get(array_index(token, array.len()).ok()?),
+        _ => None,
+    })
+}
+
+fn pointer_get_mut<'a>(state: &'a mut StateTree, tokens: &[String]) -> Option<&'a mut StateTree> {
+    tokens.iter().try_fold(state, |current, token| match current {
+        StateTree::Object(object) => object.get_mut(token.as_str()),
+        StateTree::Array(array) => {
+            let index = array_index(token, array.len()).ok()?;
+            array.get_mut(index)
+        }
+        _ => None,
+    })
+}
+
+fn add(state: &mut StateTree, tokens: &[String], value: StateTree) -> Result<(), String> {
+    let Some((last, parent_tokens)) = tokens.split_last() else {
+        *state = value;
+        return Ok(());
+    };
+    let parent = pointer_get_mut(state, parent_tokens).ok_or("parent path not found")?;
+
+    match parent {
+        StateTree::Object(object) => {
+            object.insert(Rc::from(last.as_str()), value);
+            Ok(())
+        }
+        StateTree::Array(array) if last == "-" => {
+            array.push_back(value);
+            Ok(())
+        }
+        StateTree::Array(array) => {
+            let index = array_index(last, array.len() + 1)?;
+            array.insert(index, value);
+            Ok(())
+        }
+        _ => Err("parent is not an object or array".to_string()),
+    }
+}
+
+fn remove(state: &mut StateTree, tokens: &[String]) -> Result<StateTree, String> {
+    let (last, parent_tokens) = tokens
+        .split_last()
+        .ok_or("cannot remove the document root")?;
+    let parent = pointer_get_mut(state, parent_tokens).ok_or("parent path not found")?;
+
+    match parent {
+        StateTree::Object(object) => object
+            .remove(last.as_str())
+            .ok_or_else(|| format!("key not found: {last}")),
+        StateTree::Array(array) => {
+            let index = array_index(last, array.len())?;
+            Ok(array.remove(index))
+        }
+        _ => Err("parent is not an object or array".to_string()),
+    }
+}
+
+fn array_index(token: &str, len: usize) -> Result<usize, String> {
+    let valid = !token.is_empty()
+        && token.bytes().all(|byte| byte.is_ascii_digit())
+        && (token == "0" || !token.starts_with('0'));
+    if !valid {
+        return Err(format!("invalid array index: {token}"));
+    }
+    let index = token
+        .parse::<usize>()
+        .map_err(|_| format!("invalid array index: {token}"))?;
+    if index >= len {
+        return Err(format!("array index out of bounds: {token}"));
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pointer_unescapes_tilde_and_slash() {
+        assert_eq!(parse_pointer("/a~1b/c~0d").unwrap(), vec!["a/b", "c~d"]);
+        assert!(parse_pointer("a/b").is_err());
+        assert!(parse_pointer("/a~2").is_err());
+    }
+
+    #[test]
+    fn patch_applies_every_operation_kind() {
+        let mut state = StateTree::from(json!({ "items": ["a", "c"], "user": { "name": "A" } }));
+        apply_patch(&mut state, &[
+            json!({ "op": "add", "path": "/items/1", "value": "b" }),
+            json!({ "op": "add", "path": "/items/-", "value": "d" }),
+            json!({ "op": "replace", "path": "/user/name", "value": "B" }),
+            json!({ "op": "copy", "from": "/user/name", "path": "/owner" }),
+            json!({ "op": "move", "from": "/items/0", "path": "/first" }),
+            json!({ "op": "remove", "path": "/items/2" }),
+            json!({ "op": "test", "path": "/owner", "value": "B" }),
+        ]).unwrap();
+
+        assert_eq!(state, json!({
+            "items": ["b", "c"],
+            "user": { "name": "B" },
+            "owner": "B",
+            "first": "a"
+        }));
+    }
+
+    #[test]
+    fn failed_test_operation_reports_its_index() {
+        let mut state = StateTree::from(json!({ "count": 1 }));
+        let error = apply_patch(&mut state, &[
+            json!({ "op": "replace", "path": "/count", "value": 2 }),
+            json!({ "op": "test", "path": "/count", "value": 1 }),
+        ]).unwrap_err();
+
+        assert!(error.starts_with("PATCH operation 1 failed"));
+    }
+
+    #[test]
+    fn diff_round_trips_between_states() {
+        let before = StateTree::from(json!({ "a/b": 1, "list": [1, 2, 3], "user": { "name": "A", "age": 7 } }));
+        let after = StateTree::from(json!({ "a/b": 2, "list": [1, 4], "user": { "name": "A" }, "tags": ["x"] }));
+
+        let operations = diff(&before, &after);
+        let mut state = before.clone();
+        apply_patch(&mut state, &operations).unwrap();
+        assert_eq!(state, after);
+        assert!(diff(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn diff_against_a_clone_reports_only_the_written_path() {
+        let items: Vec<Value> = (0..1000).map(|id| json!({ "id": id, "done": false })).collect();
+        let before = StateTree::from(json!({ "todos": items, "filter": "all" }));
+        let mut after = before.clone();
+        after
+            .set(&["todos".to_string(), "500".to_string(), "done".to_string()], json!(true).into())
+            .unwrap();
+
+        assert_eq!(diff(&before, &after), vec![json!({ "op": "replace", "path": "/todos/500/done", "value": true })]);
+    }
+
+    #[test]
+    fn move_into_own_child_is_rejected() {
+        let mut state = StateTree::from(json!({ "a": { "b": 1 } }));
+        assert!(apply_patch(&mut state, &[json!({ "op": "move", "from": "/a", "path": "/a/c" })]).is_err());
+    }
+}
@@ -5,14 +5,36 @@ use wasm_bindgen::prelude::*;
 
 mod compatibility;
 mod container;
+mod json_patch;
 mod render_math;
 mod render;
+mod runtime;
+mod schema_validation;
 mod store;
+mod store_codec;
+mod store_computed;
+mod store_crdt;
+mod store_delivery;
+mod store_devtools;
+mod store_history;
+mod store_journal;
+mod store_lanes;
+mod store_migrations;
+mod store_persistence;
+mod store_reducers;
+mod store_scheduler;
+mod store_selector;
+mod store_snapshots;
+mod store_sync;
+mod store_tree;
 
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     fn log(message: &str);
+
+    #[wasm_bindgen(js_namespace = console)]
+    pub(crate) fn warn(message: &str);
 }
 
 thread_local! {
@@ -1,11 +1,12 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use wasm_bindgen::prelude::*;
 
 use crate::{from_js, js_error, next_id, to_js};
 use crate::render_math::{compose_matrix, multiply_matrix};
+use crate::runtime::RuntimeFilter;
 
 thread_local! {
     static RENDER_STORES: RefCell<HashMap<String, RenderStore>> = RefCell::new(HashMap::new());
@@ -118,7 +119,9 @@ struct DirtyState {
     frame: bool,
 }
 
-struct RenderStore {
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RenderStore {
     id: String,
     frame: u64,
     time_ms: f64,
@@ -131,6 +134,7 @@ struct RenderStore {
     command_log: Vec<TimelineCommand>,
     redo_log: Vec<TimelineCommand>,
     next_instance_index: usize,
+    #[serde(skip)]
     dirty: DirtyState,
 }
 
@@ -198,6 +202,20 @@ impl RenderStore {
         self.dirty.screens = true;
     }
 
+    /// Marks everything dirty so the next patch or buffer read re-uploads
+    /// the whole store, e.g. after it was restored from a runtime document.
+    fn mark_all_dirty(&mut self) {
+        self.dirty.transforms.extend(self.entity_order.iter().cloned());
+        self.dirty.materials.extend(
+            self.entities
+                .values()
+                .filter(|entity| entity.material_id.is_some())
+                .map(|entity| entity.id.clone()),
+        );
+        self.dirty.screens = true;
+        self.dirty.frame = true;
+    }
+
     fn insert_entity(&mut self, mut entity: RenderEntity) {
         if entity.instance_index == usize::MAX {
             entity.instance_index = self.next_instance_index;
@@ -921,6 +939,38 @@ pub fn cleanup_render_store(store_id: &str) {
     });
 }
 
+pub(crate) fn export_runtime_render_stores(filter: &RuntimeFilter) -> Result<BTreeMap<String, Value>, String> {
+    RENDER_STORES.with(|stores| {
+        stores
+            .borrow()
+            .iter()
+            .filter(|(store_id, _)| filter.allows(store_id))
+            .map(|(store_id, store)| {
+                let entry = serde_json::to_value(store).map_err(|error| error.to_string())?;
+                Ok((store_id.clone(), entry))
+            })
+            .collect()
+    })
+}
+
+pub(crate) fn decode_runtime_render_stores(entries: BTreeMap<String, Value>) -> Result<Vec<(String, RenderStore)>, String> {
+    entries
+        .into_iter()
+        .map(|(store_id, entry)| {
+            let mut store: RenderStore = serde_json::from_value(entry)
+                .map_err(|error| format!("Invalid render store {store_id}: {error}"))?;
+            store.mark_all_dirty();
+            Ok((store_id, store))
+        })
+        .collect()
+}
+
+pub(crate) fn install_runtime_render_stores(entries: Vec<(String, RenderStore)>) {
+    RENDER_STORES.with(|stores| {
+        stores.borrow_mut().extend(entries);
+    });
+}
+
 #[wasm_bindgen]
 pub fn benchmark_render_matrix_buffer(entity_count: u32) -> Result<JsValue, JsValue> {
     let count = entity_count.max(1);
@@ -1595,4 +1645,31 @@ mod tests {
             elapsed
         );
     }
+
+    #[test]
+    fn runtime_round_trip_restores_entities_as_dirty() {
+        let mut store = RenderStore::new("scene".to_string(), "home".to_string());
+        store.insert_entity(RenderEntity {
+            id: "cube".to_string(),
+            parent_id: None,
+            instance_index: usize::MAX,
+            transform: Transform::default(),
+            size: [100.0, 100.0],
+            material_id: Some("steel".to_string()),
+            mesh_id: None,
+            visible: true,
+            locked: false,
+        });
+        store.tick(16.0);
+        let entry = serde_json::to_value(&store).unwrap();
+
+        let (store_id, restored) = decode_runtime_render_stores(BTreeMap::from([("scene".to_string(), entry)]))
+            .unwrap()
+            .remove(0);
+        assert_eq!(store_id, "scene");
+        assert_eq!(restored.frame, 1);
+        assert_eq!(restored.next_instance_index, 1);
+        assert!(restored.dirty.transforms.contains("cube"));
+        assert!(restored.dirty.materials.contains("cube"));
+    }
 }
@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use wasm_bindgen::prelude::*;
+
+use crate::store_journal::state_hash;
+use crate::{container, from_js, js_error, render, store, to_js};
+
+pub(crate) const RUNTIME_FORMAT: &str = "gaesup-runtime";
+pub(crate) const RUNTIME_VERSION: u32 = 1;
+
+/// Include/exclude lists of ids, matched in every section of the document.
+#[derive(Default, Deserialize)]
+pub(crate) struct RuntimeFilter {
+    #[serde(default)]
+    include: Option<Vec<String>>,
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+impl RuntimeFilter {
+    fn parse(options: JsValue) -> Result<Self, JsValue> {
+        let options = from_js(options)?;
+        if options.is_null() {
+            return Ok(Self::default());
+        }
+        serde_json::from_value(options).map_err(|error| js_error(&format!("Invalid runtime options: {error}")))
+    }
+
+    pub(crate) fn allows(&self, id: &str) -> bool {
+        let included = self
+            .include
+            .as_ref()
+            .is_none_or(|include| include.iter().any(|candidate| candidate == id));
+        included && !self.exclude.iter().any(|candidate| candidate == id)
+    }
+
+    fn retain(&self, entries: &mut BTreeMap<String, Value>) {
+        entries.retain(|id, _| self.allows(id));
+    }
+}
+
+/// Versioned dump of every store, render store and container. The checksum
+/// covers all three sections.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RuntimeDocument {
+    format: String,
+    version: u32,
+    checksum: String,
+    #[serde(default)]
+    stores: BTreeMap<String, Value>,
+    #[serde(default)]
+    render_stores: BTreeMap<String, Value>,
+    #[serde(default)]
+    containers: BTreeMap<String, Value>,
+}
+
+impl RuntimeDocument {
+    fn new(
+        stores: BTreeMap<String, Value>,
+        render_stores: BTreeMap<String, Value>,
+        containers: BTreeMap<String, Value>,
+    ) -> Self {
+        let mut document = Self {
+            format: RUNTIME_FORMAT.to_string(),
+            version: RUNTIME_VERSION,
+            checksum: String::new(),
+            stores,
+            render_stores,
+            containers,
+        };
+        document.normalize();
+        document.checksum = document.compute_checksum();
+        document
+    }
+
+    fn verify(&mut self) -> Result<(), String> {
+        if self.format != RUNTIME_FORMAT {
+            return Err(format!("Unknown runtime document format: {}", self.format));
+        }
+        if self.version != RUNTIME_VERSION {
+            return Err(format!("Unsupported runtime document version: {}", self.version));
+        }
+        self.normalize();
+        let checksum = self.compute_checksum();
+        if checksum != self.checksum {
+            return Err(format!("Runtime document checksum mismatch: expected {}, got {checksum}", self.checksum));
+        }
+        Ok(())
+    }
+
+    /// JS hands integral floats back as integers, so they are stored as
+    /// integers up front to keep the checksum stable across a round trip.
+    fn normalize(&mut self) {
+        for entries in [&mut self.stores, &mut self.render_stores, &mut self.containers] {
+            entries.values_mut().for_each(normalize_numbers);
+        }
+    }
+
+    fn compute_checksum(&self) -> String {
+        state_hash(&(&self.stores, &self.render_stores, &self.containers))
+    }
+}
+
+fn normalize_numbers(value: &mut Value) {
+    match value {
+        Value::Number(number) if number.is_f64() => {
+            let float = number.as_f64().unwrap_or_default();
+            if float.fract() == 0.0 && float.abs() <= 9_007_199_254_740_991.0 {
+                *value = Value::from(float as i64);
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(normalize_numbers),
+        Value::Object(object) => object.values_mut().for_each(normalize_numbers),
+        _ => {}
+    }
+}
+
+/// Serializes every store (state, schema, snapshots), render store and
+/// container into one checksummed document. `options` may carry `include`
+/// and `exclude` id lists.
+#[wasm_bindgen]
+pub fn export_runtime(options: JsValue) -> Result<JsValue, JsValue> {
+    let filter = RuntimeFilter::parse(options)?;
+    let document = RuntimeDocument::new(
+        store::export_runtime_stores(&filter).map_err(|error| js_error(&error))?,
+        render::export_runtime_render_stores(&filter).map_err(|error| js_error(&error))?,
+        container::export_runtime_containers(&filter).map_err(|error| js_error(&error))?,
+    );
+    to_js(&document)
+}
+
+/// Restores an `export_runtime` document after checking its format, version
+/// and checksum. Every entry is decoded and validated before anything is
+/// replaced; existing stores are updated in place and notify subscribers.
+#[wasm_bindgen]
+pub fn import_runtime(document: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+    let filter = RuntimeFilter::parse(options)?;
+    let mut document: RuntimeDocument = serde_json::from_value(from_js(document)?)
+        .map_err(|error| js_error(&format!("Invalid runtime document: {error}")))?;
+    document.verify().map_err(|error| js_error(&error))?;
+    filter.retain(&mut document.stores);
+    filter.retain(&mut document.render_stores);
+    filter.retain(&mut document.containers);
+
+    let stores = store::decode_runtime_stores(document.stores)?;
+    let render_stores = render::decode_runtime_render_stores(document.render_stores).map_err(|error| js_error(&error))?;
+    let containers = container::decode_runtime_containers(document.containers).map_err(|error| js_error(&error))?;
+
+    let summary = serde_json::json!({
+        "stores": stores.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+        "renderStores": render_stores.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+        "containers": containers.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+    });
+    render::install_runtime_render_stores(render_stores);
+    container::install_runtime_containers(containers);
+    store::install_runtime_stores(stores)?;
+    to_js(&summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sections() -> BTreeMap<String, Value> {
+        BTreeMap::from([
+            ("main".to_string(), json!({ "state": { "count": 1, "ratio": 2.0 } })),
+            ("draft".to_string(), json!({ "state": {} })),
+        ])
+    }
+
+    #[test]
+    fn checksum_survives_integral_float_round_trip() {
+        let document = RuntimeDocument::new(sections(), BTreeMap::new(), BTreeMap::new());
+        let mut encoded = serde_json::to_value(&document).unwrap();
+        encoded["stores"]["main"]["state"]["ratio"] = json!(2);
+
+        let mut decoded: RuntimeDocument = serde_json::from_value(encoded).unwrap();
+        assert!(decoded.verify().is_ok());
+    }
+
+    #[test]
+    fn tampered_document_fails_verification() {
+        let document = RuntimeDocument::new(sections(), BTreeMap::new(), BTreeMap::new());
+        let mut encoded = serde_json::to_value(&document).unwrap();
+        encoded["stores"]["main"]["state"]["count"] = json!(2);
+
+        let mut decoded: RuntimeDocument = serde_json::from_value(encoded.clone()).unwrap();
+        assert!(decoded.verify().unwrap_err().starts_with("Runtime document checksum mismatch"));
+
+        encoded["version"] = json!(99);
+        let mut decoded: RuntimeDocument = serde_json::from_value(encoded).unwrap();
+        assert_eq!(decoded.verify().unwrap_err(), "Unsupported runtime document version: 99");
+    }
+
+    #[test]
+    fn filter_applies_include_then_exclude() {
+        let filter: RuntimeFilter = serde_json::from_value(json!({ "include": ["main", "draft"], "exclude": ["draft"] })).unwrap();
+        let mut entries = sections();
+        filter.retain(&mut entries);
+        assert_eq!(entries.keys().collect::<Vec<_>>(), vec!["main"]);
+        assert!(RuntimeFilter::default().allows("anything"));
+    }
+}
@@ -0,0 +1,274 @@
+use regex_lite::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::json_patch::escape_token;
+use crate::store_tree::StateTree;
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct SchemaViolation {
+    pub(crate) path: String,
+    pub(crate) keyword: String,
+    pub(crate) message: String,
+}
+
+/// JSON Schema (draft 2020-12 subset) with its `pattern`s compiled up front.
+///
+/// Supported keywords: `type`, `enum`, `const`, `properties`, `required`,
+/// `additionalProperties`, `items`, `minItems`/`maxItems`,
+/// `minimum`/`maximum`, `exclusiveMinimum`/`exclusiveMaximum`,
+/// `minLength`/`maxLength` and `pattern`. Unknown keywords are ignored.
+#[derive(Clone)]
+pub(crate) struct CompiledSchema {
+    schema: Value,
+    patterns: HashMap<String, Regex>,
+}
+
+impl CompiledSchema {
+    pub(crate) fn compile(schema: Value) -> Result<Self, String> {
+        let mut patterns = HashMap::new();
+        collect_patterns(&schema, &mut patterns)?;
+        Ok(Self { schema, patterns })
+    }
+
+    pub(crate) fn validate(&self, instance: &StateTree) -> Vec<SchemaViolation> {
+        let mut violations = Vec::new();
+        self.validate_at(&self.schema, instance, "", &mut violations);
+        violations
+    }
+
+    fn validate_at(&self, schema: &Value, instance: &StateTree, path: &str, violations: &mut Vec<SchemaViolation>) {
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                violations.push(violation(path, "false", "no value is allowed here"));
+                return;
+            }
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(expected) = schema.get("type") {
+            let matches = match expected {
+                Value::String(kind) => type_matches(kind, instance),
+                Value::Array(kinds) => kinds.iter().filter_map(Value::as_str).any(|kind| type_matches(kind, instance)),
+                _ => true,
+            };
+            if !matches {
+                violations.push(violation(path, "type", &format!("expected type {expected}")));
+                return;
+            }
+        }
+        if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+            if !options.iter().any(|option| instance == option) {
+                violations.push(violation(path, "enum", "value is not one of the allowed values"));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if instance != expected {
+                violations.push(violation(path, "const", &format!("expected {expected}")));
+            }
+        }
+
+        match instance {
+            StateTree::Object(object) => {
+                for key in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+                    if let Some(key) = key.as_str().filter(|key| !object.contains_key(*key)) {
+                        violations.push(violation(path, "required", &format!("missing required property {key}")));
+                    }
+                }
+                let properties = schema.get("properties").and_then(Value::as_object);
+                for (key, value) in object {
+                    let child = format!("{path}/{}", escape_token(key));
+                    match properties.and_then(|properties| properties.get(key.as_ref())) {
+                        Some(property_schema) => self.validate_at(property_schema, value, &child, violations),
+                        None => {
+                            if let Some(additional) = schema.get("additionalProperties") {
+                                self.validate_at(additional, value, &child, violations);
+                            }
+                        }
+                    }
+                }
+            }
+            StateTree::Array(array) => {
+                check_bound(schema, "minItems", array.len() as f64, |actual, bound| actual >= bound, path, violations);
+                check_bound(schema, "maxItems", array.len() as f64, |actual, bound| actual <= bound, path, violations);
+                if let Some(item_schema) = schema.get("items") {
+                    for (index, item) in array.iter().enumerate() {
+                        self.validate_at(item_schema, item, &format!("{path}/{index}"), violations);
+                    }
+                }
+            }
+            StateTree::Number(number) => {
+                let value = number.as_f64().unwrap_or_default();
+                check_bound(schema, "minimum", value, |actual, bound| actual >= bound, path, violations);
+                check_bound(schema, "maximum", value, |actual, bound| actual <= bound, path, violations);
+                check_bound(schema, "exclusiveMinimum", value, |actual, bound| actual > bound, path, violations);
+                check_bound(schema, "exclusiveMaximum", value, |actual, bound| actual < bound, path, violations);
+            }
+            StateTree::String(text) => {
+                let length = text.chars().count() as f64;
+                check_bound(schema, "minLength", length, |actual, bound| actual >= bound, path, violations);
+                check_bound(schema, "maxLength", length, |actual, bound| actual <= bound, path, violations);
+                if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+                    let matched = self.patterns.get(pattern).is_some_and(|regex| regex.is_match(text));
+                    if !matched {
+                        violations.push(violation(path, "pattern", &format!("does not match pattern {pattern}")));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Keywords whose value is a subschema, or an array of subschemas.
+const SUBSCHEMA_KEYWORDS: &[&str] = &[
+    "additionalProperties",
+    "items",
+    "prefixItems",
+    "contains",
+    "not",
+    "if",
+    "then",
+    "else",
+    "allOf",
+    "anyOf",
+    "oneOf",
+];
+
+/// Keywords whose value maps names to subschemas.
+const SUBSCHEMA_MAP_KEYWORDS: &[&str] = &["properties", "patternProperties", "dependentSchemas", "$defs", "definitions"];
+
+/// Compiles `pattern` from `schema` and every subschema under it. Values
+/// inside `enum`, `const`, `default` or `examples` are data and are skipped.
+fn collect_patterns(schema: &Value, patterns: &mut HashMap<String, Regex>) -> Result<(), String> {
+    let Value::Object(object) = schema else {
+        return Ok(());
+    };
+    if let Some(pattern) = object.get("pattern").and_then(Value::as_str) {
+        if !patterns.contains_key(pattern) {
+            let regex = Regex::new(pattern).map_err(|error| format!("Invalid schema pattern {pattern}: {error}"))?;
+            patterns.insert(pattern.to_string(), regex);
+        }
+    }
+    for keyword in SUBSCHEMA_KEYWORDS {
+        match object.get(*keyword) {
+            Some(Value::Array(subschemas)) => {
+                for subschema in subschemas {
+                    collect_patterns(subschema, patterns)?;
+                }
+            }
+            Some(subschema) => collect_patterns(subschema, patterns)?,
+            None => {}
+        }
+    }
+    for keyword in SUBSCHEMA_MAP_KEYWORDS {
+        for subschema in object.get(*keyword).and_then(Value::as_object).into_iter().flat_map(|map| map.values()) {
+            collect_patterns(subschema, patterns)?;
+        }
+    }
+    Ok(())
+}
+
+fn type_matches(kind: &str, instance: &StateTree) -> bool {
+    match (kind, instance) {
+        ("object", StateTree::Object(_))
+        | ("array", StateTree::Array(_))
+        | ("string", StateTree::String(_))
+        | ("boolean", StateTree::Bool(_))
+        | ("null", StateTree::Null)
+        | ("number", StateTree::Number(_)) => true,
+        ("integer", StateTree::Number(number)) => {
+            number.is_i64() || number.is_u64() || number.as_f64().is_some_and(|number| number.fract() == 0.0)
+        }
+        _ => false,
+    }
+}
+
+fn check_bound(
+    schema: &serde_json::Map<String, Value>,
+    keyword: &str,
+    actual: f64,
+    satisfied: fn(f64, f64) -> bool,
+    path: &str,
+    violations: &mut Vec<SchemaViolation>,
+) {
+    if let Some(bound) = schema.get(keyword).and_then(Value::as_f64) {
+        if !satisfied(actual, bound) {
+            violations.push(violation(path, keyword, &format!("{actual} violates {keyword} {bound}")));
+        }
+    }
+}
+
+fn violation(path: &str, keyword: &str, message: &str) -> SchemaViolation {
+    SchemaViolation {
+        path: path.to_string(),
+        keyword: keyword.to_string(),
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cart_schema() -> CompiledSchema {
+        CompiledSchema::compile(json!({
+            "type": "object",
+            "required": ["items", "status"],
+            "properties": {
+                "status": { "enum": ["open", "closed"] },
+                "coupon": { "type": "string", "pattern": "^[A-Z]{4}$" },
+                "items": {
+                    "type": "array",
+                    "maxItems": 2,
+                    "items": {
+                        "type": "object",
+                        "properties": { "qty": { "type": "integer", "minimum": 1 } }
+                    }
+                }
+            }
+        })).unwrap()
+    }
+
+    #[test]
+    fn valid_state_has_no_violations() {
+        let state = json!({ "status": "open", "coupon": "SAVE", "items": [{ "qty": 2 }] });
+        assert!(cart_schema().validate(&state.into()).is_empty());
+    }
+
+    #[test]
+    fn violations_report_json_pointer_paths() {
+        let state = json!({ "status": "lost", "coupon": "save", "items": [{ "qty": 0 }, { "qty": 1.5 }, {}] });
+        let violations = cart_schema().validate(&state.into());
+        let found: Vec<(&str, &str)> = violations
+            .iter()
+            .map(|violation| (violation.path.as_str(), violation.keyword.as_str()))
+            .collect();
+
+        assert!(found.contains(&("/status", "enum")));
+        assert!(found.contains(&("/coupon", "pattern")));
+        assert!(found.contains(&("/items", "maxItems")));
+        assert!(found.contains(&("/items/0/qty", "minimum")));
+        assert!(found.contains(&("/items/1/qty", "type")));
+
+        let violations = cart_schema().validate(&json!({ "status": "open" }).into());
+        assert_eq!(violations[0].keyword, "required");
+    }
+
+    #[test]
+    fn invalid_pattern_is_rejected_at_compile_time() {
+        assert!(CompiledSchema::compile(json!({ "pattern": "(" })).is_err());
+        assert!(CompiledSchema::compile(json!({ "properties": { "code": { "anyOf": [{ "pattern": "(" }] } } })).is_err());
+    }
+
+    #[test]
+    fn patterns_inside_literal_values_are_data() {
+        let schema = CompiledSchema::compile(json!({ "const": { "pattern": "(" } })).unwrap();
+        assert!(schema.validate(&json!({ "pattern": "(" }).into()).is_empty());
+        assert!(CompiledSchema::compile(json!({ "enum": [{ "pattern": "[" }], "default": { "pattern": ")" } })).is_ok());
+    }
+}
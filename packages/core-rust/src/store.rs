@@ -1,11 +1,31 @@
 use chrono::Utc;
+use imbl::Vector;
 use serde::{Deserialize, Serialize};
-use serde_json::{Map, Value};
+use serde_json::Value;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use wasm_bindgen::prelude::*;
 
-use crate::{from_js, js_error, json_number, next_id, to_js};
+use crate::json_patch::{apply_patch, diff, parse_pointer};
+use crate::runtime::RuntimeFilter;
+use crate::store_codec::{BinaryFormat, StoreExport, STORE_EXPORT_VERSION};
+use crate::store_crdt::{CrdtDocument, CrdtUpdate};
+use crate::store_computed::{self, Computed, COMPUTED_PATH_ROOT};
+use crate::store_delivery::{aggregate_error, DeliveryFailure, ErrorPolicy, SubscriberDelivery};
+use crate::store_devtools::{parse_command, DevtoolsBridge, DevtoolsCommand};
+use crate::store_history::{parse_max_depth, StoreHistory};
+use crate::store_journal::{replay_log, ActionJournal, ActionLog, EntryKind, DEFAULT_MAX_ENTRIES};
+use crate::store_lanes::{Lane, LaneKind, LaneTable, LaneValue};
+use crate::store_persistence::{PersistenceRecord, StorePersistence};
+use crate::store_migrations::{apply_migration_ops, plan_migration, Migration};
+use crate::store_reducers::{call_function, ActionReducers};
+use crate::store_scheduler::{NotificationMode, NotificationQueue};
+use crate::store_selector::{Equality, Selection};
+use crate::store_snapshots::StoreSnapshots;
+use crate::store_sync::{StoreSync, SyncMessage, SyncStatus};
+use crate::store_tree::StateTree;
+use crate::schema_validation::{CompiledSchema, SchemaViolation};
+use crate::{from_js, js_error, json_number, next_id, to_js, warn};
 
 #[derive(Clone, Serialize, Deserialize)]
 struct StoreSchema {
@@ -17,11 +37,114 @@ struct StoreSchema {
     schema_version: String,
     #[serde(rename = "compatRange", skip_serializing_if = "Option::is_none")]
     compat_range: Option<String>,
+    #[serde(rename = "jsonSchema", skip_serializing_if = "Option::is_none")]
+    json_schema: Option<Value>,
+    #[serde(rename = "validationMode", skip_serializing_if = "Option::is_none")]
+    validation_mode: Option<String>,
+    #[serde(skip)]
+    compiled: Option<CompiledSchema>,
+}
+
+impl StoreSchema {
+    fn compile(&mut self) -> Result<(), String> {
+        match self.validation_mode.as_deref() {
+            None | Some("reject" | "warn") => {}
+            Some(other) => return Err(format!("Unknown validationMode: {other} (expected \"reject\" or \"warn\")")),
+        }
+        self.compiled = self.json_schema.clone().map(CompiledSchema::compile).transpose()?;
+        Ok(())
+    }
+
+    fn violations(&self, next: &StateTree) -> Vec<SchemaViolation> {
+        self.compiled
+            .as_ref()
+            .map(|compiled| compiled.validate(next))
+            .unwrap_or_default()
+    }
+
+    /// Rejects `next` when it violates the JSON Schema, or only warns when
+    /// the schema was registered with `validationMode: "warn"`.
+    ///
+    /// Every commit path checks, except the fast paths (`*_fast` counter
+    /// dispatches, counter handles and lanes), which write without
+    /// validating; `validate_store_state` reports what they let through.
+    fn check(&self, store_id: &str, next: &StateTree) -> Result<(), JsValue> {
+        let violations = self.violations(next);
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        let error = serde_json::json!({
+            "code": "STORE_SCHEMA_VIOLATION",
+            "message": format!("Store {store_id} state violates its schema"),
+            "storeId": store_id,
+            "errors": violations,
+        });
+        if self.validation_mode.as_deref() == Some("warn") {
+            warn(&error.to_string());
+            return Ok(());
+        }
+        Err(to_js(&error).unwrap_or_else(|error| error))
+    }
 }
 
 struct Subscription {
-    path: String,
+    selection: Selection,
+    equality: Equality,
+    callback: js_sys::Function,
+    last_value: StateTree,
+    /// Paused subscriptions are skipped and catch up on resume.
+    paused: bool,
+}
+
+struct Notification {
     callback: js_sys::Function,
+    next: StateTree,
+    previous: StateTree,
+    recipient: Recipient,
+}
+
+/// Subscriber failures count against the delivery policy. Internal channels
+/// are outboxes drained through their own path: a failed send is reported
+/// for that channel and never fails or unsubscribes anything.
+enum Recipient {
+    Subscription(String),
+    Channel(Channel),
+}
+
+#[derive(Clone, Copy)]
+enum Channel {
+    Devtools,
+    Persistence,
+    PersistenceSchedule,
+    Sync,
+}
+
+impl Channel {
+    fn describe(self) -> &'static str {
+        match self {
+            Channel::Devtools => "DevTools send",
+            Channel::Persistence => "Persistence write",
+            Channel::PersistenceSchedule => "Persistence schedule",
+            Channel::Sync => "Sync send",
+        }
+    }
+}
+
+struct DevtoolsConnection {
+    bridge: DevtoolsBridge,
+    send: js_sys::Function,
+}
+
+struct PersistenceConnection {
+    tracker: StorePersistence,
+    write: js_sys::Function,
+    schedule: Option<js_sys::Function>,
+}
+
+struct SyncConnection {
+    replica: StoreSync,
+    send: js_sys::Function,
 }
 
 struct StoreMetrics {
@@ -43,37 +166,132 @@ impl StoreMetrics {
 }
 
 struct Store {
-    state: Value,
+    state: StateTree,
     fast_count: Option<i64>,
     counter_handle: Option<u32>,
+    lane_handles: Vec<u32>,
     schema: Option<StoreSchema>,
     subscriptions: HashMap<String, Subscription>,
-    snapshots: HashMap<String, Value>,
+    snapshots: StoreSnapshots,
+    history: Option<StoreHistory>,
+    computed: HashMap<String, Computed>,
+    journal: Option<ActionJournal>,
+    devtools: Option<DevtoolsConnection>,
+    persistence: Option<PersistenceConnection>,
+    sync: Option<SyncConnection>,
+    crdt: Option<CrdtDocument>,
+    reducers: ActionReducers,
     metrics: StoreMetrics,
 }
 
 impl Store {
-    fn new(state: Value) -> Self {
-        let fast_count = state.get("count").and_then(Value::as_i64);
+    fn new(state: impl Into<StateTree>) -> Self {
+        let state = state.into();
+        let fast_count = state.child("count").and_then(StateTree::as_i64);
         Self {
             state,
             fast_count,
             counter_handle: None,
+            lane_handles: Vec::new(),
             schema: None,
             subscriptions: HashMap::new(),
-            snapshots: HashMap::new(),
+            snapshots: StoreSnapshots::new(),
+            history: None,
+            computed: HashMap::new(),
+            journal: None,
+            devtools: None,
+            persistence: None,
+            sync: None,
+            crdt: None,
+            reducers: ActionReducers::default(),
             metrics: StoreMetrics::new(),
         }
     }
 
+    /// Resolves a selection path, reading `$computed.<id>...` from computed
+    /// values and everything else from state.
+    fn resolve_path(&self, segments: &[String]) -> Option<&StateTree> {
+        resolve_path(&self.state, &self.computed, segments)
+    }
+
+    fn refresh_computed(&mut self) {
+        for (computed_id, computed) in self.computed.iter_mut() {
+            if let Err(error) = computed.refresh(&self.state) {
+                warn(&format!("Computed {computed_id} failed: {error}"));
+            }
+        }
+    }
+
+    fn schema_violations(&self, next: &StateTree) -> Vec<SchemaViolation> {
+        self.schema
+            .as_ref()
+            .map(|schema| schema.violations(next))
+            .unwrap_or_default()
+    }
+
+    fn check_schema(&self, store_id: &str, next: &StateTree) -> Result<(), JsValue> {
+        match self.schema.as_ref() {
+            Some(schema) => schema.check(store_id, next),
+            None => Ok(()),
+        }
+    }
+
+    fn record_history(&mut self, next: &StateTree, label: &str, timestamp: f64) {
+        if let Some(history) = self.history.as_mut() {
+            history.record(&self.state, next, label, timestamp);
+        }
+    }
+
+    /// Replaces state wholesale, committed like a `SET`. Callers flush lanes
+    /// and check the schema first.
+    fn commit_import(&mut self, state: StateTree, label: &str, timestamp: f64) {
+        self.record_history(&state, label, timestamp);
+        self.state = state;
+        if self.records_actions() {
+            self.record_action(EntryKind::Dispatch, "SET", self.state.to_value(), None, timestamp);
+        }
+        self.refresh_fast_count();
+        self.sync_lanes_from_state();
+        self.metrics.total_updates += 1;
+    }
+
+    fn records_actions(&self) -> bool {
+        self.journal.is_some() || self.devtools.is_some()
+    }
+
+    /// Reports the action behind the state just committed to the journal and
+    /// DevTools, whichever are enabled.
+    fn record_action(
+        &mut self,
+        kind: EntryKind,
+        action_type: &str,
+        payload: Value,
+        framework: Option<&str>,
+        timestamp: f64,
+    ) {
+        if let Some(connection) = self.devtools.as_mut() {
+            connection.bridge.action(action_type, &payload, &self.state);
+        }
+        if let Some(journal) = self.journal.as_mut() {
+            journal.record(kind, action_type, payload, framework, timestamp, &self.state);
+        }
+    }
+
     fn refresh_fast_count(&mut self) {
-        self.fast_count = self.state.get("count").and_then(Value::as_i64);
+        self.fast_count = self.state.child("count").and_then(StateTree::as_i64);
     }
 
     fn flush_fast_count(&mut self) {
         if let Some(count) = self.fast_count {
+            let changed = self.state.child("count").and_then(StateTree::as_i64) != Some(count);
             if let Some(object) = self.state.as_object_mut() {
-                object.insert("count".to_string(), Value::Number(count.into()));
+                if changed {
+                    object.insert("count".into(), StateTree::Number(count.into()));
+                }
+                if changed && self.records_actions() {
+                    let payload = serde_json::json!({ "count": count });
+                    self.record_action(EntryKind::Dispatch, "MERGE", payload, None, js_sys::Date::now());
+                }
             }
         }
     }
@@ -85,54 +303,92 @@ impl Store {
 
         let previous = self
             .fast_count
-            .or_else(|| self.state.get("count").and_then(Value::as_i64))
+            .or_else(|| self.state.child("count").and_then(StateTree::as_i64))
             .unwrap_or(0);
         let next = previous + i64::from(delta) * i64::from(count);
         self.fast_count = Some(next);
         Ok(next)
     }
 
-    fn flush_counter_lane(&mut self) {
-        if let Some(handle) = self.counter_handle {
-            COUNTER_HANDLES.with(|handles| {
-                if let Some(lane) = handles.borrow_mut().get_mut(&handle) {
-                    self.fast_count = Some(lane.value);
-                    self.metrics.total_updates = self.metrics.total_updates.saturating_add(lane.pending_updates);
-                    self.metrics.total_dispatches = self.metrics.total_dispatches.saturating_add(lane.pending_dispatches);
-                    lane.pending_updates = 0;
-                    lane.pending_dispatches = 0;
-                }
-            });
+    /// Writes every lane bound to this store back into `state`. The counter
+    /// lane goes through `fast_count`; other lanes write only when changed,
+    /// and a path lane on `count` also moves `fast_count` so the final
+    /// `flush_fast_count` keeps its value.
+    fn flush_lanes(&mut self) {
+        let mut flushed = Vec::new();
+        LANES.with(|lanes| {
+            let mut lanes = lanes.borrow_mut();
+            for handle in &self.lane_handles {
+                lanes.with_lane(*handle, |lane| {
+                    let (updates, dispatches) = lane.take_pending();
+                    self.metrics.total_updates = self.metrics.total_updates.saturating_add(updates);
+                    self.metrics.total_dispatches = self.metrics.total_dispatches.saturating_add(dispatches);
+                    let dirty = lane.take_dirty();
+                    if Some(*handle) == self.counter_handle {
+                        if let LaneValue::Int(value) = lane.value() {
+                            self.fast_count = Some(value);
+                        }
+                    } else if let Some(value) = dirty {
+                        if self.state.set(&lane.segments, value.clone().into()).is_ok() {
+                            if lane.segments == ["count"] {
+                                self.fast_count = value.as_i64();
+                            }
+                            flushed.push((lane.path.clone(), value));
+                        }
+                    }
+                });
+            }
+        });
+        if self.records_actions() {
+            for (path, value) in flushed {
+                let payload = serde_json::json!({ "path": path, "value": value });
+                self.record_action(EntryKind::Dispatch, "UPDATE", payload, None, js_sys::Date::now());
+            }
         }
         self.flush_fast_count();
     }
 
-    fn sync_counter_lane_from_store(&mut self) {
-        if let Some(handle) = self.counter_handle {
-            let value = self.state.get("count").and_then(Value::as_i64).unwrap_or(0);
-            self.fast_count = Some(value);
-            COUNTER_HANDLES.with(|handles| {
-                if let Some(lane) = handles.borrow_mut().get_mut(&handle) {
-                    lane.value = value;
-                    lane.pending_updates = 0;
-                    lane.pending_dispatches = 0;
-                }
-            });
+    /// Reloads every lane from committed state after a regular dispatch.
+    fn sync_lanes_from_state(&mut self) {
+        if self.counter_handle.is_some() {
+            self.fast_count = Some(self.state.child("count").and_then(StateTree::as_i64).unwrap_or(0));
         }
+        if self.lane_handles.is_empty() {
+            return;
+        }
+        LANES.with(|lanes| {
+            let mut lanes = lanes.borrow_mut();
+            for handle in &self.lane_handles {
+                lanes.with_lane(*handle, |lane| {
+                    lane.reset(self.state.get(&lane.segments).map(StateTree::to_value).as_ref())
+                });
+            }
+        });
     }
-}
 
-struct CounterLane {
-    store_id: String,
-    value: i64,
-    pending_updates: u32,
-    pending_dispatches: u32,
+    fn lane_pending(&self) -> (u32, u32) {
+        LANES.with(|lanes| {
+            let lanes = lanes.borrow();
+            self.lane_handles
+                .iter()
+                .filter_map(|handle| lanes.get(*handle))
+                .fold((0, 0), |(updates, dispatches), lane| {
+                    let (pending_updates, pending_dispatches) = lane.pending();
+                    (updates + pending_updates, dispatches + pending_dispatches)
+                })
+        })
+    }
 }
 
 thread_local! {
     static STORES: RefCell<HashMap<String, Store>> = RefCell::new(HashMap::new());
-    static COUNTER_HANDLES: RefCell<HashMap<u32, CounterLane>> = RefCell::new(HashMap::new());
-    static NEXT_COUNTER_HANDLE: RefCell<u32> = const { RefCell::new(1) };
+    static LANES: RefCell<LaneTable> = RefCell::new(LaneTable::new());
+    static MIGRATIONS: RefCell<HashMap<String, Vec<Migration>>> = RefCell::new(HashMap::new());
+    static DELIVERY: RefCell<SubscriberDelivery> = RefCell::new(SubscriberDelivery::new());
+    static SUBSCRIBER_ERROR_HOOK: RefCell<Option<js_sys::Function>> = const { RefCell::new(None) };
+    static NOTIFICATION_MODE: RefCell<(NotificationMode, Option<js_sys::Function>)> =
+        const { RefCell::new((NotificationMode::Sync, None)) };
+    static PENDING_NOTIFICATIONS: RefCell<NotificationQueue<js_sys::Function>> = RefCell::new(NotificationQueue::new());
 }
 
 #[wasm_bindgen]
@@ -140,7 +396,7 @@ pub fn create_store(store_id: &str, initial_state: JsValue) -> Result<(), JsValu
     let state = from_js(initial_state)?;
 
     STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         if stores.contains_key(store_id) {
             return Err(js_error(&format!("Store already exists: {store_id}")));
         }
@@ -151,50 +407,128 @@ pub fn create_store(store_id: &str, initial_state: JsValue) -> Result<(), JsValu
 }
 
 #[wasm_bindgen]
-pub fn cleanup_store(store_id: &str) {
+pub fn cleanup_store(store_id: &str) -> Result<(), JsValue> {
     STORES.with(|stores| {
-        stores.borrow_mut().remove(store_id);
-    });
-    COUNTER_HANDLES.with(|handles| {
-        handles.borrow_mut().retain(|_, lane| lane.store_id != store_id);
+        stores.try_borrow_mut().map_err(|_| stores_busy())?.remove(store_id);
+        Ok::<(), JsValue>(())
+    })?;
+    LANES.with(|lanes| {
+        lanes.borrow_mut().retain(|lane| lane.store_id != store_id);
     });
+    Ok(())
 }
 
 #[wasm_bindgen]
-pub fn garbage_collect() {
+pub fn garbage_collect() -> Result<(), JsValue> {
     STORES.with(|stores| {
-        stores.borrow_mut().clear();
-    });
-    COUNTER_HANDLES.with(|handles| {
-        handles.borrow_mut().clear();
+        stores.try_borrow_mut().map_err(|_| stores_busy())?.clear();
+        Ok::<(), JsValue>(())
+    })?;
+    LANES.with(|lanes| {
+        lanes.borrow_mut().clear();
     });
+    Ok(())
+}
+
+/// Applies `action_type` to the store. `framework` names the adapter that
+/// dispatched it for the action journal.
+#[wasm_bindgen]
+pub fn dispatch(
+    store_id: &str,
+    action_type: &str,
+    payload: JsValue,
+    framework: Option<String>,
+) -> Result<JsValue, JsValue> {
+    let start = js_sys::Date::now();
+    let next_state = dispatch_value(store_id, action_type, from_js(payload)?, framework.as_deref(), start)?;
+    to_js(&next_state)
 }
 
+/// `dispatch` with the payload and the returned next state encoded as
+/// MessagePack or CBOR bytes. An empty payload dispatches `null`.
 #[wasm_bindgen]
-pub fn dispatch(store_id: &str, action_type: &str, payload: JsValue) -> Result<JsValue, JsValue> {
+pub fn dispatch_bytes(
+    store_id: &str,
+    action_type: &str,
+    payload: &[u8],
+    format: Option<String>,
+    framework: Option<String>,
+) -> Result<Vec<u8>, JsValue> {
     let start = js_sys::Date::now();
-    let payload = from_js(payload)?;
+    let format = BinaryFormat::parse(format.as_deref()).map_err(|error| js_error(&error))?;
+    let payload = if payload.is_empty() {
+        Value::Null
+    } else {
+        format
+            .decode(payload)
+            .map_err(|error| js_error(&format!("Invalid payload bytes: {error}")))?
+    };
+    let next_state = dispatch_value(store_id, action_type, payload, framework.as_deref(), start)?;
+    format.encode(&next_state).map_err(|error| js_error(&error))
+}
 
+fn dispatch_value(
+    store_id: &str,
+    action_type: &str,
+    payload: Value,
+    framework: Option<&str>,
+    start: f64,
+) -> Result<StateTree, JsValue> {
+    let reduced = run_function_reducer(store_id, action_type, &payload)?;
     let (next_state, notifications) = STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         let store = stores
             .get_mut(store_id)
             .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
-        store.flush_counter_lane();
+        store.flush_lanes();
 
-        let next_state = apply_action(&store.state, action_type, payload)?;
+        let recorded_payload = store.records_actions().then(|| payload.clone());
+        let next_state = match reduced {
+            Some((base, next)) if base == store.state => next,
+            Some(_) => return Err(js_error(&format!("Store {store_id} changed while reducer {action_type} ran"))),
+            None => apply_action(&store.state, action_type, payload, &store.reducers).map_err(|error| js_error(&error))?,
+        };
+        store.check_schema(store_id, &next_state)?;
+        store.record_history(&next_state, action_type, start);
         store.state = next_state.clone();
+        if let Some(payload) = recorded_payload {
+            store.record_action(EntryKind::Dispatch, action_type, payload, framework, start);
+        }
         store.refresh_fast_count();
-        store.sync_counter_lane_from_store();
+        store.sync_lanes_from_state();
         store.metrics.total_updates += 1;
         store.metrics.total_dispatches += 1;
         store.metrics.dispatch_time_total += js_sys::Date::now() - start;
         let notifications = collect_notifications(store);
-        Ok::<(Value, Vec<(String, js_sys::Function)>), JsValue>((next_state, notifications))
+        Ok::<(StateTree, Vec<Notification>), JsValue>((next_state, notifications))
     })?;
 
-    notify_subscribers(&next_state, &notifications)?;
-    to_js(&next_state)
+    notify_subscribers(notifications)?;
+    Ok(next_state)
+}
+
+/// Runs the JS function reducer registered for `action_type`, if any, with
+/// the stores released so it can read them. Returns the state it started
+/// from alongside the state it produced; the caller commits only if the
+/// store still holds the former.
+fn run_function_reducer(
+    store_id: &str,
+    action_type: &str,
+    payload: &Value,
+) -> Result<Option<(StateTree, StateTree)>, JsValue> {
+    let reducer = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.flush_lanes();
+        Ok::<_, JsValue>(store.reducers.function(action_type).map(|function| (function, store.state.clone())))
+    })?;
+    let Some((function, base)) = reducer else {
+        return Ok(None);
+    };
+    let next = call_function(&function, action_type, &base, payload).map_err(|error| js_error(&error))?;
+    Ok(Some((base, next)))
 }
 
 #[wasm_bindgen]
@@ -203,26 +537,30 @@ pub fn dispatch_counter(store_id: &str, delta: i32, framework: &str, action_name
     let timestamp = js_sys::Date::now();
 
     let (next_state, notifications) = STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         let store = stores
             .get_mut(store_id)
             .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
 
-        store.flush_counter_lane();
+        store.flush_lanes();
         let mut next = store.state.clone();
-        apply_counter_steps(&mut next, delta, 1, framework, action_name, timestamp)?;
+        apply_counter_steps(&mut next, delta, 1, framework, action_name, timestamp).map_err(|error| js_error(&error))?;
+        store.check_schema(store_id, &next)?;
 
+        store.record_history(&next, action_name, timestamp);
         store.state = next.clone();
+        let payload = serde_json::json!({ "delta": delta, "count": 1 });
+        store.record_action(EntryKind::Counter, action_name, payload, Some(framework), timestamp);
         store.refresh_fast_count();
-        store.sync_counter_lane_from_store();
+        store.sync_lanes_from_state();
         store.metrics.total_updates += 1;
         store.metrics.total_dispatches += 1;
         store.metrics.dispatch_time_total += js_sys::Date::now() - start;
         let notifications = collect_notifications(store);
-        Ok::<(Value, Vec<(String, js_sys::Function)>), JsValue>((next, notifications))
+        Ok::<(StateTree, Vec<Notification>), JsValue>((next, notifications))
     })?;
 
-    notify_subscribers(&next_state, &notifications)?;
+    notify_subscribers(notifications)?;
     to_js(&next_state)
 }
 
@@ -238,33 +576,40 @@ pub fn dispatch_counter_batch(
     let timestamp = js_sys::Date::now();
 
     let (next_state, notifications) = STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         let store = stores
             .get_mut(store_id)
             .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
 
-        store.flush_counter_lane();
+        store.flush_lanes();
         let mut next = store.state.clone();
-        apply_counter_steps(&mut next, delta, count, framework, action_name, timestamp)?;
+        apply_counter_steps(&mut next, delta, count, framework, action_name, timestamp)
+            .map_err(|error| js_error(&error))?;
+        store.check_schema(store_id, &next)?;
 
+        store.record_history(&next, action_name, timestamp);
         store.state = next.clone();
+        let payload = serde_json::json!({ "delta": delta, "count": count });
+        store.record_action(EntryKind::Counter, action_name, payload, Some(framework), timestamp);
         store.refresh_fast_count();
-        store.sync_counter_lane_from_store();
+        store.sync_lanes_from_state();
         store.metrics.total_updates += count;
         store.metrics.total_dispatches += count;
         store.metrics.dispatch_time_total += js_sys::Date::now() - start;
         let notifications = collect_notifications(store);
-        Ok::<(Value, Vec<(String, js_sys::Function)>), JsValue>((next, notifications))
+        Ok::<(StateTree, Vec<Notification>), JsValue>((next, notifications))
     })?;
 
-    notify_subscribers(&next_state, &notifications)?;
+    notify_subscribers(notifications)?;
     to_js(&next_state)
 }
 
+/// Adds `delta` to `count` without history, journal entries or schema
+/// validation; `dispatch_counter` is the checked equivalent.
 #[wasm_bindgen]
 pub fn dispatch_counter_fast(store_id: &str, delta: i32) -> Result<f64, JsValue> {
     STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         let store = stores
             .get_mut(store_id)
             .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
@@ -279,7 +624,7 @@ pub fn dispatch_counter_fast(store_id: &str, delta: i32) -> Result<f64, JsValue>
 #[wasm_bindgen]
 pub fn dispatch_counter_batch_fast(store_id: &str, delta: i32, count: u32) -> Result<f64, JsValue> {
     STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         let store = stores
             .get_mut(store_id)
             .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
@@ -294,7 +639,7 @@ pub fn dispatch_counter_batch_fast(store_id: &str, delta: i32, count: u32) -> Re
 #[wasm_bindgen]
 pub fn create_counter_handle(store_id: &str) -> Result<u32, JsValue> {
     STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         let store = stores
             .get_mut(store_id)
             .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
@@ -302,45 +647,39 @@ pub fn create_counter_handle(store_id: &str) -> Result<u32, JsValue> {
         if let Some(handle) = store.counter_handle {
             return Ok(handle);
         }
-
-        let handle = NEXT_COUNTER_HANDLE.with(|next_handle| {
-            let mut next_handle = next_handle.borrow_mut();
-            let handle = *next_handle;
-            *next_handle = next_handle.saturating_add(1).max(1);
-            handle
+        // A lane created with `create_lane_handle(store, "count", "i64")`
+        // becomes the counter rather than a second lane on the same path.
+        store.flush_lanes();
+        let existing = LANES.with(|lanes| {
+            let lanes = lanes.borrow();
+            store
+                .lane_handles
+                .iter()
+                .find_map(|handle| lanes.get(*handle).filter(|lane| lane.segments == ["count"]).map(|lane| (*handle, lane.kind())))
         });
+        if let Some((handle, kind)) = existing {
+            if kind != LaneKind::Int {
+                return Err(js_error("Path count already has a lane of another kind"));
+            }
+            store.fast_count = Some(store.state.child("count").and_then(StateTree::as_i64).unwrap_or(0));
+            store.counter_handle = Some(handle);
+            return Ok(handle);
+        }
+
         let value = store
             .fast_count
-            .or_else(|| store.state.get("count").and_then(Value::as_i64))
+            .or_else(|| store.state.child("count").and_then(StateTree::as_i64))
             .unwrap_or(0);
         store.fast_count = Some(value);
+        let handle = insert_lane(store, store_id, "count", vec!["count".to_string()], LaneValue::Int(value));
         store.counter_handle = Some(handle);
-        COUNTER_HANDLES.with(|handles| {
-            handles.borrow_mut().insert(handle, CounterLane {
-                store_id: store_id.to_string(),
-                value,
-                pending_updates: 0,
-                pending_dispatches: 0,
-            });
-        });
         Ok(handle)
     })
 }
 
 #[wasm_bindgen]
-pub fn release_counter_handle(handle: u32) {
-    let lane = COUNTER_HANDLES.with(|handles| handles.borrow_mut().remove(&handle));
-    if let Some(lane) = lane {
-        STORES.with(|stores| {
-            if let Some(store) = stores.borrow_mut().get_mut(&lane.store_id) {
-                store.fast_count = Some(lane.value);
-                store.flush_fast_count();
-                store.metrics.total_updates = store.metrics.total_updates.saturating_add(lane.pending_updates);
-                store.metrics.total_dispatches = store.metrics.total_dispatches.saturating_add(lane.pending_dispatches);
-                store.counter_handle = None;
-            }
-        });
-    }
+pub fn release_counter_handle(handle: u32) -> Result<(), JsValue> {
+    release_lane_handle(handle)
 }
 
 #[wasm_bindgen]
@@ -350,15 +689,13 @@ pub fn dispatch_counter_handle_fast(handle: u32, delta: i32) -> Result<f64, JsVa
 
 #[wasm_bindgen]
 pub fn dispatch_counter_handle_batch_fast(handle: u32, delta: i32, count: u32) -> Result<f64, JsValue> {
-    COUNTER_HANDLES.with(|handles| {
-        let mut handles = handles.borrow_mut();
-        let lane = handles
-            .get_mut(&handle)
-            .ok_or_else(|| js_error(&format!("Counter handle not found: {handle}")))?;
-        lane.value += i64::from(delta) * i64::from(count);
-        lane.pending_updates = lane.pending_updates.saturating_add(count);
-        lane.pending_dispatches = lane.pending_dispatches.saturating_add(count);
-        Ok(lane.value as f64)
+    LANES.with(|lanes| {
+        lanes
+            .borrow_mut()
+            .with_lane(handle, |lane| lane.add_int(i64::from(delta), count))
+            .ok_or_else(|| js_error(&format!("Counter handle not found: {handle}")))?
+            .map(|value| value as f64)
+            .ok_or_else(|| js_error(&format!("Lane {handle} is not an i64 lane")))
     })
 }
 
@@ -369,123 +706,1602 @@ pub fn dispatch_counter_handle_fast_unchecked(handle: u32, delta: i32) -> f64 {
 
 #[wasm_bindgen]
 pub fn dispatch_counter_handle_batch_fast_unchecked(handle: u32, delta: i32, count: u32) -> f64 {
-    COUNTER_HANDLES.with(|handles| {
-        let mut handles = handles.borrow_mut();
-        let Some(lane) = handles.get_mut(&handle) else {
-            return f64::NAN;
+    LANES.with(|lanes| {
+        lanes
+            .borrow_mut()
+            .with_lane(handle, |lane| lane.add_int(i64::from(delta), count))
+            .flatten()
+            .map_or(f64::NAN, |value| value as f64)
+    })
+}
+
+/// Binds a typed fast lane (`i64`, `f64` or `bool`) to `path`.
+///
+/// Lane updates skip the dispatch pipeline, including history and schema
+/// validation, and are written into the store state on the next select,
+/// snapshot or dispatch. Creating a lane for a path that already has one
+/// returns the existing handle.
+#[wasm_bindgen]
+pub fn create_lane_handle(store_id: &str, path: &str, kind: &str) -> Result<u32, JsValue> {
+    let kind = LaneKind::parse(kind).map_err(|error| js_error(&error))?;
+    let segments = parse_path(path).map_err(|error| js_error(&error))?;
+    if segments.is_empty() {
+        return Err(js_error("Lane path must not be empty"));
+    }
+
+    let timestamp = js_sys::Date::now();
+    let (handle, notifications) = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let existing = LANES.with(|lanes| {
+            let lanes = lanes.borrow();
+            store
+                .lane_handles
+                .iter()
+                .find_map(|handle| lanes.get(*handle).filter(|lane| lane.segments == segments).map(|lane| (*handle, lane.kind())))
+        });
+        if let Some((handle, existing_kind)) = existing {
+            return if existing_kind == kind {
+                Ok((handle, Vec::new()))
+            } else {
+                Err(js_error(&format!("Path {path} already has a lane of another kind")))
+            };
+        }
+
+        let current = store.state.get(&segments).map(StateTree::to_value);
+        let value = LaneValue::from_json(kind, current.as_ref()).map_err(|error| js_error(&error))?;
+        // A missing or coerced value is committed now, like an `UPDATE`, so a
+        // path that cannot hold it fails here rather than on a later flush.
+        let seed = value.to_json();
+        let mut notifications = Vec::new();
+        if current.as_ref() != Some(&seed) {
+            let mut next = store.state.clone();
+            next.set(&segments, seed.clone().into()).map_err(|error| js_error(&error))?;
+            store.check_schema(store_id, &next)?;
+            store.record_history(&next, "UPDATE", timestamp);
+            store.state = next;
+            if store.records_actions() {
+                let payload = serde_json::json!({ "path": path, "value": seed });
+                store.record_action(EntryKind::Dispatch, "UPDATE", payload, None, timestamp);
+            }
+            store.refresh_fast_count();
+            store.metrics.total_updates += 1;
+            notifications = collect_notifications(store);
+        }
+        Ok::<_, JsValue>((insert_lane(store, store_id, path, segments, value), notifications))
+    })?;
+
+    notify_subscribers(notifications)?;
+    Ok(handle)
+}
+
+#[wasm_bindgen]
+pub fn release_lane_handle(handle: u32) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let Some(mut lane) = LANES.with(|lanes| lanes.borrow_mut().remove(handle)) else {
+            return Ok(());
+        };
+        let Some(store) = stores.get_mut(&lane.store_id) else {
+            return Ok(());
         };
-        lane.value += i64::from(delta) * i64::from(count);
-        lane.pending_updates = lane.pending_updates.saturating_add(count);
-        lane.pending_dispatches = lane.pending_dispatches.saturating_add(count);
-        lane.value as f64
+        store.lane_handles.retain(|bound| *bound != handle);
+        let (updates, dispatches) = lane.take_pending();
+        store.metrics.total_updates = store.metrics.total_updates.saturating_add(updates);
+        store.metrics.total_dispatches = store.metrics.total_dispatches.saturating_add(dispatches);
+        if store.counter_handle == Some(handle) {
+            if let LaneValue::Int(value) = lane.value() {
+                store.fast_count = Some(value);
+            }
+            store.flush_fast_count();
+            store.counter_handle = None;
+        } else if let Some(value) = lane.take_dirty() {
+            let _ = store.state.set(&lane.segments, value.into());
+        }
+        Ok(())
+    })
+}
+
+#[wasm_bindgen]
+pub fn lane_add_fast(handle: u32, delta: f64) -> Result<f64, JsValue> {
+    with_lane(handle, |lane| lane.add(delta, 1))
+}
+
+#[wasm_bindgen]
+pub fn lane_set_fast(handle: u32, value: f64) -> Result<f64, JsValue> {
+    with_lane(handle, |lane| lane.set(value))
+}
+
+#[wasm_bindgen]
+pub fn lane_toggle_fast(handle: u32) -> Result<bool, JsValue> {
+    with_lane(handle, |lane| lane.toggle())
+}
+
+/// Current lane value without flushing; bool lanes read as 0 or 1.
+#[wasm_bindgen]
+pub fn lane_value(handle: u32) -> Result<f64, JsValue> {
+    with_lane(handle, |lane| Ok(lane.value().as_f64()))
+}
+
+/// Index of the lane's slot in the shared lane buffer views.
+#[wasm_bindgen]
+pub fn lane_slot(handle: u32) -> Result<u32, JsValue> {
+    LANES.with(|lanes| {
+        lanes
+            .borrow()
+            .slot(handle)
+            .map(|slot| slot as u32)
+            .ok_or_else(|| js_error(&format!("Lane {handle} has no buffer slot")))
+    })
+}
+
+/// Describes the shared lane buffer: `new Float64Array(memory.buffer,
+/// valuesPtr, capacity)` holds lane values and `new Int32Array(memory.buffer,
+/// generationsPtr, capacity)` their generation counters. JS writers store
+/// the value and then increment the generation. Views must be recreated when
+/// `memory.buffer` is detached by memory growth.
+#[wasm_bindgen]
+pub fn lane_buffer_info() -> Result<JsValue, JsValue> {
+    LANES.with(|lanes| {
+        let lanes = lanes.borrow();
+        let buffer = lanes.buffer();
+        to_js(&serde_json::json!({
+            "valuesPtr": buffer.values_ptr(),
+            "generationsPtr": buffer.generations_ptr(),
+            "capacity": buffer.capacity(),
+        }))
+    })
+}
+
+/// Sets how many lanes get a buffer slot; lanes beyond it still work
+/// through calls. Fails while any lane holds a slot.
+#[wasm_bindgen]
+pub fn configure_lane_buffer(capacity: u32) -> Result<(), JsValue> {
+    LANES.with(|lanes| {
+        lanes
+            .borrow_mut()
+            .set_buffer_capacity(capacity as usize)
+            .map_err(|error| js_error(&error))
+    })
+}
+
+#[wasm_bindgen]
+pub fn lane_memory() -> JsValue {
+    wasm_bindgen::memory()
+}
+
+fn with_lane<T>(handle: u32, update: impl FnOnce(&mut Lane) -> Result<T, String>) -> Result<T, JsValue> {
+    LANES.with(|lanes| {
+        lanes
+            .borrow_mut()
+            .with_lane(handle, update)
+            .ok_or_else(|| js_error(&format!("Lane handle not found: {handle}")))?
+            .map_err(|error| js_error(&error))
     })
 }
 
+fn insert_lane(store: &mut Store, store_id: &str, path: &str, segments: Vec<String>, value: LaneValue) -> u32 {
+    let lane = Lane::new(store_id.to_string(), path.to_string(), segments, value);
+    let handle = LANES.with(|lanes| lanes.borrow_mut().insert(lane));
+    store.lane_handles.push(handle);
+    handle
+}
+
 #[wasm_bindgen]
 pub fn select(store_id: &str, path: &str) -> Result<JsValue, JsValue> {
+    select_with(store_id, path, |value| match value {
+        Some(value) => to_js(value),
+        None => Ok(JsValue::UNDEFINED),
+    })
+}
+
+/// `select` encoded as MessagePack or CBOR bytes; a missing path encodes nil.
+#[wasm_bindgen]
+pub fn select_bytes(store_id: &str, path: &str, format: Option<String>) -> Result<Vec<u8>, JsValue> {
+    let format = BinaryFormat::parse(format.as_deref()).map_err(|error| js_error(&error))?;
+    select_with(store_id, path, |value| {
+        format.encode(&value.unwrap_or(&StateTree::Null)).map_err(|error| js_error(&error))
+    })
+}
+
+fn select_with<T>(
+    store_id: &str,
+    path: &str,
+    read: impl FnOnce(Option<&StateTree>) -> Result<T, JsValue>,
+) -> Result<T, JsValue> {
+    let segments = parse_path(path).map_err(|error| js_error(&error))?;
+    if segments.is_empty() || is_computed_path(&segments) {
+        settle_computed(store_id)?;
+    }
     STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         let store = stores
             .get_mut(store_id)
             .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
 
         store.metrics.total_selects += 1;
-        if path == "count" {
+        let has_path_lanes = store.lane_handles.iter().any(|handle| Some(*handle) != store.counter_handle);
+        if segments.is_empty() || is_computed_path(&segments) {
+            store.flush_lanes();
+            store.refresh_computed();
+        } else if has_path_lanes {
+            store.flush_lanes();
+        }
+        if segments == ["count"] {
             if let Some(handle) = store.counter_handle {
                 if let Some(count) = counter_lane_value(handle) {
-                    return Ok(JsValue::from_f64(count as f64));
+                    return read(Some(&StateTree::Number(count.into())));
                 }
             }
             if let Some(count) = store.fast_count {
-                return Ok(JsValue::from_f64(count as f64));
+                return read(Some(&StateTree::Number(count.into())));
             }
         }
-        if path.is_empty() {
-            store.flush_counter_lane();
-        }
-        match select_value(&store.state, path) {
-            Some(value) => to_js(value),
-            None => Ok(JsValue::UNDEFINED),
+        if let Some((computed_id, error)) = segments
+            .get(1)
+            .filter(|_| is_computed_path(&segments))
+            .and_then(|computed_id| Some((computed_id, store.computed.get(computed_id)?.error()?)))
+        {
+            return Err(js_error(&format!("Computed {computed_id} failed: {error}")));
         }
+        read(store.resolve_path(&segments))
     })
 }
 
 #[wasm_bindgen]
 pub fn subscribe(store_id: &str, path: &str, callback: js_sys::Function) -> Result<String, JsValue> {
+    let segments = parse_path(path).map_err(|error| js_error(&error))?;
+    let (subscription_id, _) = insert_subscription(store_id, Selection::Path(segments), Equality::Deep, &callback)?;
+    Ok(subscription_id)
+}
+
+/// Subscribes to `paths`: one path, or an array of paths delivered to
+/// `callback` as a tuple. `options.equality` is `"deep"` (the default),
+/// `"shallow"` or `{ keys: [path, ...] }` relative to the selection, and
+/// `options.fireImmediately` calls `callback(current, current)` right away.
+#[wasm_bindgen]
+pub fn subscribe_with_selector(
+    store_id: &str,
+    paths: JsValue,
+    callback: js_sys::Function,
+    options: JsValue,
+) -> Result<SubscriptionHandle, JsValue> {
+    let options = from_js(options)?;
+    let selection = match from_js(paths)? {
+        Value::String(path) => Selection::Path(parse_path(&path).map_err(|error| js_error(&error))?),
+        Value::Array(paths) => Selection::Tuple(
+            paths
+                .iter()
+                .map(|path| match path.as_str() {
+                    Some(path) => parse_path(path),
+                    None => Err(format!("Subscription paths must be strings, got {path}")),
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|error| js_error(&error))?,
+        ),
+        other => return Err(js_error(&format!("Subscription paths must be a path or an array of paths, got {other}"))),
+    };
+    let equality = Equality::parse(options.get("equality").unwrap_or(&Value::Null), parse_path)
+        .map_err(|error| js_error(&error))?;
+
+    let (subscription_id, current) = insert_subscription(store_id, selection, equality, &callback)?;
+    if options.get("fireImmediately").and_then(Value::as_bool).unwrap_or(false) {
+        deliver_notifications(vec![Notification {
+            callback,
+            next: current.clone(),
+            previous: current,
+            recipient: Recipient::Subscription(subscription_id.clone()),
+        }])?;
+    }
+    Ok(SubscriptionHandle { subscription_id })
+}
+
+fn insert_subscription(
+    store_id: &str,
+    selection: Selection,
+    equality: Equality,
+    callback: &js_sys::Function,
+) -> Result<(String, StateTree), JsValue> {
+    settle_computed(store_id)?;
     STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         let store = stores
             .get_mut(store_id)
             .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
 
+        store.flush_lanes();
+        store.refresh_computed();
+        let last_value = selection.resolve(|segments| store.resolve_path(segments));
         let subscription_id = next_id("sub");
         store.subscriptions.insert(
             subscription_id.clone(),
             Subscription {
-                path: path.to_string(),
-                callback,
+                selection,
+                equality,
+                callback: callback.clone(),
+                last_value: last_value.clone(),
+                paused: false,
             },
         );
 
-        Ok(subscription_id)
+        Ok((subscription_id, last_value))
     })
 }
 
+/// Returned by `subscribe_with_selector`. Pausing stops delivery; resuming
+/// delivers one catch-up notification if the selection changed meanwhile.
+#[wasm_bindgen]
+pub struct SubscriptionHandle {
+    subscription_id: String,
+}
+
+#[wasm_bindgen]
+impl SubscriptionHandle {
+    #[wasm_bindgen(getter)]
+    pub fn id(&self) -> String {
+        self.subscription_id.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn paused(&self) -> Result<bool, JsValue> {
+        STORES.with(|stores| {
+            Ok(stores
+                .try_borrow()
+                .map_err(|_| stores_busy())?
+                .values()
+                .find_map(|store| store.subscriptions.get(&self.subscription_id))
+                .is_some_and(|subscription| subscription.paused))
+        })
+    }
+
+    pub fn pause(&self) -> Result<(), JsValue> {
+        STORES.with(|stores| {
+            if let Some(subscription) = stores
+                .try_borrow_mut()
+                .map_err(|_| stores_busy())?
+                .values_mut()
+                .find_map(|store| store.subscriptions.get_mut(&self.subscription_id))
+            {
+                subscription.paused = true;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn resume(&self) -> Result<(), JsValue> {
+        let store_id = STORES.with(|stores| {
+            Ok::<_, JsValue>(
+                stores
+                    .try_borrow()
+                    .map_err(|_| stores_busy())?
+                    .iter()
+                    .find(|(_, store)| store.subscriptions.contains_key(&self.subscription_id))
+                    .map(|(store_id, _)| store_id.clone()),
+            )
+        })?;
+        if let Some(store_id) = store_id {
+            settle_computed(&store_id)?;
+        }
+        let notification = STORES.with(|stores| {
+            let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+            let Some(store) = stores
+                .values_mut()
+                .find(|store| store.subscriptions.contains_key(&self.subscription_id))
+            else {
+                return Ok(None);
+            };
+            store.flush_lanes();
+            store.refresh_computed();
+            let Store {
+                state,
+                computed,
+                subscriptions,
+                ..
+            } = store;
+            let Some(subscription) = subscriptions.get_mut(&self.subscription_id) else {
+                return Ok(None);
+            };
+            if !std::mem::replace(&mut subscription.paused, false) {
+                return Ok(None);
+            }
+            let next = subscription.selection.resolve(|segments| resolve_path(state, computed, segments));
+            let change = take_selection_change(&mut subscription.last_value, Some(&next), &subscription.equality);
+            Ok::<_, JsValue>(change.map(|(next, previous)| Notification {
+                callback: subscription.callback.clone(),
+                next,
+                previous,
+                recipient: Recipient::Subscription(self.subscription_id.clone()),
+            }))
+        })?;
+        notify_subscribers(notification.into_iter().collect())
+    }
+
+    pub fn unsubscribe(&self) -> Result<(), JsValue> {
+        unsubscribe(&self.subscription_id)
+    }
+}
+
 #[wasm_bindgen]
-pub fn unsubscribe(subscription_id: &str) {
+pub fn unsubscribe(subscription_id: &str) -> Result<(), JsValue> {
     STORES.with(|stores| {
-        for store in stores.borrow_mut().values_mut() {
+        for store in stores.try_borrow_mut().map_err(|_| stores_busy())?.values_mut() {
             store.subscriptions.remove(subscription_id);
         }
-    });
+        Ok::<(), JsValue>(())
+    })?;
+    DELIVERY.with(|delivery| delivery.borrow_mut().forget(subscription_id));
+    Ok(())
 }
 
+/// Sets how throwing subscriber callbacks are handled, for every store:
+/// `{ policy: "continue" | "unsubscribe" | "rethrow", maxFailures }`. Other
+/// subscribers are notified either way; `unsubscribe` drops a subscription
+/// after `maxFailures` consecutive failures (default 3) and `rethrow` fails
+/// the triggering call with every error combined.
 #[wasm_bindgen]
-pub fn create_snapshot(store_id: &str) -> Result<String, JsValue> {
-    STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
-        let store = stores
-            .get_mut(store_id)
-            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+pub fn set_subscriber_error_policy(options: JsValue) -> Result<(), JsValue> {
+    let policy = ErrorPolicy::parse(&from_js(options)?).map_err(|error| js_error(&error))?;
+    DELIVERY.with(|delivery| delivery.borrow_mut().set_policy(policy));
+    Ok(())
+}
 
-        store.flush_counter_lane();
-        let snapshot_id = next_id("snap");
-        store.snapshots.insert(snapshot_id.clone(), store.state.clone());
-        Ok(snapshot_id)
-    })
+/// Chooses when subscriber callbacks run: `sync` (the default) calls them
+/// inside the committing call, `batched` queues them until
+/// `flush_notifications`, and `deferred` queues them and hands a flush
+/// function to `scheduler`, e.g. `queueMicrotask` or `requestAnimationFrame`.
+/// Queued notifications coalesce to the final value per subscription.
+/// Anything still queued is flushed before the mode changes.
+#[wasm_bindgen]
+pub fn set_notification_mode(mode: &str, scheduler: Option<js_sys::Function>) -> Result<(), JsValue> {
+    let mode = NotificationMode::parse(mode).map_err(|error| js_error(&error))?;
+    let scheduler = match (mode, scheduler) {
+        (NotificationMode::Deferred, None) => {
+            return Err(js_error("Deferred notifications need a scheduler callback"));
+        }
+        (NotificationMode::Deferred, scheduler) => scheduler,
+        _ => None,
+    };
+    flush_notifications()?;
+    NOTIFICATION_MODE.with(|current| *current.borrow_mut() = (mode, scheduler));
+    Ok(())
 }
 
+/// Delivers every queued subscriber notification and returns how many were
+/// delivered. Each is checked against its subscription as it stands now:
+/// removed subscriptions are dropped, changes the subscription's equality
+/// considers equal are skipped, and a paused subscription keeps its last
+/// delivered value so `resume` catches it up.
 #[wasm_bindgen]
-pub fn restore_snapshot(store_id: &str, snapshot_id: &str) -> Result<JsValue, JsValue> {
-    let (restored, notifications) = STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
+pub fn flush_notifications() -> Result<u32, JsValue> {
+    let notifications: Vec<Notification> = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let pending = PENDING_NOTIFICATIONS.with(|pending| {
+            pending.borrow_mut().drain(|subscription_id, pending| {
+                let Some(subscription) = stores
+                    .values_mut()
+                    .find_map(|store| store.subscriptions.get_mut(subscription_id))
+                else {
+                    return false;
+                };
+                if subscription.paused {
+                    subscription.last_value = pending.previous.clone();
+                    return false;
+                }
+                !subscription.equality.equal(&pending.previous, &pending.next)
+            })
+        });
+        Ok::<_, JsValue>(
+            pending
+                .into_iter()
+                .map(|(subscription_id, pending)| Notification {
+                    callback: pending.callback,
+                    next: pending.next,
+                    previous: pending.previous,
+                    recipient: Recipient::Subscription(subscription_id),
+                })
+                .collect(),
+        )
+    })?;
+    let delivered = notifications.len() as u32;
+    deliver_notifications(notifications)?;
+    Ok(delivered)
+}
+
+/// Called as `hook(error, { subscriptionId, message, failures, unsubscribed })`
+/// for each failed callback. Without a hook, failures are logged as warnings.
+#[wasm_bindgen]
+pub fn set_subscriber_error_hook(hook: Option<js_sys::Function>) {
+    SUBSCRIBER_ERROR_HOOK.with(|current| *current.borrow_mut() = hook);
+}
+
+/// Registers a value derived from `spec.inputs` paths, selectable and
+/// subscribable as `$computed.<computed_id>`.
+///
+/// `spec.op` is one of `sum`, `count`, `filter` or `pick`; when `compute` is
+/// given it is called with the input values instead, outside the store, so it
+/// may select from it. A compute that throws keeps its last value and fails
+/// selects of it until it succeeds again.
+#[wasm_bindgen]
+pub fn create_computed(
+    store_id: &str,
+    computed_id: &str,
+    spec: JsValue,
+    compute: Option<js_sys::Function>,
+) -> Result<JsValue, JsValue> {
+    let spec = from_js(spec)?;
+    let mut computed = Computed::from_spec(&spec, compute).map_err(|error| js_error(&error))?;
+
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         let store = stores
             .get_mut(store_id)
             .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
 
-        let snapshot = store
-            .snapshots
-            .get(snapshot_id)
-            .cloned()
-            .ok_or_else(|| js_error(&format!("Snapshot not found: {snapshot_id}")))?;
-
-        store.state = snapshot.clone();
-        store.refresh_fast_count();
-        store.sync_counter_lane_from_store();
-        let notifications = collect_notifications(store);
-        Ok::<(Value, Vec<(String, js_sys::Function)>), JsValue>((snapshot, notifications))
+        store.flush_lanes();
+        computed.refresh(&store.state).map_err(|error| js_error(&error))?;
+        store.computed.insert(computed_id.to_string(), computed);
+        Ok::<_, JsValue>(())
     })?;
+    notify_subscribers(Vec::new())?;
 
-    notify_subscribers(&restored, &notifications)?;
-    to_js(&restored)
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        let computed = store
+            .computed
+            .get(computed_id)
+            .ok_or_else(|| js_error(&format!("Computed {computed_id} was removed while it ran")))?;
+        if let Some(error) = computed.error() {
+            let error = error.to_string();
+            store.computed.remove(computed_id);
+            return Err(js_error(&error));
+        }
+        to_js(computed.value())
+    })
 }
 
 #[wasm_bindgen]
-pub fn get_metrics(store_id: &str) -> Result<JsValue, JsValue> {
+pub fn remove_computed(store_id: &str, computed_id: &str) -> Result<bool, JsValue> {
     STORES.with(|stores| {
-        let stores = stores.borrow();
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         let store = stores
-            .get(store_id)
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        Ok(store.computed.remove(computed_id).is_some())
+    })
+}
+
+#[wasm_bindgen]
+pub fn create_snapshot(store_id: &str) -> Result<String, JsValue> {
+    insert_snapshot(store_id, None, Value::Null)
+}
+
+#[wasm_bindgen]
+pub fn create_labeled_snapshot(store_id: &str, label: &str, metadata: JsValue) -> Result<String, JsValue> {
+    insert_snapshot(store_id, Some(label.to_string()), from_js(metadata)?)
+}
+
+fn insert_snapshot(store_id: &str, label: Option<String>, metadata: Value) -> Result<String, JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let snapshot_id = next_id("snap");
+        store.snapshots.insert(
+            snapshot_id.clone(),
+            store.state.clone(),
+            label,
+            metadata,
+            js_sys::Date::now(),
+        );
+        Ok(snapshot_id)
+    })
+}
+
+#[wasm_bindgen]
+pub fn list_snapshots(store_id: &str) -> Result<JsValue, JsValue> {
+    STORES.with(|stores| {
+        let stores = stores.try_borrow().map_err(|_| stores_busy())?;
+        let store = stores
+            .get(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        to_js(&store.snapshots.list())
+    })
+}
+
+#[wasm_bindgen]
+pub fn delete_snapshot(store_id: &str, snapshot_id: &str) -> Result<bool, JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        Ok(store.snapshots.remove(snapshot_id))
+    })
+}
+
+#[wasm_bindgen]
+pub fn set_snapshot_limit(store_id: &str, limit: u32) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.snapshots.set_limit((limit > 0).then_some(limit as usize));
+        Ok(())
+    })
+}
+
+/// Returns the JSON Patch turning snapshot `from` into snapshot `to`, or into
+/// the live state when `to` is omitted.
+#[wasm_bindgen]
+pub fn diff_snapshots(store_id: &str, from: &str, to: Option<String>) -> Result<JsValue, JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let before = store
+            .snapshots
+            .get(from)
+            .cloned()
+            .ok_or_else(|| js_error(&format!("Snapshot not found: {from}")))?;
+        let operations = match to.as_deref() {
+            Some(to) => {
+                let after = store
+                    .snapshots
+                    .get(to)
+                    .ok_or_else(|| js_error(&format!("Snapshot not found: {to}")))?;
+                diff(&before, after)
+            }
+            None => diff(&before, &store.state),
+        };
+        to_js(&operations)
+    })
+}
+
+#[wasm_bindgen]
+pub fn restore_snapshot(store_id: &str, snapshot_id: &str) -> Result<JsValue, JsValue> {
+    let (restored, notifications) = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let snapshot = store
+            .snapshots
+            .get(snapshot_id)
+            .cloned()
+            .ok_or_else(|| js_error(&format!("Snapshot not found: {snapshot_id}")))?;
+
+        store.check_schema(store_id, &snapshot)?;
+        let timestamp = js_sys::Date::now();
+        store.record_history(&snapshot, "RESTORE_SNAPSHOT", timestamp);
+        store.state = snapshot.clone();
+        if store.records_actions() {
+            store.record_action(EntryKind::Dispatch, "SET", snapshot.to_value(), None, timestamp);
+        }
+        store.refresh_fast_count();
+        store.sync_lanes_from_state();
+        let notifications = collect_notifications(store);
+        Ok::<(StateTree, Vec<Notification>), JsValue>((snapshot, notifications))
+    })?;
+
+    notify_subscribers(notifications)?;
+    to_js(&restored)
+}
+
+#[wasm_bindgen]
+pub fn enable_store_history(store_id: &str, options: JsValue) -> Result<(), JsValue> {
+    let options = from_js(options)?;
+    let max_depth = parse_max_depth(options.get("maxDepth")).map_err(|error| js_error(&error))?;
+    let exclude = options
+        .get("exclude")
+        .and_then(Value::as_array)
+        .map(|paths| paths.iter().filter_map(Value::as_str).map(parse_path).collect())
+        .transpose()
+        .map_err(|error| js_error(&error))?
+        .unwrap_or_default();
+
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.history = Some(StoreHistory::new(max_depth, exclude));
+        Ok(())
+    })
+}
+
+#[wasm_bindgen]
+pub fn disable_store_history(store_id: &str) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.history = None;
+        Ok(())
+    })
+}
+
+#[wasm_bindgen]
+pub fn begin_store_history_group(store_id: &str, label: &str) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.flush_lanes();
+        let history = store
+            .history
+            .as_mut()
+            .ok_or_else(|| js_error(&format!("History is not enabled for store: {store_id}")))?;
+        history.begin_group(&store.state, label).map_err(|error| js_error(&error))
+    })
+}
+
+#[wasm_bindgen]
+pub fn end_store_history_group(store_id: &str) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.flush_lanes();
+        let history = store
+            .history
+            .as_mut()
+            .ok_or_else(|| js_error(&format!("History is not enabled for store: {store_id}")))?;
+        history
+            .end_group(&store.state, js_sys::Date::now())
+            .map_err(|error| js_error(&error))
+    })
+}
+
+#[wasm_bindgen]
+pub fn undo_store(store_id: &str) -> Result<JsValue, JsValue> {
+    step_store_history(store_id, true)
+}
+
+#[wasm_bindgen]
+pub fn redo_store(store_id: &str) -> Result<JsValue, JsValue> {
+    step_store_history(store_id, false)
+}
+
+#[wasm_bindgen]
+pub fn get_store_history(store_id: &str) -> Result<JsValue, JsValue> {
+    STORES.with(|stores| {
+        let stores = stores.try_borrow().map_err(|_| stores_busy())?;
+        let store = stores
+            .get(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        match &store.history {
+            Some(history) => to_js(&history.summary()),
+            None => Ok(JsValue::NULL),
+        }
+    })
+}
+
+fn step_store_history(store_id: &str, undo: bool) -> Result<JsValue, JsValue> {
+    let stepped = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.flush_lanes();
+        let history = store
+            .history
+            .as_mut()
+            .ok_or_else(|| js_error(&format!("History is not enabled for store: {store_id}")))?;
+        let mut next = store.state.clone();
+        let changed = if undo {
+            history.undo(&mut next)
+        } else {
+            history.redo(&mut next)
+        }
+        .map_err(|error| js_error(&error))?;
+        if !changed {
+            return Ok(None);
+        }
+        if let Err(error) = store.check_schema(store_id, &next) {
+            // Step back so the entry stays where it was.
+            if let Some(history) = store.history.as_mut() {
+                let _ = if undo { history.redo(&mut next) } else { history.undo(&mut next) };
+            }
+            return Err(error);
+        }
+        store.state = next;
+
+        if store.records_actions() {
+            let state = store.state.to_value();
+            store.record_action(EntryKind::Dispatch, "SET", state, None, js_sys::Date::now());
+        }
+        store.refresh_fast_count();
+        store.sync_lanes_from_state();
+        store.metrics.total_updates += 1;
+        let notifications = collect_notifications(store);
+        Ok::<Option<(StateTree, Vec<Notification>)>, JsValue>(Some((store.state.clone(), notifications)))
+    })?;
+
+    let Some((state, notifications)) = stepped else {
+        return Ok(JsValue::NULL);
+    };
+    notify_subscribers(notifications)?;
+    to_js(&state)
+}
+
+/// Registers a named reducer for `action_type` on the store: either a
+/// declarative op list (`set`/`inc`/`toggle`/`push` with `$payload` refs) or
+/// a JS function `(state, payload) => nextState`.
+#[wasm_bindgen]
+pub fn register_reducer(
+    store_id: &str,
+    action_type: &str,
+    spec: JsValue,
+    reducer: Option<js_sys::Function>,
+) -> Result<(), JsValue> {
+    if BUILT_IN_ACTIONS.contains(&action_type) {
+        return Err(js_error(&format!("Cannot override built-in action: {action_type}")));
+    }
+    let spec = from_js(spec)?;
+
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store
+            .reducers
+            .register(action_type, &spec, reducer)
+            .map_err(|error| js_error(&error))
+    })
+}
+
+#[wasm_bindgen]
+pub fn unregister_reducer(store_id: &str, action_type: &str) -> Result<bool, JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        Ok(store.reducers.remove(action_type))
+    })
+}
+
+/// Chooses how the store's `DEEP_MERGE` actions combine arrays:
+/// `{ arrayStrategy: "replace" | "concat" | "mergeById", idKey? }`. The
+/// default, `replace`, is plain RFC 7396.
+#[wasm_bindgen]
+pub fn set_array_merge_strategy(store_id: &str, options: JsValue) -> Result<(), JsValue> {
+    let strategy = ArrayMergeStrategy::parse(&from_js(options)?).map_err(|error| js_error(&error))?;
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.reducers.set_array_merge(strategy);
+        Ok(())
+    })
+}
+
+/// In strict mode dispatching an action with no built-in or registered
+/// reducer fails instead of leaving the state unchanged.
+#[wasm_bindgen]
+pub fn set_store_strict(store_id: &str, strict: bool) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.reducers.set_strict(strict);
+        Ok(())
+    })
+}
+
+/// Starts journaling the store's actions. `options.maxEntries` bounds the
+/// journal (10,000 by default); older entries are folded into the exported
+/// initial state.
+#[wasm_bindgen]
+pub fn enable_action_log(store_id: &str, options: JsValue) -> Result<(), JsValue> {
+    let options = from_js(options)?;
+    let max_entries = match options.get("maxEntries") {
+        None | Some(Value::Null) => DEFAULT_MAX_ENTRIES,
+        Some(value) => value
+            .as_u64()
+            .filter(|count| *count > 0)
+            .map(|count| count as usize)
+            .ok_or_else(|| js_error(&format!("maxEntries must be a positive integer, got {value}")))?,
+    };
+
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        if store.journal.is_none() {
+            store.journal = Some(ActionJournal::new(store.state.clone(), max_entries));
+        }
+        Ok(())
+    })
+}
+
+#[wasm_bindgen]
+pub fn disable_action_log(store_id: &str) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.journal = None;
+        Ok(())
+    })
+}
+
+#[wasm_bindgen]
+pub fn export_action_log(store_id: &str) -> Result<JsValue, JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let journal = store
+            .journal
+            .as_ref()
+            .ok_or_else(|| js_error(&format!("Action log is not enabled for store: {store_id}")))?;
+        to_js(&journal.export(store_id))
+    })
+}
+
+/// Encodes the store's state in a versioned `{ version, storeId, state }`
+/// envelope, the format used for persisting stores.
+#[wasm_bindgen]
+pub fn export_store_bytes(store_id: &str, format: Option<String>) -> Result<Vec<u8>, JsValue> {
+    let format = BinaryFormat::parse(format.as_deref()).map_err(|error| js_error(&error))?;
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let export = StoreExport {
+            version: STORE_EXPORT_VERSION,
+            store_id: store_id.to_string(),
+            state: store.state.clone(),
+        };
+        format.encode(&export).map_err(|error| js_error(&error))
+    })
+}
+
+/// Replaces the store's state with an `export_store_bytes` envelope. The
+/// import is committed like a `SET`: schema-checked, recorded in history
+/// and the action log, and delivered to subscribers.
+#[wasm_bindgen]
+pub fn import_store_bytes(store_id: &str, bytes: &[u8], format: Option<String>) -> Result<(), JsValue> {
+    let timestamp = js_sys::Date::now();
+    let format = BinaryFormat::parse(format.as_deref()).map_err(|error| js_error(&error))?;
+    let export: StoreExport = format
+        .decode(bytes)
+        .map_err(|error| js_error(&format!("Invalid store export: {error}")))?;
+    if export.version != STORE_EXPORT_VERSION {
+        return Err(js_error(&format!("Unsupported store export version: {}", export.version)));
+    }
+
+    let notifications = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        store.check_schema(store_id, &export.state)?;
+        store.commit_import(export.state, "IMPORT", timestamp);
+        Ok::<Vec<Notification>, JsValue>(collect_notifications(store))
+    })?;
+
+    notify_subscribers(notifications)
+}
+
+/// One store's entry in a runtime document.
+#[derive(Deserialize)]
+pub(crate) struct RuntimeStoreEntry {
+    state: StateTree,
+    #[serde(default)]
+    schema: Option<StoreSchema>,
+    #[serde(default = "StoreSnapshots::new")]
+    snapshots: StoreSnapshots,
+}
+
+pub(crate) fn export_runtime_stores(filter: &RuntimeFilter) -> Result<BTreeMap<String, Value>, String> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| STORES_BUSY.to_string())?;
+        let mut entries = BTreeMap::new();
+        for (store_id, store) in stores.iter_mut().filter(|(store_id, _)| filter.allows(store_id)) {
+            store.flush_lanes();
+            let entry = serde_json::json!({
+                "state": store.state,
+                "schema": store.schema,
+                "snapshots": store.snapshots,
+            });
+            entries.insert(store_id.clone(), entry);
+        }
+        Ok(entries)
+    })
+}
+
+/// Decodes every entry and checks its state against the entry's schema, or
+/// the schema already registered for that store, before anything is applied.
+pub(crate) fn decode_runtime_stores(entries: BTreeMap<String, Value>) -> Result<Vec<(String, RuntimeStoreEntry)>, JsValue> {
+    STORES.with(|stores| {
+        let stores = stores.try_borrow().map_err(|_| stores_busy())?;
+        entries
+            .into_iter()
+            .map(|(store_id, entry)| {
+                let mut entry: RuntimeStoreEntry = serde_json::from_value(entry)
+                    .map_err(|error| js_error(&format!("Invalid store {store_id}: {error}")))?;
+                if let Some(schema) = entry.schema.as_mut() {
+                    schema.compile().map_err(|error| js_error(&error))?;
+                }
+                let schema = entry
+                    .schema
+                    .as_ref()
+                    .or_else(|| stores.get(&store_id).and_then(|store| store.schema.as_ref()));
+                if let Some(schema) = schema {
+                    schema.check(&store_id, &entry.state)?;
+                }
+                Ok((store_id, entry))
+            })
+            .collect()
+    })
+}
+
+/// Creates missing stores and commits imported state into existing ones,
+/// keeping their subscriptions, lanes and reducers.
+pub(crate) fn install_runtime_stores(entries: Vec<(String, RuntimeStoreEntry)>) -> Result<(), JsValue> {
+    let timestamp = js_sys::Date::now();
+    let notifications = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let mut notifications = Vec::new();
+        for (store_id, entry) in entries {
+            let store = match stores.get_mut(&store_id) {
+                Some(store) => {
+                    store.flush_lanes();
+                    store.commit_import(entry.state, "IMPORT", timestamp);
+                    store
+                }
+                None => stores.entry(store_id).or_insert_with(|| Store::new(entry.state)),
+            };
+            if entry.schema.is_some() {
+                store.schema = entry.schema;
+            }
+            store.snapshots = entry.snapshots;
+            notifications.extend(collect_notifications(store));
+        }
+        Ok::<_, JsValue>(notifications)
+    })?;
+
+    notify_subscribers(notifications)
+}
+
+/// Replays `log` into the store and keeps recording on top of it.
+#[wasm_bindgen]
+pub fn import_action_log(store_id: &str, log: JsValue) -> Result<JsValue, JsValue> {
+    commit_replay(store_id, parse_action_log(log)?, true)
+}
+
+/// Rebuilds the store's state from `log`, failing if any entry's recorded
+/// state hash does not match the replayed state.
+#[wasm_bindgen]
+pub fn replay_actions(store_id: &str, log: JsValue) -> Result<JsValue, JsValue> {
+    commit_replay(store_id, parse_action_log(log)?, false)
+}
+
+fn parse_action_log(log: JsValue) -> Result<ActionLog, JsValue> {
+    serde_json::from_value(from_js(log)?).map_err(|error| js_error(&format!("Invalid action log: {error}")))
+}
+
+fn commit_replay(store_id: &str, log: ActionLog, adopt: bool) -> Result<JsValue, JsValue> {
+    let timestamp = js_sys::Date::now();
+
+    let (state, notifications) = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        let states = replay_log(&log, &store.reducers).map_err(|error| js_error(&error))?;
+        let state = states.last().cloned().unwrap_or_else(|| log.initial_state.clone().into());
+        store.flush_lanes();
+        store.check_schema(store_id, &state)?;
+        store.record_history(&state, "REPLAY", timestamp);
+        store.state = state.clone();
+        if adopt {
+            let max_entries = store.journal.as_ref().map_or(DEFAULT_MAX_ENTRIES, ActionJournal::max_entries);
+            store.journal = Some(ActionJournal::from_log(log, states, max_entries));
+        } else if store.records_actions() {
+            store.record_action(EntryKind::Dispatch, "SET", state.to_value(), None, timestamp);
+        }
+        store.refresh_fast_count();
+        store.sync_lanes_from_state();
+        let notifications = collect_notifications(store);
+        Ok::<(StateTree, Vec<Notification>), JsValue>((state, notifications))
+    })?;
+
+    notify_subscribers(notifications)?;
+    to_js(&state)
+}
+
+/// Streams `INIT`/`ACTION` messages for the store to `send`, e.g. a Redux
+/// DevTools connection or a local message channel.
+#[wasm_bindgen]
+pub fn connect_devtools(store_id: &str, send: js_sys::Function, name: Option<String>) -> Result<(), JsValue> {
+    let notifications = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let mut bridge = DevtoolsBridge::new(name.unwrap_or_else(|| store_id.to_string()));
+        bridge.init(&store.state);
+        store.devtools = Some(DevtoolsConnection { bridge, send });
+        Ok::<Vec<Notification>, JsValue>(collect_notifications(store))
+    })?;
+
+    notify_subscribers(notifications)
+}
+
+#[wasm_bindgen]
+pub fn disconnect_devtools(store_id: &str) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.devtools = None;
+        Ok(())
+    })
+}
+
+/// Marks the store persistent. Changes are diffed against the last write and
+/// handed to `write` as `base`/`delta` records at most once per
+/// `throttleMs`; `paths` limits what is written and `compactEvery` sets how
+/// many deltas follow a base before the next base is written. A change held
+/// back by the window is written when it closes: `schedule(delayMs)` is
+/// called once per window and should run `flush_store_persistence` after
+/// `delayMs`, e.g. with `setTimeout`. Without it the change waits for the
+/// next commit or flush.
+#[wasm_bindgen]
+pub fn enable_store_persistence(
+    store_id: &str,
+    options: JsValue,
+    write: js_sys::Function,
+    schedule: Option<js_sys::Function>,
+) -> Result<(), JsValue> {
+    let options = from_js(options)?;
+    let key = options
+        .get("key")
+        .and_then(Value::as_str)
+        .unwrap_or(store_id)
+        .to_string();
+    let throttle_ms = options.get("throttleMs").and_then(Value::as_f64);
+    let compact_every = options
+        .get("compactEvery")
+        .and_then(Value::as_u64)
+        .map(|count| count.min(u64::from(u32::MAX)) as u32);
+    let paths = options
+        .get("paths")
+        .and_then(Value::as_array)
+        .map(|paths| paths.iter().filter_map(Value::as_str).map(parse_path).collect())
+        .transpose()
+        .map_err(|error| js_error(&error))?
+        .unwrap_or_default();
+
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.persistence = Some(PersistenceConnection {
+            tracker: StorePersistence::new(key, paths, throttle_ms, compact_every),
+            write,
+            schedule,
+        });
+        Ok(())
+    })
+}
+
+#[wasm_bindgen]
+pub fn disable_store_persistence(store_id: &str) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.persistence = None;
+        Ok(())
+    })
+}
+
+/// Writes pending changes now, ignoring the throttle window; `compact`
+/// writes a full base record instead of a delta. Returns whether a record
+/// was written.
+#[wasm_bindgen]
+pub fn flush_store_persistence(store_id: &str, compact: bool) -> Result<bool, JsValue> {
+    let notifications = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let Store { state, persistence, .. } = store;
+        let connection = persistence
+            .as_mut()
+            .ok_or_else(|| js_error(&format!("Store is not persistent: {store_id}")))?;
+        let now = js_sys::Date::now();
+        let record = if compact {
+            Some(connection.tracker.compact(state, now))
+        } else {
+            connection.tracker.poll(state, now, true)
+        };
+        Ok::<Vec<Notification>, JsValue>(record.map(|record| persistence_write(connection, record)).into_iter().collect())
+    })?;
+
+    let written = !notifications.is_empty();
+    notify_subscribers(notifications)?;
+    Ok(written)
+}
+
+/// Restores a persistent store from the records its adapter kept: the last
+/// `base` record plus the `delta` records after it, in sequence order.
+#[wasm_bindgen]
+pub fn hydrate_store_persistence(store_id: &str, records: JsValue) -> Result<JsValue, JsValue> {
+    let timestamp = js_sys::Date::now();
+    let records: Vec<PersistenceRecord> = serde_json::from_value(from_js(records)?)
+        .map_err(|error| js_error(&format!("Invalid persistence records: {error}")))?;
+
+    let (state, notifications) = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let connection = store
+            .persistence
+            .as_mut()
+            .ok_or_else(|| js_error(&format!("Store is not persistent: {store_id}")))?;
+        let state = connection
+            .tracker
+            .rehydrate(&store.state, &records)
+            .map_err(|error| js_error(&error))?;
+        store.check_schema(store_id, &state)?;
+        store.commit_import(state.clone(), "HYDRATE", timestamp);
+        let notifications = collect_notifications(store);
+        Ok::<(StateTree, Vec<Notification>), JsValue>((state, notifications))
+    })?;
+
+    notify_subscribers(notifications)?;
+    to_js(&state)
+}
+
+/// Joins the store to a sync channel: committed changes are sent to `send`
+/// as versioned `change` messages and `sync_receive` applies messages from
+/// other replicas. With `resync: true` the store asks its peers for a
+/// snapshot straight away. Returns the replica id.
+#[wasm_bindgen]
+pub fn connect_store_sync(store_id: &str, send: js_sys::Function, options: JsValue) -> Result<String, JsValue> {
+    let options = from_js(options)?;
+    let replica_id = options
+        .get("replicaId")
+        .and_then(Value::as_str)
+        .map(ToString::to_string)
+        .unwrap_or_else(|| next_id("replica"));
+    let resync = options.get("resync").and_then(Value::as_bool).unwrap_or(false);
+
+    let notifications = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let mut connection = SyncConnection {
+            replica: StoreSync::new(store_id.to_string(), replica_id.clone(), &store.state),
+            send,
+        };
+        let request = resync.then(|| connection.replica.resync_request(None));
+        let request = request.map(|request| sync_send(&connection, &request));
+        store.sync = Some(connection);
+        Ok::<Vec<Notification>, JsValue>(request.into_iter().collect())
+    })?;
+
+    notify_subscribers(notifications)?;
+    Ok(replica_id)
+}
+
+#[wasm_bindgen]
+pub fn disconnect_store_sync(store_id: &str) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.sync = None;
+        Ok(())
+    })
+}
+
+/// Applies a message received from the sync channel. Returns
+/// `{ status, conflicts, remoteWon }`; conflicts list the JSON Pointers
+/// that overlapped concurrent local changes.
+#[wasm_bindgen]
+pub fn sync_receive(store_id: &str, message: JsValue) -> Result<JsValue, JsValue> {
+    let timestamp = js_sys::Date::now();
+    let message: SyncMessage = serde_json::from_value(from_js(message)?)
+        .map_err(|error| js_error(&format!("Invalid sync message: {error}")))?;
+
+    let (report, notifications) = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        if store.sync.is_none() {
+            return Err(js_error(&format!("Store is not synchronized: {store_id}")));
+        }
+
+        store.flush_lanes();
+        let mut notifications = collect_notifications(store);
+        let report = receive_sync_message(store, store_id, message, timestamp, &mut notifications);
+        Ok::<(Result<Value, JsValue>, Vec<Notification>), JsValue>((report, notifications))
+    })?;
+
+    notify_subscribers(notifications)?;
+    to_js(&report?)
+}
+
+/// Runs `message` through the store's replica and commits the outcome. A
+/// state the schema rejects leaves both the store and the replica as they
+/// were, so the remote change is not later reverted by a local capture.
+fn receive_sync_message(
+    store: &mut Store,
+    store_id: &str,
+    message: SyncMessage,
+    timestamp: f64,
+    notifications: &mut Vec<Notification>,
+) -> Result<Value, JsValue> {
+    let Store { state, sync, schema, .. } = store;
+    let connection = sync.as_mut().ok_or_else(|| js_error(&format!("Store is not synchronized: {store_id}")))?;
+    let checkpoint = schema.is_some().then(|| connection.replica.clone());
+    let outcome = connection.replica.receive(state, message).map_err(|error| js_error(&error))?;
+    if let Some(next) = outcome.state.as_ref() {
+        if let Err(error) = store.check_schema(store_id, next) {
+            if let (Some(connection), Some(checkpoint)) = (store.sync.as_mut(), checkpoint) {
+                connection.replica = checkpoint;
+            }
+            return Err(error);
+        }
+    }
+    if let (Some(connection), Some(reply)) = (store.sync.as_ref(), outcome.reply.as_ref()) {
+        notifications.push(sync_send(connection, reply));
+    }
+
+    if let Some(next) = outcome.state {
+        store.record_history(&next, "SYNC", timestamp);
+        store.state = next;
+        if store.records_actions() {
+            let (action_type, payload) = match outcome.status {
+                SyncStatus::Applied => ("PATCH", Value::from(outcome.applied)),
+                _ => ("SET", store.state.to_value()),
+            };
+            store.record_action(EntryKind::Dispatch, action_type, payload, None, timestamp);
+        }
+        store.refresh_fast_count();
+        store.sync_lanes_from_state();
+        store.metrics.total_updates += 1;
+        notifications.extend(collect_notifications(store));
+    }
+    Ok(serde_json::json!({
+        "status": outcome.status,
+        "conflicts": outcome.conflicts,
+        "remoteWon": outcome.remote_won,
+    }))
+}
+
+/// Backs the store with a CRDT document. Every commit, whatever the action,
+/// is recorded as CRDT operations; `counters` lists the paths whose integer
+/// values merge as counters (default `["count"]`). Returns the replica id.
+#[wasm_bindgen]
+pub fn enable_crdt(store_id: &str, options: JsValue) -> Result<String, JsValue> {
+    let options = from_js(options)?;
+    let replica_id = options
+        .get("replicaId")
+        .and_then(Value::as_str)
+        .map(ToString::to_string)
+        .unwrap_or_else(|| next_id("replica"));
+    let counters = match options.get("counters").and_then(Value::as_array) {
+        Some(paths) => paths
+            .iter()
+            .filter_map(Value::as_str)
+            .map(parse_path)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| js_error(&error))?,
+        None => vec![vec!["count".to_string()]],
+    };
+
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let document = CrdtDocument::new(replica_id.clone(), counters, &store.state).map_err(|error| js_error(&error))?;
+        store.crdt = Some(document);
+        Ok(replica_id)
+    })
+}
+
+#[wasm_bindgen]
+pub fn disable_crdt(store_id: &str) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.crdt = None;
+        Ok(())
+    })
+}
+
+/// Encodes the operations committed locally since the last call; empty when
+/// there are none. With `full` set, encodes the whole document instead, for
+/// bootstrapping a new peer.
+#[wasm_bindgen]
+pub fn crdt_take_update(store_id: &str, full: bool, format: Option<String>) -> Result<Vec<u8>, JsValue> {
+    let format = BinaryFormat::parse(format.as_deref()).map_err(|error| js_error(&error))?;
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let Store { state, crdt, .. } = store;
+        let document = crdt
+            .as_mut()
+            .ok_or_else(|| js_error(&format!("Store is not a CRDT store: {store_id}")))?;
+        document.reconcile(state).map_err(|error| js_error(&error))?;
+        let update = if full {
+            document.full_update()
+        } else {
+            match document.take_update() {
+                Some(update) => update,
+                None => return Ok(Vec::new()),
+            }
+        };
+        format.encode(&update).map_err(|error| js_error(&error))
+    })
+}
+
+/// Merges an update produced by another replica and commits the merged
+/// state. Local commits are recorded first, so none are lost. A merge whose
+/// result fails the store's schema is rejected and leaves the replica as it
+/// was.
+#[wasm_bindgen]
+pub fn crdt_merge_update(store_id: &str, update: &[u8], format: Option<String>) -> Result<(), JsValue> {
+    let timestamp = js_sys::Date::now();
+    let format = BinaryFormat::parse(format.as_deref()).map_err(|error| js_error(&error))?;
+    let update: CrdtUpdate = format
+        .decode(update)
+        .map_err(|error| js_error(&format!("Invalid CRDT update: {error}")))?;
+
+    let notifications = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        store.flush_lanes();
+        let Store { state, crdt, schema, .. } = store;
+        let document = crdt
+            .as_mut()
+            .ok_or_else(|| js_error(&format!("Store is not a CRDT store: {store_id}")))?;
+        document.reconcile(state).map_err(|error| js_error(&error))?;
+        let checkpoint = schema.is_some().then(|| document.clone());
+        document.merge(update).map_err(|error| js_error(&error))?;
+        let merged = document.state().clone();
+        if merged != store.state {
+            if let Err(error) = store.check_schema(store_id, &merged) {
+                store.crdt = checkpoint;
+                return Err(error);
+            }
+            store.commit_import(merged, "CRDT_MERGE", timestamp);
+        }
+        Ok::<Vec<Notification>, JsValue>(collect_notifications(store))
+    })?;
+
+    notify_subscribers(notifications)
+}
+
+/// Handles a DevTools monitor message. Jumps and imports are applied via
+/// `SET`; returns whether the store state was replaced.
+#[wasm_bindgen]
+pub fn devtools_receive(store_id: &str, message: JsValue) -> Result<bool, JsValue> {
+    let command = parse_command(&from_js(message)?).map_err(|error| js_error(&error))?;
+    match command {
+        DevtoolsCommand::Ignore => Ok(false),
+        DevtoolsCommand::Reinit => {
+            let notifications = STORES.with(|stores| {
+                let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+                let store = stores
+                    .get_mut(store_id)
+                    .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+                store.flush_lanes();
+                let state = store.state.clone();
+                if let Some(connection) = store.devtools.as_mut() {
+                    connection.bridge.init(&state);
+                }
+                Ok::<Vec<Notification>, JsValue>(collect_notifications(store))
+            })?;
+            notify_subscribers(notifications)?;
+            Ok(false)
+        }
+        DevtoolsCommand::SetState(state) => {
+            set_devtools_muted(store_id, true)?;
+            let result = dispatch(store_id, "SET", to_js(&state)?, None);
+            set_devtools_muted(store_id, false)?;
+            result.map(|_| true)
+        }
+    }
+}
+
+fn set_devtools_muted(store_id: &str, muted: bool) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+
+        if let Some(connection) = store.devtools.as_mut() {
+            connection.bridge.set_muted(muted);
+        }
+        Ok(())
+    })
+}
+
+#[wasm_bindgen]
+pub fn get_metrics(store_id: &str) -> Result<JsValue, JsValue> {
+    STORES.with(|stores| {
+        let stores = stores.try_borrow().map_err(|_| stores_busy())?;
+        let store = stores
+            .get(store_id)
             .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
 
         let mut avg_dispatch_time = store.metrics.dispatch_time_total
@@ -493,31 +2309,23 @@ pub fn get_metrics(store_id: &str) -> Result<JsValue, JsValue> {
         if store.metrics.total_dispatches > 0 && avg_dispatch_time <= 0.0 {
             avg_dispatch_time = 0.001;
         }
-        let lane_metrics = store.counter_handle.and_then(counter_lane_metrics);
+        let (lane_updates, lane_dispatches) = store.lane_pending();
         let effective_count = store
             .counter_handle
             .and_then(counter_lane_value)
             .or(store.fast_count);
-        let memory_state = if effective_count.is_some() {
-            let mut state = store.state.clone();
-            if let Some(object) = state.as_object_mut() {
-                object.insert(
-                    "count".to_string(),
-                    Value::Number(effective_count.unwrap_or_default().into()),
-                );
-            }
-            state
-        } else {
-            store.state.clone()
-        };
+        let mut memory_state = store.state.clone();
+        if let (Some(count), Some(object)) = (effective_count, memory_state.as_object_mut()) {
+            object.insert("count".into(), StateTree::Number(count.into()));
+        }
         let metrics = serde_json::json!({
             "store_id": store_id,
             "subscriber_count": store.subscriptions.len(),
             "snapshot_count": store.snapshots.len(),
             "memory_usage": serde_json::to_string(&memory_state).map(|state| state.len()).unwrap_or(0),
             "total_selects": store.metrics.total_selects,
-            "total_updates": store.metrics.total_updates + lane_metrics.map(|metrics| metrics.0).unwrap_or(0),
-            "total_dispatches": store.metrics.total_dispatches + lane_metrics.map(|metrics| metrics.1).unwrap_or(0),
+            "total_updates": store.metrics.total_updates + lane_updates,
+            "total_dispatches": store.metrics.total_dispatches + lane_dispatches,
             "avg_dispatch_time": avg_dispatch_time,
             "schema": store.schema,
             "timestamp": Utc::now().to_rfc3339(),
@@ -529,10 +2337,11 @@ pub fn get_metrics(store_id: &str) -> Result<JsValue, JsValue> {
 
 #[wasm_bindgen]
 pub fn register_store_schema(schema: JsValue) -> Result<(), JsValue> {
-    let schema: StoreSchema = serde_wasm_bindgen::from_value(schema)?;
+    let mut schema: StoreSchema = serde_wasm_bindgen::from_value(schema)?;
+    schema.compile().map_err(|error| js_error(&error))?;
 
     STORES.with(|stores| {
-        let mut stores = stores.borrow_mut();
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
         let store = stores
             .get_mut(&schema.store_id)
             .ok_or_else(|| js_error(&format!("Store not found: {}", schema.store_id)))?;
@@ -541,11 +2350,24 @@ pub fn register_store_schema(schema: JsValue) -> Result<(), JsValue> {
     })
 }
 
+#[wasm_bindgen]
+pub fn validate_store_state(store_id: &str) -> Result<JsValue, JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.flush_lanes();
+        to_js(&store.schema_violations(&store.state))
+    })
+}
+
 #[wasm_bindgen]
 pub fn get_store_schemas() -> Result<JsValue, JsValue> {
     STORES.with(|stores| {
         let schemas: Vec<StoreSchema> = stores
-            .borrow()
+            .try_borrow()
+            .map_err(|_| stores_busy())?
             .values()
             .filter_map(|store| store.schema.clone())
             .collect();
@@ -553,6 +2375,126 @@ pub fn get_store_schemas() -> Result<JsValue, JsValue> {
     })
 }
 
+#[wasm_bindgen]
+pub fn register_store_migration(
+    schema_id: &str,
+    migration: JsValue,
+    up: Option<js_sys::Function>,
+    down: Option<js_sys::Function>,
+) -> Result<(), JsValue> {
+    let spec = from_js(migration)?;
+    let migration = Migration::from_spec(&spec, up, down).map_err(|error| js_error(&error))?;
+
+    MIGRATIONS.with(|migrations| {
+        let mut migrations = migrations.borrow_mut();
+        let registered = migrations.entry(schema_id.to_string()).or_default();
+        registered.retain(|existing| !existing.same_edge(&migration));
+        registered.push(migration);
+    });
+    Ok(())
+}
+
+/// Migrates a store's state to `target_version` (an exact version or a range)
+/// through the migrations registered for its schema id.
+///
+/// The migrated state is validated before it is committed: against
+/// `json_schema`, which then replaces the registered JSON Schema, or, when
+/// that is omitted, against the registered one, which is kept. The migration
+/// fails if the store changed while migration callbacks ran.
+#[wasm_bindgen]
+pub fn migrate_store(store_id: &str, target_version: &str, json_schema: JsValue) -> Result<JsValue, JsValue> {
+    let target_schema = match from_js(json_schema)? {
+        Value::Null => None,
+        json_schema => {
+            let compiled = CompiledSchema::compile(json_schema.clone()).map_err(|error| js_error(&error))?;
+            Some((json_schema, compiled))
+        }
+    };
+    let (schema_id, from_version, base) = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.flush_lanes();
+        let schema = store
+            .schema
+            .as_ref()
+            .ok_or_else(|| js_error(&format!("Store has no registered schema: {store_id}")))?;
+        Ok::<(String, String, StateTree), JsValue>((
+            schema.schema_id.clone(),
+            schema.schema_version.clone(),
+            store.state.clone(),
+        ))
+    })?;
+    let mut state = base.clone();
+
+    let steps = MIGRATIONS.with(|migrations| {
+        let migrations = migrations.borrow();
+        let registered = migrations.get(&schema_id).map(Vec::as_slice).unwrap_or_default();
+        plan_migration(registered, &from_version, target_version)
+    })
+    .map_err(|error| js_error(&error))?;
+
+    for step in &steps {
+        apply_migration_ops(&mut state, &step.ops).map_err(|error| js_error(&error))?;
+        if let Some(callback) = &step.callback {
+            let migrated = callback
+                .call3(
+                    &JsValue::NULL,
+                    &to_js(&state)?,
+                    &JsValue::from_str(&step.from),
+                    &JsValue::from_str(&step.to),
+                )
+                .map_err(|error| js_error(&format!("Migration callback failed: {:?}", error)))?;
+            state = from_js(migrated)?.into();
+        }
+    }
+    let to_version = steps.last().map(|step| step.to.clone()).unwrap_or_else(|| from_version.clone());
+
+    let notifications = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let store = stores
+            .get_mut(store_id)
+            .ok_or_else(|| js_error(&format!("Store not found: {store_id}")))?;
+        store.flush_lanes();
+        if store.state != base {
+            return Err(js_error(&format!("Store {store_id} changed while it was being migrated")));
+        }
+        let mut schema = store
+            .schema
+            .clone()
+            .filter(|schema| schema.schema_version == from_version)
+            .ok_or_else(|| js_error(&format!("Store schema changed during migration: {store_id}")))?;
+        schema.schema_version = to_version.clone();
+        if let Some((json_schema, compiled)) = target_schema {
+            schema.json_schema = Some(json_schema);
+            schema.compiled = Some(compiled);
+        }
+        schema.check(store_id, &state)?;
+        store.schema = Some(schema);
+
+        let timestamp = js_sys::Date::now();
+        store.record_history(&state, "MIGRATE", timestamp);
+        store.state = state.clone();
+        if store.records_actions() {
+            store.record_action(EntryKind::Dispatch, "SET", state.to_value(), None, timestamp);
+        }
+        store.refresh_fast_count();
+        store.sync_lanes_from_state();
+        Ok::<Vec<Notification>, JsValue>(collect_notifications(store))
+    })?;
+
+    notify_subscribers(notifications)?;
+    to_js(&serde_json::json!({
+        "storeId": store_id,
+        "schemaId": schema_id,
+        "fromVersion": from_version,
+        "toVersion": to_version,
+        "steps": steps.len(),
+        "state": state,
+    }))
+}
+
 #[wasm_bindgen]
 pub struct BatchUpdate {
     store_id: String,
@@ -569,95 +2511,236 @@ impl BatchUpdate {
         }
     }
 
-    pub fn add_update(&mut self, action_type: &str, payload: JsValue) {
-        self.updates.push((action_type.to_string(), payload));
+    pub fn add_update(&mut self, action_type: &str, payload: JsValue) {
+        self.updates.push((action_type.to_string(), payload));
+    }
+
+    /// Applies every update atomically and notifies subscribers once.
+    pub fn execute(&self) -> Result<JsValue, JsValue> {
+        if self.updates.is_empty() {
+            return Ok(JsValue::UNDEFINED);
+        }
+        let actions = self
+            .updates
+            .iter()
+            .map(|(action_type, payload)| {
+                Ok(StagedAction {
+                    store_id: self.store_id.clone(),
+                    action_type: action_type.clone(),
+                    payload: from_js(payload.clone())?,
+                })
+            })
+            .collect::<Result<Vec<_>, JsValue>>()?;
+        let (mut states, notifications) = commit_transaction(&actions)?;
+        notify_subscribers(notifications)?;
+        to_js(&states.remove(&self.store_id).unwrap_or_default())
+    }
+}
+
+struct StagedAction {
+    store_id: String,
+    action_type: String,
+    payload: Value,
+}
+
+/// Actions staged across several stores and committed all-or-nothing.
+///
+/// Every action is applied to a working copy of its store, all staged states
+/// are checked against their schemas, and only then are the stores updated.
+/// Each affected subscriber fires once, after the whole commit.
+#[wasm_bindgen]
+pub struct Transaction {
+    actions: Vec<StagedAction>,
+}
+
+#[wasm_bindgen]
+impl Transaction {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self { actions: Vec::new() }
+    }
+
+    pub fn add(&mut self, store_id: &str, action_type: &str, payload: JsValue) -> Result<(), JsValue> {
+        self.actions.push(StagedAction {
+            store_id: store_id.to_string(),
+            action_type: action_type.to_string(),
+            payload: from_js(payload)?,
+        });
+        Ok(())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.actions.len()
     }
 
-    pub fn execute(&self) -> Result<JsValue, JsValue> {
-        let mut result = JsValue::UNDEFINED;
-        for (action_type, payload) in &self.updates {
-            result = dispatch(&self.store_id, action_type, payload.clone())?;
-        }
-        Ok(result)
+    /// Commits the staged actions and returns the next state of each
+    /// affected store keyed by store id. If the commit fails the actions
+    /// stay staged, so it can be retried or rolled back.
+    pub fn commit(&mut self) -> Result<JsValue, JsValue> {
+        let (states, notifications) = commit_transaction(&self.actions)?;
+        self.actions.clear();
+        notify_subscribers(notifications)?;
+        to_js(&states)
+    }
+
+    pub fn rollback(&mut self) {
+        self.actions.clear();
     }
 }
 
-fn apply_action(current: &Value, action_type: &str, payload: Value) -> Result<Value, JsValue> {
-    match action_type {
-        "SET" => Ok(payload),
-        "MERGE" => Ok(merge_state(current, payload)),
-        "UPDATE" => {
-            let path = payload
-                .get("path")
-                .and_then(Value::as_str)
-                .ok_or_else(|| js_error("UPDATE requires payload.path"))?;
-            let value = payload.get("value").cloned().unwrap_or(Value::Null);
-            let mut next = current.clone();
-            set_path(&mut next, path, value);
-            Ok(next)
+impl Default for Transaction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Updates the stores and returns their new states with the notifications
+/// to deliver once the caller has settled.
+fn commit_transaction(actions: &[StagedAction]) -> Result<(HashMap<String, StateTree>, Vec<Notification>), JsValue> {
+    let timestamp = js_sys::Date::now();
+
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        for action in actions {
+            stores
+                .get_mut(&action.store_id)
+                .ok_or_else(|| js_error(&format!("Store not found: {}", action.store_id)))?
+                .flush_lanes();
         }
-        "DELETE" => {
-            let path = payload
-                .as_str()
-                .or_else(|| payload.get("path").and_then(Value::as_str))
-                .ok_or_else(|| js_error("DELETE requires a path"))?;
-            let mut next = current.clone();
-            delete_path(&mut next, path);
-            Ok(next)
+
+        let staged = stage_transaction(&stores, actions).map_err(|error| js_error(&error))?;
+        for (store_id, next) in &staged {
+            stores[store_id].check_schema(store_id, next)?;
         }
-        "BATCH" => {
-            let updates = payload
-                .as_array()
-                .ok_or_else(|| js_error("BATCH requires an array payload"))?;
-            let mut next = current.clone();
 
-            for update in updates {
-                let nested_action_type = update
-                    .get("actionType")
-                    .or_else(|| update.get("type"))
-                    .and_then(Value::as_str)
-                    .unwrap_or("UPDATE");
-                let nested_payload = update.get("payload").cloned().unwrap_or_else(|| update.clone());
-                apply_action_mut(&mut next, nested_action_type, nested_payload)?;
+        let mut notifications = Vec::new();
+        for (store_id, next) in &staged {
+            let Some(store) = stores.get_mut(store_id) else {
+                continue;
+            };
+            store.record_history(next, "TRANSACTION", timestamp);
+            store.state = next.clone();
+            if store.records_actions() {
+                let batch: Vec<Value> = actions
+                    .iter()
+                    .filter(|action| action.store_id == *store_id)
+                    .map(|action| serde_json::json!({ "actionType": action.action_type, "payload": action.payload }))
+                    .collect();
+                store.record_action(EntryKind::Dispatch, "BATCH", Value::Array(batch), None, timestamp);
             }
-
-            Ok(next)
+            store.refresh_fast_count();
+            store.sync_lanes_from_state();
+            store.metrics.total_updates += 1;
+            store.metrics.total_dispatches += 1;
+            notifications.extend(collect_notifications(store));
         }
-        _ => Ok(current.clone()),
+        Ok((staged.into_iter().collect(), notifications))
+    })
+}
+
+/// Applies `actions` to working copies of their stores without touching the
+/// stores themselves, returning the staged states in first-touched order.
+fn stage_transaction(
+    stores: &HashMap<String, Store>,
+    actions: &[StagedAction],
+) -> Result<Vec<(String, StateTree)>, String> {
+    let mut staged: Vec<(String, StateTree)> = Vec::new();
+    for (index, action) in actions.iter().enumerate() {
+        let position = match staged.iter().position(|(store_id, _)| *store_id == action.store_id) {
+            Some(position) => position,
+            None => {
+                let store = stores
+                    .get(&action.store_id)
+                    .ok_or_else(|| format!("Store not found: {}", action.store_id))?;
+                staged.push((action.store_id.clone(), store.state.clone()));
+                staged.len() - 1
+            }
+        };
+        let reducers = &stores[&action.store_id].reducers;
+        apply_action_mut(&mut staged[position].1, &action.action_type, action.payload.clone(), reducers).map_err(|error| {
+            format!(
+                "Transaction action {index} ({}) on {} failed: {error}",
+                action.action_type, action.store_id
+            )
+        })?;
     }
+    Ok(staged)
 }
 
-fn apply_action_mut(state: &mut Value, action_type: &str, payload: Value) -> Result<(), JsValue> {
+const BUILT_IN_ACTIONS: &[&str] = &[
+    "SET",
+    "MERGE",
+    "DEEP_MERGE",
+    "UPDATE",
+    "DELETE",
+    "PUSH",
+    "INSERT",
+    "SPLICE",
+    "MOVE",
+    "REMOVE_WHERE",
+    "PATCH",
+    "BATCH",
+];
+
+fn apply_action(
+    current: &StateTree,
+    action_type: &str,
+    payload: Value,
+    reducers: &ActionReducers,
+) -> Result<StateTree, String> {
+    if action_type == "SET" {
+        return Ok(payload.into());
+    }
+
+    let mut next = current.clone();
+    apply_action_mut(&mut next, action_type, payload, reducers)?;
+    Ok(next)
+}
+
+pub(crate) fn apply_action_mut(
+    state: &mut StateTree,
+    action_type: &str,
+    payload: Value,
+    reducers: &ActionReducers,
+) -> Result<(), String> {
     match action_type {
         "SET" => {
-            *state = payload;
+            *state = payload.into();
             Ok(())
         }
         "MERGE" => {
-            *state = merge_state(state, payload);
+            merge_into(state, payload);
+            Ok(())
+        }
+        "DEEP_MERGE" => {
+            merge_patch(state, payload, reducers.array_merge());
             Ok(())
         }
         "UPDATE" => {
-            let path = payload
-                .get("path")
-                .and_then(Value::as_str)
-                .ok_or_else(|| js_error("UPDATE requires payload.path"))?;
+            let segments = parse_path(payload_path(&payload, action_type)?)?;
             let value = payload.get("value").cloned().unwrap_or(Value::Null);
-            set_path(state, path, value);
-            Ok(())
+            state.set(&segments, value.into())
         }
         "DELETE" => {
             let path = payload
                 .as_str()
                 .or_else(|| payload.get("path").and_then(Value::as_str))
-                .ok_or_else(|| js_error("DELETE requires a path"))?;
-            delete_path(state, path);
+                .ok_or("DELETE requires a path")?;
+            state.remove(&parse_path(path)?);
             Ok(())
         }
+        "PUSH" | "INSERT" | "SPLICE" | "MOVE" | "REMOVE_WHERE" => apply_array_action(state, action_type, &payload),
+        "PATCH" => {
+            let operations = payload
+                .as_array()
+                .ok_or("PATCH requires an array of JSON Patch operations")?;
+            apply_patch(state, operations)
+        }
         "BATCH" => {
             let updates = payload
                 .as_array()
-                .ok_or_else(|| js_error("BATCH requires an array payload"))?;
+                .ok_or("BATCH requires an array payload")?;
             for update in updates {
                 let nested_action_type = update
                     .get("actionType")
@@ -665,99 +2748,269 @@ fn apply_action_mut(state: &mut Value, action_type: &str, payload: Value) -> Res
                     .and_then(Value::as_str)
                     .unwrap_or("UPDATE");
                 let nested_payload = update.get("payload").cloned().unwrap_or_else(|| update.clone());
-                apply_action_mut(state, nested_action_type, nested_payload)?;
+                apply_action_mut(state, nested_action_type, nested_payload, reducers)?;
             }
             Ok(())
         }
-        _ => Ok(()),
+        other => reducers.apply(state, other, &payload),
     }
 }
 
-fn apply_counter_steps(
-    state: &mut Value,
+pub(crate) fn apply_counter_steps(
+    state: &mut StateTree,
     delta: i32,
     count: u32,
     framework: &str,
     action_name: &str,
     timestamp: f64,
-) -> Result<(), JsValue> {
+) -> Result<(), String> {
     let object = state
         .as_object_mut()
-        .ok_or_else(|| js_error("Counter dispatch requires an object state"))?;
-    let previous = object.get("count").and_then(Value::as_i64).unwrap_or(0);
+        .ok_or("Counter dispatch requires an object state")?;
+    let previous = object.get("count").and_then(StateTree::as_i64).unwrap_or(0);
     let total_delta = i64::from(delta) * i64::from(count);
     let new_value = previous + total_delta;
 
-    object.insert("count".to_string(), Value::Number(new_value.into()));
-    object.insert("lastUpdated".to_string(), json_number(timestamp));
-    object.insert("framework".to_string(), Value::String(framework.to_string()));
+    object.insert("count".into(), StateTree::Number(new_value.into()));
+    object.insert("lastUpdated".into(), json_number(timestamp).into());
+    object.insert("framework".into(), StateTree::String(framework.into()));
 
-    let mut history = object
-        .get("history")
-        .and_then(Value::as_array)
-        .cloned()
-        .unwrap_or_default();
+    let mut history = match object.get("history") {
+        Some(StateTree::Array(history)) => history.clone(),
+        _ => Default::default(),
+    };
 
     let start = count.saturating_sub(10);
     for index in start..count {
         let step_previous = previous + i64::from(delta) * i64::from(index);
         let step_new = step_previous + i64::from(delta);
-        history.push(serde_json::json!({
+        history.push_back(StateTree::from(serde_json::json!({
             "action": action_name,
             "framework": framework,
             "timestamp": timestamp,
             "previousValue": step_previous,
             "newValue": step_new,
-        }));
+        })));
     }
     if history.len() > 10 {
         history = history.split_off(history.len() - 10);
     }
-    object.insert("history".to_string(), Value::Array(history));
+    object.insert("history".into(), StateTree::Array(history));
     Ok(())
 }
 
 fn counter_lane_value(handle: u32) -> Option<i64> {
-    COUNTER_HANDLES.with(|handles| {
-        handles
-            .borrow()
-            .get(&handle)
-            .map(|lane| lane.value)
+    LANES.with(|lanes| match lanes.borrow_mut().with_lane(handle, |lane| lane.value())? {
+        LaneValue::Int(value) => Some(value),
+        _ => None,
     })
 }
 
-fn counter_lane_metrics(handle: u32) -> Option<(u32, u32)> {
-    COUNTER_HANDLES.with(|handles| {
-        handles
-            .borrow()
-            .get(&handle)
-            .map(|lane| (lane.pending_updates, lane.pending_dispatches))
-    })
+fn merge_into(state: &mut StateTree, payload: Value) {
+    match (state, payload) {
+        (StateTree::Object(current_object), Value::Object(payload_object)) => {
+            for (key, value) in payload_object {
+                current_object.insert(key.into(), value.into());
+            }
+        }
+        (state, payload) => *state = payload.into(),
+    }
 }
 
-fn merge_state(current: &Value, payload: Value) -> Value {
-    match (current, payload) {
-        (Value::Object(current_object), Value::Object(payload_object)) => {
-            let mut next = current_object.clone();
-            for (key, value) in payload_object {
-                next.insert(key, value);
+/// How `DEEP_MERGE` combines an array in the patch with an array in the
+/// state, set per store with `set_array_merge_strategy`.
+#[derive(Default)]
+pub(crate) enum ArrayMergeStrategy {
+    /// The patch array replaces the state array, as in RFC 7396.
+    #[default]
+    Replace,
+    Concat,
+    /// Deep-merges elements sharing the same id key and appends the rest.
+    MergeById(String),
+}
+
+impl ArrayMergeStrategy {
+    /// Parses `{ arrayStrategy: "replace" | "concat" | "mergeById", idKey? }`;
+    /// `idKey` defaults to `id`.
+    pub(crate) fn parse(options: &Value) -> Result<Self, String> {
+        let strategy = match options.get("arrayStrategy") {
+            None | Some(Value::Null) => "replace",
+            Some(strategy) => strategy.as_str().ok_or("arrayStrategy must be a string")?,
+        };
+        match strategy {
+            "replace" => Ok(Self::Replace),
+            "concat" => Ok(Self::Concat),
+            "mergeById" => Ok(Self::MergeById(
+                options.get("idKey").and_then(Value::as_str).unwrap_or("id").to_string(),
+            )),
+            other => Err(format!("Unknown arrayStrategy: {other}")),
+        }
+    }
+}
+
+/// Merges `item` into nothing, so object members that are `null` are
+/// dropped as RFC 7396 does for keys missing from the target.
+fn merge_patch_item(item: Value, strategy: &ArrayMergeStrategy) -> StateTree {
+    let mut tree = StateTree::Null;
+    merge_patch(&mut tree, item, strategy);
+    tree
+}
+
+fn merge_patch(target: &mut StateTree, patch: Value, strategy: &ArrayMergeStrategy) {
+    match patch {
+        Value::Object(patch_object) => {
+            if !target.is_object() {
+                *target = StateTree::object();
+            }
+            let Some(target_object) = target.as_object_mut() else {
+                return;
+            };
+            for (key, value) in patch_object {
+                if value.is_null() {
+                    target_object.remove(key.as_str());
+                } else {
+                    merge_patch(target_object.entry(key.into()).or_default(), value, strategy);
+                }
             }
-            Value::Object(next)
         }
-        (_, payload) => payload,
+        Value::Array(patch_array) => match (strategy, target) {
+            (ArrayMergeStrategy::Concat, StateTree::Array(target_array)) => {
+                target_array.extend(patch_array.into_iter().map(|item| merge_patch_item(item, strategy)));
+            }
+            (ArrayMergeStrategy::MergeById(id_key), StateTree::Array(target_array)) => {
+                for item in patch_array {
+                    let existing = item.get(id_key.as_str()).and_then(|id| {
+                        target_array
+                            .iter()
+                            .position(|candidate| candidate.child(id_key).is_some_and(|candidate_id| candidate_id == id))
+                    });
+                    match existing {
+                        Some(index) => merge_patch(&mut target_array[index], item, strategy),
+                        None => target_array.push_back(merge_patch_item(item, strategy)),
+                    }
+                }
+            }
+            (_, target) => *target = Value::Array(patch_array).into(),
+        },
+        patch => *target = patch.into(),
     }
 }
 
-fn select_value<'a>(state: &'a Value, path: &str) -> Option<&'a Value> {
+/// Parses a store path into reference tokens shared by select, subscribe,
+/// UPDATE, DELETE and the array actions.
+///
+/// Paths starting with `/` are RFC 6901 JSON Pointers. Otherwise segments are
+/// separated by `.`, `\` escapes the next character (`config.v1\.2`), and
+/// brackets address an index or a quoted key (`items[3].title`,
+/// `users["a@b.com"].name`). The empty path selects the whole state.
+pub(crate) fn parse_path(path: &str) -> Result<Vec<String>, String> {
     if path.is_empty() {
-        return Some(state);
+        return Ok(Vec::new());
+    }
+    if path.starts_with('/') {
+        return parse_pointer(path);
+    }
+
+    let characters: Vec<char> = path.chars().collect();
+    let mut segments = Vec::new();
+    let mut segment: Option<String> = None;
+    let mut after_bracket = false;
+    let mut index = 0;
+
+    while index < characters.len() {
+        let character = characters[index];
+        index += 1;
+        match character {
+            '.' => {
+                match segment.take() {
+                    Some(segment) => segments.push(segment),
+                    None if after_bracket => {}
+                    None => return Err(invalid_path(path, "empty segment")),
+                }
+                match characters.get(index) {
+                    None => return Err(invalid_path(path, "trailing '.'")),
+                    Some('.' | '[') => return Err(invalid_path(path, "empty segment")),
+                    Some(_) => {}
+                }
+                after_bracket = false;
+            }
+            '[' => {
+                if let Some(segment) = segment.take() {
+                    segments.push(segment);
+                }
+                let (key, next) = parse_bracket(&characters, index, path)?;
+                segments.push(key);
+                index = next;
+                after_bracket = true;
+            }
+            ']' => return Err(invalid_path(path, "unexpected ']'")),
+            _ if after_bracket => return Err(invalid_path(path, "expected '.' or '[' after ']'")),
+            '\\' => {
+                let escaped = characters
+                    .get(index)
+                    .ok_or_else(|| invalid_path(path, "trailing escape"))?;
+                index += 1;
+                segment.get_or_insert_with(String::new).push(*escaped);
+            }
+            other => segment.get_or_insert_with(String::new).push(other),
+        }
+    }
+
+    if let Some(segment) = segment {
+        segments.push(segment);
+    }
+    Ok(segments)
+}
+
+fn parse_bracket(characters: &[char], start: usize, path: &str) -> Result<(String, usize), String> {
+    if let Some(quote @ ('"' | '\'')) = characters.get(start) {
+        let mut key = String::new();
+        let mut index = start + 1;
+        loop {
+            match characters.get(index) {
+                None => return Err(invalid_path(path, "unterminated quoted key")),
+                Some('\\') => {
+                    let escaped = characters
+                        .get(index + 1)
+                        .ok_or_else(|| invalid_path(path, "trailing escape"))?;
+                    key.push(*escaped);
+                    index += 2;
+                }
+                Some(character) if character == quote => break,
+                Some(character) => {
+                    key.push(*character);
+                    index += 1;
+                }
+            }
+        }
+        if characters.get(index + 1) != Some(&']') {
+            return Err(invalid_path(path, "expected ']' after quoted key"));
+        }
+        return Ok((key, index + 2));
     }
 
+    let end = characters[start..]
+        .iter()
+        .position(|character| *character == ']')
+        .map(|offset| start + offset)
+        .ok_or_else(|| invalid_path(path, "unclosed '['"))?;
+    let index: String = characters[start..end].iter().collect();
+    if index.is_empty() || !index.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid_path(path, "bracket must contain an index or a quoted key"));
+    }
+    Ok((index, end + 1))
+}
+
+fn invalid_path(path: &str, reason: &str) -> String {
+    format!("Invalid path '{path}': {reason}")
+}
+
+pub(crate) fn select_value<'a>(state: &'a Value, segments: &[String]) -> Option<&'a Value> {
     let mut current = state;
-    for part in path.split('.') {
+    for segment in segments {
         current = match current {
-            Value::Object(object) => object.get(part)?,
-            Value::Array(array) => array.get(part.parse::<usize>().ok()?)?,
+            Value::Object(object) => object.get(segment)?,
+            Value::Array(array) => array.get(segment.parse::<usize>().ok()?)?,
             _ => return None,
         };
     }
@@ -765,89 +3018,409 @@ fn select_value<'a>(state: &'a Value, path: &str) -> Option<&'a Value> {
     Some(current)
 }
 
-fn set_path(state: &mut Value, path: &str, value: Value) {
-    let parts: Vec<&str> = path.split('.').filter(|part| !part.is_empty()).collect();
-    if parts.is_empty() {
-        *state = value;
-        return;
+fn payload_path<'a>(payload: &'a Value, action_type: &str) -> Result<&'a str, String> {
+    payload
+        .get("path")
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("{action_type} requires payload.path"))
+}
+
+fn payload_index(payload: &Value, key: &str, action_type: &str) -> Result<usize, String> {
+    payload
+        .get(key)
+        .and_then(Value::as_u64)
+        .map(|index| index as usize)
+        .ok_or_else(|| format!("{action_type} requires a non-negative payload.{key}"))
+}
+
+fn array_at_path<'a>(state: &'a mut StateTree, path: &str, create: bool) -> Result<&'a mut Vector<StateTree>, String> {
+    let segments = parse_path(path)?;
+    if create && state.get(&segments).is_none_or(StateTree::is_null) {
+        state.set(&segments, StateTree::Array(Vector::new()))?;
     }
 
-    let mut current = state;
-    for part in &parts[..parts.len() - 1] {
-        if !current.is_object() {
-            *current = Value::Object(Map::new());
+    state
+        .get_mut(&segments)
+        .and_then(StateTree::as_array_mut)
+        .ok_or_else(|| format!("Path is not an array: {path}"))
+}
+
+fn apply_array_action(state: &mut StateTree, action_type: &str, payload: &Value) -> Result<(), String> {
+    let path = payload_path(payload, action_type)?;
+    let array = array_at_path(state, path, action_type == "PUSH")?;
+
+    match action_type {
+        "PUSH" => match payload.get("values").and_then(Value::as_array) {
+            Some(values) => array.extend(values.iter().cloned().map(StateTree::from)),
+            None => array.push_back(payload.get("value").cloned().unwrap_or(Value::Null).into()),
+        },
+        "INSERT" => {
+            let index = payload_index(payload, "index", action_type)?;
+            if index > array.len() {
+                return Err(format!("INSERT index out of bounds: {index}"));
+            }
+            array.insert(index, payload.get("value").cloned().unwrap_or(Value::Null).into());
         }
+        "SPLICE" => {
+            let start = payload_index(payload, "start", action_type)?;
+            if start > array.len() {
+                return Err(format!("SPLICE start out of bounds: {start}"));
+            }
+            let delete_count = payload
+                .get("deleteCount")
+                .and_then(Value::as_u64)
+                .map(|count| count as usize)
+                .unwrap_or(array.len() - start);
+            let end = start.saturating_add(delete_count).min(array.len());
+            let mut tail = array.split_off(start);
+            let rest = tail.split_off(end - start);
+            if let Some(items) = payload.get("items").and_then(Value::as_array) {
+                array.extend(items.iter().cloned().map(StateTree::from));
+            }
+            array.append(rest);
+        }
+        "MOVE" => {
+            let from = payload_index(payload, "from", action_type)?;
+            let to = payload_index(payload, "to", action_type)?;
+            if from >= array.len() || to >= array.len() {
+                return Err(format!("MOVE index out of bounds: {from} -> {to}"));
+            }
+            let item = array.remove(from);
+            array.insert(to, item);
+        }
+        "REMOVE_WHERE" => {
+            let value = payload.get("value").ok_or("REMOVE_WHERE requires payload.value")?;
+            match payload.get("key").and_then(Value::as_str) {
+                Some(key) => array.retain(|item| item.child(key).is_none_or(|field| field != value)),
+                None => array.retain(|item| item != value),
+            }
+        }
+        other => return Err(format!("Unknown array action: {other}")),
+    }
+    Ok(())
+}
 
-        let object = current.as_object_mut().expect("object was just created");
-        current = object
-            .entry((*part).to_string())
-            .or_insert_with(|| Value::Object(Map::new()));
+fn collect_notifications(store: &mut Store) -> Vec<Notification> {
+    if let Some(document) = store.crdt.as_mut() {
+        if let Err(error) = document.reconcile(&store.state) {
+            warn(&format!("CRDT store could not record a commit: {error}"));
+        }
+    }
+    store.refresh_computed();
+    let mut notifications = collect_subscription_notifications(store);
+
+    if let Some(connection) = store.devtools.as_mut() {
+        notifications.extend(connection.bridge.take_messages().into_iter().map(|message| Notification {
+            callback: connection.send.clone(),
+            next: message.into(),
+            previous: StateTree::Null,
+            recipient: Recipient::Channel(Channel::Devtools),
+        }));
+    }
+    if let Some(connection) = store.persistence.as_mut() {
+        let now = js_sys::Date::now();
+        if let Some(record) = connection.tracker.poll(&store.state, now, false) {
+            notifications.push(persistence_write(connection, record));
+        }
+        if let Some(schedule) = connection.schedule.as_ref() {
+            if let Some(delay) = connection.tracker.take_trailing_delay(now) {
+                notifications.push(Notification {
+                    callback: schedule.clone(),
+                    next: StateTree::from(json_number(delay)),
+                    previous: StateTree::Null,
+                    recipient: Recipient::Channel(Channel::PersistenceSchedule),
+                });
+            }
+        }
+    }
+    if let Some(connection) = store.sync.as_mut() {
+        if let Some(message) = connection.replica.capture(&store.state) {
+            notifications.push(sync_send(connection, &message));
+        }
     }
+    notifications
+}
+
+/// Notifications for active subscriptions whose selection changed.
+fn collect_subscription_notifications(store: &mut Store) -> Vec<Notification> {
+    let Store {
+        state,
+        computed,
+        subscriptions,
+        ..
+    } = store;
+    subscriptions
+        .iter_mut()
+        .filter(|(_, subscription)| !subscription.paused)
+        .filter_map(|(subscription_id, subscription)| {
+            let next = subscription.selection.resolve(|segments| resolve_path(state, computed, segments));
+            let (next, previous) = take_selection_change(&mut subscription.last_value, Some(&next), &subscription.equality)?;
+            Some(Notification {
+                callback: subscription.callback.clone(),
+                next,
+                previous,
+                recipient: Recipient::Subscription(subscription_id.clone()),
+            })
+        })
+        .collect()
+}
+
+/// Brings a store's computed values up to date before they are read.
+fn settle_computed(store_id: &str) -> Result<(), JsValue> {
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        if let Some(store) = stores.get_mut(store_id) {
+            store.flush_lanes();
+            store.refresh_computed();
+        }
+        Ok::<_, JsValue>(())
+    })?;
+    notify_subscribers(Vec::new())
+}
 
-    if !current.is_object() {
-        *current = Value::Object(Map::new());
+/// Calls the JS compute functions whose inputs changed, with the stores
+/// released so they can select from them, and returns the notifications
+/// for subscriptions to the new values.
+fn evaluate_computed_functions() -> Result<Vec<Notification>, JsValue> {
+    let calls = STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let mut calls = Vec::new();
+        for (store_id, store) in stores.iter_mut() {
+            for (computed_id, computed) in store.computed.iter_mut() {
+                if let Some((callback, inputs)) = computed.take_pending_call() {
+                    calls.push((store_id.clone(), computed_id.clone(), callback, inputs));
+                }
+            }
+        }
+        Ok::<_, JsValue>(calls)
+    })?;
+    if calls.is_empty() {
+        return Ok(Vec::new());
     }
 
-    if let Some(object) = current.as_object_mut() {
-        object.insert(parts[parts.len() - 1].to_string(), value);
+    let results: Vec<_> = calls
+        .into_iter()
+        .map(|(store_id, computed_id, callback, inputs)| {
+            let values: Vec<Value> = inputs.iter().map(StateTree::to_value).collect();
+            let result = store_computed::call_function(&callback, &values);
+            (store_id, computed_id, inputs, result)
+        })
+        .collect();
+
+    STORES.with(|stores| {
+        let mut stores = stores.try_borrow_mut().map_err(|_| stores_busy())?;
+        let mut settled: Vec<String> = Vec::new();
+        for (store_id, computed_id, inputs, result) in results {
+            let Some(computed) = stores.get_mut(&store_id).and_then(|store| store.computed.get_mut(&computed_id)) else {
+                continue;
+            };
+            if let Err(error) = &result {
+                warn(&format!("Computed {computed_id} failed: {error}"));
+            }
+            if computed.settle(&inputs, result) && !settled.contains(&store_id) {
+                settled.push(store_id);
+            }
+        }
+        let mut notifications = Vec::new();
+        for store_id in settled {
+            if let Some(store) = stores.get_mut(&store_id) {
+                notifications.extend(collect_subscription_notifications(store));
+            }
+        }
+        Ok(notifications)
+    })
+}
+
+fn sync_send(connection: &SyncConnection, message: &SyncMessage) -> Notification {
+    Notification {
+        callback: connection.send.clone(),
+        next: serde_json::to_value(message).map(StateTree::from).unwrap_or_default(),
+        previous: StateTree::Null,
+        recipient: Recipient::Channel(Channel::Sync),
     }
 }
 
-fn delete_path(state: &mut Value, path: &str) {
-    let parts: Vec<&str> = path.split('.').filter(|part| !part.is_empty()).collect();
-    if parts.is_empty() {
-        *state = Value::Null;
-        return;
+fn persistence_write(connection: &PersistenceConnection, record: PersistenceRecord) -> Notification {
+    Notification {
+        callback: connection.write.clone(),
+        next: serde_json::to_value(record).map(StateTree::from).unwrap_or_default(),
+        previous: StateTree::Null,
+        recipient: Recipient::Channel(Channel::Persistence),
     }
+}
 
-    let mut current = state;
-    for part in &parts[..parts.len() - 1] {
-        current = match current {
-            Value::Object(object) => match object.get_mut(*part) {
-                Some(value) => value,
-                None => return,
-            },
-            _ => return,
-        };
+fn is_computed_path(segments: &[String]) -> bool {
+    segments.first().map(String::as_str) == Some(COMPUTED_PATH_ROOT)
+}
+
+fn resolve_path<'a>(
+    state: &'a StateTree,
+    computed: &'a HashMap<String, Computed>,
+    segments: &[String],
+) -> Option<&'a StateTree> {
+    if !is_computed_path(segments) {
+        return state.get(segments);
     }
+    let computed = computed.get(segments.get(1)?)?;
+    computed.value().get(&segments[2..])
+}
 
-    if let Value::Object(object) = current {
-        object.remove(parts[parts.len() - 1]);
+/// Compares the selected slice with the last delivered one under the
+/// subscription's equality.
+fn take_selection_change(
+    last_value: &mut StateTree,
+    next: Option<&StateTree>,
+    equality: &Equality,
+) -> Option<(StateTree, StateTree)> {
+    let next = next.unwrap_or(&StateTree::Null);
+    if equality.equal(next, last_value) {
+        return None;
     }
+    let previous = std::mem::replace(last_value, next.clone());
+    Some((next.clone(), previous))
 }
 
-fn collect_notifications(store: &Store) -> Vec<(String, js_sys::Function)> {
-    store
-        .subscriptions
-        .values()
-        .map(|subscription| (subscription.path.clone(), subscription.callback.clone()))
-        .collect()
+/// Error for a JS callback (a reducer inside a transaction, a compute
+/// function) that calls back into the stores while they are being updated.
+fn stores_busy() -> JsValue {
+    js_error(STORES_BUSY)
 }
 
-fn notify_subscribers(
-    state: &Value,
-    notifications: &[(String, js_sys::Function)]
-) -> Result<(), JsValue> {
-    for (path, callback) in notifications {
-        let selected = select_value(state, path).unwrap_or(&Value::Null);
-        let selected = to_js(selected)?;
-        callback
-            .call1(&JsValue::NULL, &selected)
-            .map_err(|error| js_error(&format!("Subscriber callback failed: {:?}", error)))?;
+const STORES_BUSY: &str = "Stores are busy: a callback running during a store update cannot call back into the store";
+
+/// Delivers `notifications` under the notification mode. Outside `sync`
+/// mode subscriber callbacks are queued and coalesced; devtools, persistence
+/// and sync channels are always delivered straight away.
+fn notify_subscribers(mut notifications: Vec<Notification>) -> Result<(), JsValue> {
+    notifications.extend(evaluate_computed_functions()?);
+    let (messages, notifications): (Vec<_>, Vec<_>) = notifications
+        .into_iter()
+        .partition(|notification| matches!(notification.recipient, Recipient::Channel(_)));
+    messages.into_iter().for_each(send_channel_message);
+
+    let (mode, scheduler) = NOTIFICATION_MODE.with(|current| current.borrow().clone());
+    if mode == NotificationMode::Sync {
+        return deliver_notifications(notifications);
     }
 
+    let mut needs_flush = false;
+    PENDING_NOTIFICATIONS.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        for notification in notifications {
+            if let Recipient::Subscription(subscription_id) = notification.recipient {
+                needs_flush |= pending.push(subscription_id, notification.callback, notification.next, notification.previous);
+            }
+        }
+    });
+    if let (true, Some(scheduler)) = (needs_flush, scheduler) {
+        let flush = Closure::once_into_js(|| flush_notifications().map(|_| ()));
+        if let Err(error) = scheduler.call1(&JsValue::NULL, &flush) {
+            PENDING_NOTIFICATIONS.with(|pending| pending.borrow_mut().unschedule());
+            return Err(error);
+        }
+    }
     Ok(())
 }
 
+/// Hands a DevTools, persistence or sync message to its JS callback. A
+/// failure is logged for that channel; the commit that produced the
+/// message has already landed, so the caller is not failed.
+fn send_channel_message(message: Notification) {
+    let Recipient::Channel(channel) = message.recipient else {
+        return;
+    };
+    let sent = to_js(&message.next).and_then(|payload| message.callback.call1(&JsValue::NULL, &payload));
+    if let Err(error) = sent {
+        warn(&format!("{} failed: {}", channel.describe(), describe_js_error(&error)));
+    }
+}
+
+/// Invokes every callback, even after one throws. Failures go to the error
+/// hook (or the console) and are then handled by the delivery policy.
+fn deliver_notifications(notifications: Vec<Notification>) -> Result<(), JsValue> {
+    let mut failures = Vec::new();
+    for notification in notifications {
+        let Recipient::Subscription(subscription_id) = &notification.recipient else {
+            send_channel_message(notification);
+            continue;
+        };
+        let delivered = to_js(&notification.next).and_then(|next| {
+            let previous = to_js(&notification.previous)?;
+            notification.callback.call2(&JsValue::NULL, &next, &previous)
+        });
+        match delivered {
+            Ok(_) => DELIVERY.with(|delivery| delivery.borrow_mut().record_success(subscription_id)),
+            Err(error) => {
+                let failure = DELIVERY.with(|delivery| {
+                    delivery
+                        .borrow_mut()
+                        .record_failure(subscription_id, describe_js_error(&error))
+                });
+                if failure.unsubscribed {
+                    STORES.with(|stores| {
+                        for store in stores.try_borrow_mut().map_err(|_| stores_busy())?.values_mut() {
+                            store.subscriptions.remove(subscription_id);
+                        }
+                        Ok::<(), JsValue>(())
+                    })?;
+                }
+                report_subscriber_error(&error, &failure);
+                failures.push(failure);
+            }
+        }
+    }
+
+    match DELIVERY.with(|delivery| delivery.borrow().policy()) {
+        ErrorPolicy::Rethrow => aggregate_error(&failures).map_or(Ok(()), |message| Err(js_error(&message))),
+        ErrorPolicy::Continue | ErrorPolicy::Unsubscribe { .. } => Ok(()),
+    }
+}
+
+fn report_subscriber_error(error: &JsValue, failure: &DeliveryFailure) {
+    let Some(hook) = SUBSCRIBER_ERROR_HOOK.with(|hook| hook.borrow().clone()) else {
+        if DELIVERY.with(|delivery| delivery.borrow().policy()) != ErrorPolicy::Rethrow {
+            warn(&format!("Subscriber callback failed: {}", failure.message));
+        }
+        return;
+    };
+    let info = serde_json::json!({
+        "subscriptionId": failure.subscription_id,
+        "message": failure.message,
+        "failures": failure.failures,
+        "unsubscribed": failure.unsubscribed,
+    });
+    let reported = to_js(&info).and_then(|info| hook.call2(&JsValue::NULL, error, &info));
+    if let Err(hook_error) = reported {
+        warn(&format!(
+            "Subscriber error hook failed: {} (while reporting: {})",
+            describe_js_error(&hook_error),
+            failure.message
+        ));
+    }
+}
+
+fn describe_js_error(error: &JsValue) -> String {
+    error
+        .dyn_ref::<js_sys::Error>()
+        .map(|error| String::from(error.message()))
+        .or_else(|| error.as_string())
+        .unwrap_or_else(|| format!("{error:?}"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::json;
 
+    fn apply_action(current: &Value, action_type: &str, payload: Value) -> Result<Value, String> {
+        let current = StateTree::from(current.clone());
+        super::apply_action(&current, action_type, payload, &ActionReducers::default()).map(|next| next.to_value())
+    }
+
     #[test]
     fn merge_replaces_only_payload_keys() {
         let current = json!({ "count": 1, "name": "orders" });
-        let next = merge_state(&current, json!({ "count": 2 }));
+        let next = apply_action(&current, "MERGE", json!({ "count": 2 })).unwrap();
         assert_eq!(next["count"], 2);
         assert_eq!(next["name"], "orders");
     }
@@ -907,13 +3480,96 @@ mod tests {
         assert_eq!(next["user"]["active"], true);
     }
 
+    #[test]
+    fn patch_is_applied_atomically() {
+        let current = json!({ "count": 1, "items": ["a"] });
+        let next = apply_action(&current, "PATCH", json!([
+            { "op": "add", "path": "/items/-", "value": "b" },
+            { "op": "replace", "path": "/count", "value": 2 }
+        ])).unwrap();
+        assert_eq!(next, json!({ "count": 2, "items": ["a", "b"] }));
+
+        let error = apply_action(&current, "PATCH", json!([
+            { "op": "replace", "path": "/count", "value": 3 },
+            { "op": "test", "path": "/count", "value": 1 }
+        ])).unwrap_err();
+        assert!(error.contains("test failed"));
+        assert_eq!(current["count"], 1);
+    }
+
+    #[test]
+    fn deep_merge_follows_merge_patch_semantics() {
+        let current = json!({ "user": { "name": "A", "age": 7, "tags": ["x"] }, "count": 1 });
+        let next = apply_action(&current, "DEEP_MERGE", json!({
+            "user": { "name": "B", "age": null, "tags": ["y"] }
+        })).unwrap();
+        assert_eq!(next, json!({ "user": { "name": "B", "tags": ["y"] }, "count": 1 }));
+    }
+
+    #[test]
+    fn deep_merge_supports_array_strategies() {
+        let deep_merge = |options: Value, patch: Value| {
+            let current = StateTree::from(json!({ "tags": ["x"], "todos": [{ "id": 1, "done": false, "title": "A" }] }));
+            let mut reducers = ActionReducers::default();
+            reducers.set_array_merge(ArrayMergeStrategy::parse(&options).unwrap());
+            super::apply_action(&current, "DEEP_MERGE", patch, &reducers).unwrap().to_value()
+        };
+
+        let next = deep_merge(json!({ "arrayStrategy": "concat" }), json!({ "tags": ["y", { "a": 1, "b": null }] }));
+        assert_eq!(next["tags"], json!(["x", "y", { "a": 1 }]));
+
+        let next = deep_merge(
+            json!({ "arrayStrategy": "mergeById" }),
+            json!({ "todos": [{ "id": 1, "done": true }, { "id": 2, "title": "B", "note": null }] }),
+        );
+        assert_eq!(next["todos"], json!([
+            { "id": 1, "done": true, "title": "A" },
+            { "id": 2, "title": "B" }
+        ]));
+
+        let next = apply_action(&json!({ "tags": ["x"] }), "DEEP_MERGE", json!({ "arrayStrategy": "concat", "tags": ["y"] })).unwrap();
+        assert_eq!(next, json!({ "arrayStrategy": "concat", "tags": ["y"] }));
+        assert!(ArrayMergeStrategy::parse(&json!({ "arrayStrategy": "zip" })).is_err());
+    }
+
+    #[test]
+    fn update_and_delete_honor_array_indices() {
+        let current = json!({ "todos": [{ "title": "A" }, { "title": "B" }] });
+        let next = apply_action(&current, "UPDATE", json!({ "path": "todos.1.title", "value": "C" })).unwrap();
+        assert_eq!(next["todos"], json!([{ "title": "A" }, { "title": "C" }]));
+
+        let next = apply_action(&next, "DELETE", json!("todos.0")).unwrap();
+        assert_eq!(next["todos"], json!([{ "title": "C" }]));
+
+        assert!(apply_action(&current, "UPDATE", json!({ "path": "todos.5", "value": 1 })).is_err());
+    }
+
+    #[test]
+    fn array_actions_mutate_list_at_path() {
+        let current = json!({ "list": { "todos": [1, 2] } });
+        let next = apply_action(&current, "BATCH", json!([
+            { "actionType": "PUSH", "payload": { "path": "list.todos", "values": [3, 4] } },
+            { "actionType": "INSERT", "payload": { "path": "list.todos", "index": 0, "value": 0 } },
+            { "actionType": "SPLICE", "payload": { "path": "list.todos", "start": 1, "deleteCount": 2, "items": [9] } },
+            { "actionType": "MOVE", "payload": { "path": "list.todos", "from": 0, "to": 3 } },
+            { "actionType": "PUSH", "payload": { "path": "orders", "value": { "id": 1 } } }
+        ])).unwrap();
+        assert_eq!(next["list"]["todos"], json!([9, 3, 4, 0]));
+        assert_eq!(next["orders"], json!([{ "id": 1 }]));
+
+        let current = json!({ "orders": [{ "id": 1 }, { "id": 2 }, { "id": 1 }] });
+        let next = apply_action(&current, "REMOVE_WHERE", json!({ "path": "orders", "key": "id", "value": 1 })).unwrap();
+        assert_eq!(next["orders"], json!([{ "id": 2 }]));
+    }
+
     #[test]
     fn counter_batch_updates_count_and_keeps_last_ten_history_entries() {
-        let mut state = json!({
+        let mut state = StateTree::from(json!({
             "count": 0,
             "history": []
-        });
+        }));
         apply_counter_steps(&mut state, 1, 12, "bench", "INCREMENT", 100.0).unwrap();
+        let state = state.to_value();
 
         assert_eq!(state["count"], 12);
         let history = state["history"].as_array().unwrap();
@@ -932,8 +3588,9 @@ mod tests {
         store.flush_fast_count();
 
         assert_eq!(next, 11);
-        assert_eq!(store.state["count"], 11);
-        assert_eq!(store.state["history"].as_array().unwrap().len(), 1);
+        let state = store.state.to_value();
+        assert_eq!(state["count"], 11);
+        assert_eq!(state["history"].as_array().unwrap().len(), 1);
     }
 
     #[test]
@@ -944,16 +3601,135 @@ mod tests {
         }));
         let next = store.apply_fast_count_delta(3, 2).unwrap();
         assert_eq!(next, 7);
-        assert_eq!(store.state["count"], 1);
+        assert_eq!(store.state, json!({ "count": 1, "name": "counter" }));
 
         store.flush_fast_count();
-        assert_eq!(store.state["count"], 7);
-        assert_eq!(store.state["name"], "counter");
+        assert_eq!(store.state, json!({ "count": 7, "name": "counter" }));
+    }
+
+    #[test]
+    fn reentrant_export_fails_instead_of_panicking() {
+        STORES.with(|stores| {
+            let _update = stores.borrow_mut();
+            assert_eq!(
+                export_runtime_stores(&RuntimeFilter::default()).unwrap_err(),
+                STORES_BUSY
+            );
+        });
+        assert!(export_runtime_stores(&RuntimeFilter::default()).is_ok());
+    }
+
+    #[test]
+    fn count_path_lane_survives_flush() {
+        let mut store = Store::new(json!({ "count": 0 }));
+        let handle = insert_lane(&mut store, "main", "count", vec!["count".to_string()], LaneValue::Int(0));
+        LANES.with(|lanes| lanes.borrow_mut().with_lane(handle, |lane| lane.add(5.0, 1).unwrap()));
+
+        store.flush_lanes();
+        assert_eq!(store.state, json!({ "count": 5 }));
+        assert_eq!(store.fast_count, Some(5));
+        LANES.with(|lanes| lanes.borrow_mut().remove(handle));
     }
 
     #[test]
     fn select_supports_object_and_array_paths() {
         let state = json!({ "users": [{ "name": "A" }, { "name": "B" }] });
-        assert_eq!(select_value(&state, "users.1.name").unwrap(), "B");
+        assert_eq!(select_value(&state, &parse_path("users.1.name").unwrap()).unwrap(), "B");
+    }
+
+    #[test]
+    fn path_parser_accepts_pointer_brackets_and_escapes() {
+        assert_eq!(parse_path("items[3].title").unwrap(), vec!["items", "3", "title"]);
+        assert_eq!(parse_path("/items/3/title").unwrap(), vec!["items", "3", "title"]);
+        assert_eq!(parse_path(r#"users["a.b@x.com"].name"#).unwrap(), vec!["users", "a.b@x.com", "name"]);
+        assert_eq!(parse_path(r"config.v1\.2").unwrap(), vec!["config", "v1.2"]);
+        assert_eq!(parse_path("[0][1]").unwrap(), vec!["0", "1"]);
+        assert!(parse_path("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_paths_are_rejected() {
+        for path in ["a..b", ".a", "a.", "a[", "a[x]", "a]", "a[0]b", "a.[0]", r"a\", r#"a["b]"#] {
+            assert!(parse_path(path).is_err(), "{path} should be rejected");
+        }
+    }
+
+    #[test]
+    fn update_and_delete_reach_keys_containing_dots() {
+        let current = json!({ "emails": { "a.b@x.com": { "verified": false } } });
+        let next = apply_action(&current, "UPDATE", json!({
+            "path": r#"emails["a.b@x.com"].verified"#,
+            "value": true
+        })).unwrap();
+        assert_eq!(next["emails"]["a.b@x.com"]["verified"], true);
+
+        let next = apply_action(&next, "DELETE", json!("/emails/a.b@x.com")).unwrap();
+        assert_eq!(next["emails"], json!({}));
+    }
+
+    #[test]
+    fn transaction_stages_across_stores_without_mutating_them() {
+        let mut stores = HashMap::new();
+        stores.insert("cart".to_string(), Store::new(json!({ "items": [1] })));
+        stores.insert("inventory".to_string(), Store::new(json!({ "stock": 5 })));
+        let action = |store_id: &str, action_type: &str, payload: Value| StagedAction {
+            store_id: store_id.to_string(),
+            action_type: action_type.to_string(),
+            payload,
+        };
+
+        let staged = stage_transaction(&stores, &[
+            action("cart", "PUSH", json!({ "path": "items", "value": 2 })),
+            action("inventory", "MERGE", json!({ "stock": 4 })),
+            action("cart", "UPDATE", json!({ "path": "paid", "value": true })),
+        ])
+        .unwrap();
+        assert_eq!(staged, vec![
+            ("cart".to_string(), json!({ "items": [1, 2], "paid": true }).into()),
+            ("inventory".to_string(), json!({ "stock": 4 }).into()),
+        ]);
+
+        let error = stage_transaction(&stores, &[
+            action("cart", "PUSH", json!({ "path": "items", "value": 3 })),
+            action("inventory", "PUSH", json!({ "path": "stock", "value": 1 })),
+        ])
+        .unwrap_err();
+        assert!(error.starts_with("Transaction action 1 (PUSH) on inventory failed"));
+        assert_eq!(stores["cart"].state, json!({ "items": [1] }));
+    }
+
+    #[test]
+    fn selection_change_fires_only_when_selected_slice_changes() {
+        let mut last_value = StateTree::from(json!("A"));
+        let segments = parse_path("user.name").unwrap();
+        let state = StateTree::from(json!({ "user": { "name": "A" }, "count": 1 }));
+        assert!(take_selection_change(&mut last_value, state.get(&segments), &Equality::Deep).is_none());
+
+        let state = StateTree::from(json!({ "user": { "name": "B" }, "count": 1 }));
+        let (next, previous) = take_selection_change(&mut last_value, state.get(&segments), &Equality::Deep).unwrap();
+        assert_eq!(next, json!("B"));
+        assert_eq!(previous, json!("A"));
+        assert_eq!(last_value, json!("B"));
+
+        let state = StateTree::from(json!({ "user": { "name": "B" }, "count": 2 }));
+        assert!(take_selection_change(&mut last_value, state.get(&segments), &Equality::Deep).is_none());
+    }
+
+    #[test]
+    fn unknown_validation_mode_is_a_registration_error() {
+        let mut schema: StoreSchema = serde_json::from_value(json!({
+            "storeId": "main",
+            "schemaId": "app",
+            "schemaVersion": "1.0.0",
+            "jsonSchema": { "type": "object" },
+            "validationMode": "strict"
+        }))
+        .unwrap();
+        assert_eq!(
+            schema.compile().unwrap_err(),
+            "Unknown validationMode: strict (expected \"reject\" or \"warn\")"
+        );
+        schema.validation_mode = Some("warn".to_string());
+        assert!(schema.compile().is_ok());
     }
 }
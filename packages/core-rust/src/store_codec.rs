@@ -0,0 +1,91 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::store_tree::StateTree;
+
+pub(crate) const STORE_EXPORT_VERSION: u32 = 1;
+
+/// Binary encodings accepted by the `*_bytes` store APIs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BinaryFormat {
+    MessagePack,
+    Cbor,
+}
+
+impl BinaryFormat {
+    /// `"msgpack"` (the default) or `"cbor"`.
+    pub(crate) fn parse(format: Option<&str>) -> Result<Self, String> {
+        match format.unwrap_or("msgpack") {
+            "msgpack" | "messagepack" => Ok(Self::MessagePack),
+            "cbor" => Ok(Self::Cbor),
+            other => Err(format!("Unknown binary format: {other}")),
+        }
+    }
+
+    /// Structs encode as maps so JS decoders see field names.
+    pub(crate) fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(|error| error.to_string()),
+            Self::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|error| error.to_string())?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub(crate) fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Self::MessagePack => rmp_serde::from_slice(bytes).map_err(|error| error.to_string()),
+            Self::Cbor => ciborium::from_reader(bytes).map_err(|error| error.to_string()),
+        }
+    }
+}
+
+/// Self-describing store dump produced by `export_store_bytes`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StoreExport {
+    pub(crate) version: u32,
+    #[serde(default)]
+    pub(crate) store_id: String,
+    pub(crate) state: StateTree,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn both_formats_round_trip_state() {
+        let state = json!({ "count": -3, "ratio": 0.5, "big": u64::MAX, "todos": [{ "done": true, "text": "a" }], "none": null });
+        for format in [BinaryFormat::MessagePack, BinaryFormat::Cbor] {
+            let bytes = format.encode(&StateTree::from(state.clone())).unwrap();
+            let tree: StateTree = format.decode(&bytes).unwrap();
+            assert_eq!(tree, state);
+            let value: Value = format.decode(&bytes).unwrap();
+            assert_eq!(value, state);
+        }
+    }
+
+    #[test]
+    fn export_envelope_round_trips() {
+        let export = StoreExport {
+            version: STORE_EXPORT_VERSION,
+            store_id: "main".to_string(),
+            state: json!({ "count": 1 }).into(),
+        };
+        let bytes = BinaryFormat::Cbor.encode(&export).unwrap();
+        let decoded: StoreExport = BinaryFormat::Cbor.decode(&bytes).unwrap();
+        assert_eq!(decoded.store_id, "main");
+        assert_eq!(decoded.state, json!({ "count": 1 }));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_formats() {
+        assert_eq!(BinaryFormat::parse(None), Ok(BinaryFormat::MessagePack));
+        assert_eq!(BinaryFormat::parse(Some("cbor")), Ok(BinaryFormat::Cbor));
+        assert_eq!(BinaryFormat::parse(Some("bson")), Err("Unknown binary format: bson".to_string()));
+    }
+}
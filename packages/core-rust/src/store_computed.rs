@@ -0,0 +1,279 @@
+use serde_json::{Map, Number, Value};
+
+use crate::store::parse_path;
+use crate::store_tree::StateTree;
+use crate::{from_js, json_number, to_js};
+
+/// Root segment under which computed values are selectable and subscribable,
+/// e.g. `$computed.cartTotal`.
+pub(crate) const COMPUTED_PATH_ROOT: &str = "$computed";
+
+enum Expression {
+    Sum { field: Option<String> },
+    Count { predicate: Option<Predicate> },
+    Filter { predicate: Predicate },
+    Pick { fields: Vec<String> },
+    Function(js_sys::Function),
+}
+
+struct Predicate {
+    key: Option<String>,
+    value: Value,
+}
+
+impl Predicate {
+    fn parse(spec: Option<&Value>) -> Option<Self> {
+        let spec = spec?;
+        Some(Self {
+            key: spec.get("key").and_then(Value::as_str).map(ToString::to_string),
+            value: spec.get("value").cloned().unwrap_or(Value::Null),
+        })
+    }
+
+    fn matches(&self, item: &Value) -> bool {
+        match &self.key {
+            Some(key) => item.get(key) == Some(&self.value),
+            None => *item == self.value,
+        }
+    }
+}
+
+/// A derived value recomputed from input paths only when they change.
+pub(crate) struct Computed {
+    inputs: Vec<Vec<String>>,
+    expression: Expression,
+    last_inputs: Option<Vec<StateTree>>,
+    value: StateTree,
+    /// Why the last evaluation failed; `value` keeps the last good result.
+    error: Option<String>,
+    /// Inputs a JS function has not been called with yet.
+    pending: Option<Vec<StateTree>>,
+}
+
+impl Computed {
+    /// Builds a computed value from `{ inputs, op, field?, fields?, where? }`,
+    /// or from a JS function called with the input values as arguments.
+    pub(crate) fn from_spec(spec: &Value, callback: Option<js_sys::Function>) -> Result<Self, String> {
+        let inputs = spec
+            .get("inputs")
+            .and_then(Value::as_array)
+            .ok_or("Computed spec requires an inputs array")?
+            .iter()
+            .map(|input| input.as_str().ok_or("Computed inputs must be paths").map_err(ToString::to_string).and_then(parse_path))
+            .collect::<Result<Vec<_>, String>>()?;
+        let field = spec.get("field").and_then(Value::as_str).map(ToString::to_string);
+
+        let expression = match (callback, spec.get("op").and_then(Value::as_str)) {
+            (Some(callback), _) => Expression::Function(callback),
+            (None, Some("sum")) => Expression::Sum { field },
+            (None, Some("count")) => Expression::Count {
+                predicate: Predicate::parse(spec.get("where")),
+            },
+            (None, Some("filter")) => Expression::Filter {
+                predicate: Predicate::parse(spec.get("where")).ok_or("filter requires where")?,
+            },
+            (None, Some("pick")) => Expression::Pick {
+                fields: match spec.get("fields").and_then(Value::as_array) {
+                    Some(fields) => fields.iter().filter_map(Value::as_str).map(ToString::to_string).collect(),
+                    None => vec![field.ok_or("pick requires field or fields")?],
+                },
+            },
+            (None, Some(other)) => return Err(format!("Unknown computed op: {other}")),
+            (None, None) => return Err("Computed spec requires op or a function".to_string()),
+        };
+
+        Ok(Self {
+            inputs,
+            expression,
+            last_inputs: None,
+            value: StateTree::Null,
+            error: None,
+            pending: None,
+        })
+    }
+
+    pub(crate) fn value(&self) -> &StateTree {
+        &self.value
+    }
+
+    pub(crate) fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Recomputes when any input changed since the last run; returns whether
+    /// the computed value was re-evaluated. Inputs still shared with the last
+    /// run compare by pointer.
+    ///
+    /// A JS function is not called here, since the caller may be holding the
+    /// stores; its inputs wait in `take_pending_call` instead.
+    pub(crate) fn refresh(&mut self, state: &StateTree) -> Result<bool, String> {
+        let inputs: Vec<StateTree> = self
+            .inputs
+            .iter()
+            .map(|segments| state.get(segments).cloned().unwrap_or_default())
+            .collect();
+        if self.last_inputs.as_ref() == Some(&inputs) {
+            return Ok(false);
+        }
+        if let Expression::Function(_) = self.expression {
+            self.last_inputs = Some(inputs.clone());
+            self.pending = Some(inputs);
+            return Ok(false);
+        }
+
+        let values: Vec<Value> = inputs.iter().map(StateTree::to_value).collect();
+        self.last_inputs = Some(inputs);
+        match self.evaluate(&values) {
+            Ok(value) => {
+                self.value = value.into();
+                self.error = None;
+                Ok(true)
+            }
+            Err(error) => {
+                self.error = Some(error.clone());
+                Err(error)
+            }
+        }
+    }
+
+    /// The JS function and the inputs it still has to be called with.
+    pub(crate) fn take_pending_call(&mut self) -> Option<(js_sys::Function, Vec<StateTree>)> {
+        let Expression::Function(callback) = &self.expression else {
+            return None;
+        };
+        Some((callback.clone(), self.pending.take()?))
+    }
+
+    /// Stores the result of a call made for `inputs`; returns `false` when
+    /// the inputs changed again meanwhile and the result is stale.
+    pub(crate) fn settle(&mut self, inputs: &[StateTree], result: Result<Value, String>) -> bool {
+        if self.last_inputs.as_deref() != Some(inputs) {
+            return false;
+        }
+        match result {
+            Ok(value) => {
+                self.value = value.into();
+                self.error = None;
+            }
+            Err(error) => self.error = Some(error),
+        }
+        true
+    }
+
+    fn evaluate(&self, inputs: &[Value]) -> Result<Value, String> {
+        let items = || {
+            inputs.iter().flat_map(|input| match input {
+                Value::Array(items) => items.iter().collect::<Vec<_>>(),
+                Value::Null => Vec::new(),
+                other => vec![other],
+            })
+        };
+
+        Ok(match &self.expression {
+            Expression::Sum { field } => {
+                let numbers: Vec<&Number> = items()
+                    .filter_map(|item| match field {
+                        Some(field) => item.get(field)?.as_number(),
+                        None => item.as_number(),
+                    })
+                    .collect();
+                if numbers.iter().all(|number| number.is_i64()) {
+                    let total = numbers
+                        .iter()
+                        .try_fold(0_i64, |total, number| total.checked_add(number.as_i64()?))
+                        .ok_or("sum overflows a 64-bit integer")?;
+                    Value::from(total)
+                } else {
+                    json_number(numbers.iter().filter_map(|number| number.as_f64()).sum())
+                }
+            }
+            Expression::Count { predicate } => {
+                let count = items()
+                    .filter(|item| predicate.as_ref().is_none_or(|predicate| predicate.matches(item)))
+                    .count();
+                Value::from(count)
+            }
+            Expression::Filter { predicate } => {
+                Value::Array(items().filter(|item| predicate.matches(item)).cloned().collect())
+            }
+            Expression::Pick { fields } => Value::Array(
+                items()
+                    .map(|item| match fields.as_slice() {
+                        [field] => item.get(field).cloned().unwrap_or(Value::Null),
+                        fields => Value::Object(
+                            fields
+                                .iter()
+                                .map(|field| (field.clone(), item.get(field).cloned().unwrap_or(Value::Null)))
+                                .collect::<Map<String, Value>>(),
+                        ),
+                    })
+                    .collect(),
+            ),
+            Expression::Function(callback) => call_function(callback, inputs)?,
+        })
+    }
+}
+
+/// Calls a JS compute function with the input values as arguments.
+pub(crate) fn call_function(callback: &js_sys::Function, inputs: &[Value]) -> Result<Value, String> {
+    let args = js_sys::Array::new();
+    for input in inputs {
+        args.push(&to_js(input).map_err(|error| format!("{:?}", error))?);
+    }
+    let result = callback
+        .apply(&wasm_bindgen::JsValue::NULL, &args)
+        .map_err(|error| format!("Computed function failed: {:?}", error))?;
+    from_js(result).map_err(|error| format!("{:?}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn computed(spec: Value) -> Computed {
+        Computed::from_spec(&spec, None).unwrap()
+    }
+
+    #[test]
+    fn declarative_ops_derive_values_from_inputs() {
+        let state = StateTree::from(json!({
+            "cart": { "items": [{ "id": 1, "price": 2.5 }, { "id": 2, "price": 4 }] },
+            "todos": [{ "title": "A", "done": true }, { "title": "B", "done": false }]
+        }));
+
+        let mut total = computed(json!({ "inputs": ["cart.items"], "op": "sum", "field": "price" }));
+        total.refresh(&state).unwrap();
+        assert_eq!(total.value(), &json!(6.5));
+
+        let mut open = computed(json!({ "inputs": ["todos"], "op": "count", "where": { "key": "done", "value": false } }));
+        open.refresh(&state).unwrap();
+        assert_eq!(open.value(), &json!(1));
+
+        let mut done = computed(json!({ "inputs": ["todos"], "op": "filter", "where": { "key": "done", "value": true } }));
+        done.refresh(&state).unwrap();
+        assert_eq!(done.value(), &json!([{ "title": "A", "done": true }]));
+
+        let mut titles = computed(json!({ "inputs": ["todos"], "op": "pick", "field": "title" }));
+        titles.refresh(&state).unwrap();
+        assert_eq!(titles.value(), &json!(["A", "B"]));
+    }
+
+    #[test]
+    fn computed_only_reevaluates_when_inputs_change() {
+        let mut total = computed(json!({ "inputs": ["a", "b"], "op": "sum" }));
+        assert!(total.refresh(&StateTree::from(json!({ "a": 1, "b": 2, "other": 0 }))).unwrap());
+        assert!(!total.refresh(&StateTree::from(json!({ "a": 1, "b": 2, "other": 5 }))).unwrap());
+        assert!(total.refresh(&StateTree::from(json!({ "a": 3, "b": 2, "other": 5 }))).unwrap());
+        assert_eq!(total.value(), &json!(5));
+    }
+
+    #[test]
+    fn integer_sums_stay_exact() {
+        let mut total = computed(json!({ "inputs": ["a", "b"], "op": "sum" }));
+        total.refresh(&StateTree::from(json!({ "a": 9_007_199_254_740_993_i64, "b": 2 }))).unwrap();
+        assert_eq!(total.value(), &json!(9_007_199_254_740_995_i64));
+        assert!(total.refresh(&StateTree::from(json!({ "a": i64::MAX, "b": 1 }))).is_err());
+        assert_eq!(total.error(), Some("sum overflows a 64-bit integer"));
+    }
+}
@@ -0,0 +1,965 @@
+use imbl::{OrdMap, Vector};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::store_tree::StateTree;
+
+pub(crate) const CRDT_UPDATE_VERSION: u32 = 2;
+
+/// Lamport counter and replica id of the operation that created something;
+/// `2` numbers the nodes a single operation creates.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub(crate) struct Id(u64, String, u32);
+
+/// Addresses a map entry by key or a sequence element by the id it was
+/// inserted with, so paths stay valid while concurrent edits shift indices.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Step {
+    Key(String),
+    Elem(Id),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub(crate) enum Op {
+    /// Last-writer-wins write of a map entry or sequence element.
+    Set { id: Id, path: Vec<Step>, value: Value },
+    /// Last-writer-wins delete of a map entry, or a sequence tombstone.
+    Remove { id: Id, path: Vec<Step> },
+    /// RGA insert into the sequence at `path`, right of `after`.
+    Insert {
+        id: Id,
+        path: Vec<Step>,
+        after: Option<Id>,
+        value: Value,
+    },
+    /// A replica's running increment/decrement totals for a counter.
+    Counter {
+        id: Id,
+        path: Vec<Step>,
+        replica: String,
+        increments: i64,
+        decrements: i64,
+    },
+}
+
+impl Op {
+    fn id(&self) -> &Id {
+        match self {
+            Op::Set { id, .. } | Op::Remove { id, .. } | Op::Insert { id, .. } | Op::Counter { id, .. } => id,
+        }
+    }
+}
+
+/// Binary update message. Incremental updates carry operations a peer has
+/// not necessarily seen yet; a state update carries the whole document, so a
+/// joining peer does not replay history. Merging is idempotent and
+/// order-independent.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CrdtUpdate {
+    pub(crate) version: u32,
+    #[serde(default)]
+    pub(crate) ops: Vec<Op>,
+    /// Sender and the counter of the last operation it sent before these,
+    /// so receivers know the counters in between were never its own.
+    #[serde(default)]
+    pub(crate) origin: Option<(String, u64)>,
+    #[serde(default)]
+    pub(crate) snapshot: Option<Snapshot>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct Snapshot {
+    clock: u64,
+    root: Node,
+    seen: Seen,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum Node {
+    Leaf(Value),
+    Map(BTreeMap<String, Entry>),
+    Seq(Vec<Element>),
+    Counter(BTreeMap<String, (i64, i64)>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    id: Id,
+    /// `None` once removed; the tombstone keeps older writes from reviving it.
+    node: Option<Node>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Element {
+    id: Id,
+    value_id: Id,
+    /// The element it was inserted right of, for merging state updates.
+    after: Option<Id>,
+    node: Node,
+    deleted: bool,
+}
+
+enum Target<'a> {
+    Found(&'a mut Node),
+    /// Not delivered yet; the operation is retried after later merges.
+    Pending,
+    /// Removed or replaced by a newer write; the operation no longer applies.
+    Gone,
+}
+
+enum Outcome {
+    Pending,
+    Applied(Option<Patch>),
+}
+
+/// What an applied operation changed in the plain state, relative to the
+/// container at `parent`.
+enum Patch {
+    Write { parent: Vec<Step>, slot: Slot, value: StateTree },
+    Delete { parent: Vec<Step>, slot: Slot },
+    Insert { parent: Vec<Step>, index: usize, value: StateTree },
+}
+
+enum Slot {
+    Key(String),
+    Index(usize),
+}
+
+/// Applied operation ids as runs of Lamport counters per replica. Counters a
+/// replica skipped are reported by its updates, so its runs join up once
+/// everything it sent has arrived.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Seen(BTreeMap<String, BTreeMap<u64, u64>>);
+
+impl Seen {
+    fn contains(&self, id: &Id) -> bool {
+        self.0
+            .get(&id.1)
+            .and_then(|runs| runs.range(..=id.0).next_back())
+            .is_some_and(|(_, end)| id.0 <= *end)
+    }
+
+    /// Marks counters `start..=end` of `replica`; an empty range is a no-op.
+    fn insert(&mut self, replica: &str, start: u64, end: u64) {
+        if start > end {
+            return;
+        }
+        let runs = self.0.entry(replica.to_string()).or_default();
+        let (mut start, mut end) = (start, end);
+        if let Some((&left_start, &left_end)) = runs.range(..=start).next_back() {
+            if left_end.saturating_add(1) >= start {
+                start = left_start;
+                end = end.max(left_end);
+            }
+        }
+        let joined: Vec<(u64, u64)> = runs
+            .range(start..=end.saturating_add(1))
+            .map(|(run_start, run_end)| (*run_start, *run_end))
+            .collect();
+        for (run_start, run_end) in joined {
+            runs.remove(&run_start);
+            end = end.max(run_end);
+        }
+        runs.insert(start, end);
+    }
+
+    fn merge(&mut self, other: &Seen) {
+        for (replica, runs) in &other.0 {
+            for (start, end) in runs {
+                self.insert(replica, *start, *end);
+            }
+        }
+    }
+}
+
+/// CRDT replica of a store's state. Local commits are reconciled into
+/// operations; `select` keeps reading the materialized plain state, which
+/// merges patch along the paths they touch.
+#[derive(Clone)]
+pub(crate) struct CrdtDocument {
+    replica: String,
+    clock: u64,
+    counters: Vec<Vec<String>>,
+    root: Node,
+    seen: Seen,
+    /// Counter of the newest local operation.
+    last_local: u64,
+    /// Counter of the newest local operation handed out by `take_update`.
+    sent_through: u64,
+    pending: Vec<Op>,
+    outbox: Vec<Op>,
+    materialized: StateTree,
+}
+
+impl CrdtDocument {
+    /// `counters` lists key paths whose integer values merge as counters.
+    pub(crate) fn new(replica: String, counters: Vec<Vec<String>>, state: &StateTree) -> Result<Self, String> {
+        let mut document = Self {
+            replica,
+            clock: 0,
+            counters,
+            root: Node::Map(BTreeMap::new()),
+            seen: Seen::default(),
+            last_local: 0,
+            sent_through: 0,
+            pending: Vec::new(),
+            outbox: Vec::new(),
+            materialized: StateTree::object(),
+        };
+        document.reconcile(state)?;
+        Ok(document)
+    }
+
+    pub(crate) fn state(&self) -> &StateTree {
+        &self.materialized
+    }
+
+    /// Turns the difference between the document and `target` into
+    /// operations, applies them and queues them for the next update. Only
+    /// subtrees that differ from the materialized state are walked.
+    pub(crate) fn reconcile(&mut self, target: &StateTree) -> Result<(), String> {
+        if *target == self.materialized {
+            return Ok(());
+        }
+        let (Node::Map(entries), StateTree::Object(old), StateTree::Object(object)) = (&self.root, &self.materialized, target)
+        else {
+            return Err("CRDT stores require an object state".to_string());
+        };
+
+        let mut generator = Generator {
+            replica: &self.replica,
+            clock: &mut self.clock,
+            ops: Vec::new(),
+        };
+        generator.map(&[], entries, old, object);
+        for op in generator.ops {
+            let counter = op.id().0;
+            self.seen.insert(&self.replica, self.last_local + 1, counter - 1);
+            self.last_local = counter;
+            self.ingest(op.clone(), false);
+            self.outbox.push(op);
+        }
+        self.materialized = target.clone();
+        Ok(())
+    }
+
+    /// Local operations since the last call.
+    pub(crate) fn take_update(&mut self) -> Option<CrdtUpdate> {
+        let last = self.outbox.last()?.id().0;
+        let since = std::mem::replace(&mut self.sent_through, last);
+        Some(CrdtUpdate {
+            version: CRDT_UPDATE_VERSION,
+            ops: std::mem::take(&mut self.outbox),
+            origin: Some((self.replica.clone(), since)),
+            snapshot: None,
+        })
+    }
+
+    /// The whole document with its tombstones, for bootstrapping a peer.
+    pub(crate) fn full_update(&self) -> CrdtUpdate {
+        CrdtUpdate {
+            version: CRDT_UPDATE_VERSION,
+            ops: Vec::new(),
+            origin: None,
+            snapshot: Some(Snapshot {
+                clock: self.clock,
+                root: self.root.clone(),
+                seen: self.seen.clone(),
+            }),
+        }
+    }
+
+    pub(crate) fn merge(&mut self, update: CrdtUpdate) -> Result<(), String> {
+        if update.version != CRDT_UPDATE_VERSION {
+            return Err(format!("Unsupported CRDT update version: {}", update.version));
+        }
+        if let Some(snapshot) = update.snapshot {
+            self.clock = self.clock.max(snapshot.clock);
+            merge_node(&mut self.root, snapshot.root);
+            self.seen.merge(&snapshot.seen);
+            self.materialized = materialize(&self.root);
+        }
+        if let Some((replica, since)) = &update.origin {
+            let mut previous = *since;
+            for op in update.ops.iter().filter(|op| op.id().1 == *replica) {
+                self.seen.insert(replica, previous + 1, op.id().0.saturating_sub(1));
+                previous = op.id().0;
+            }
+        }
+        for op in update.ops {
+            self.ingest(op, true);
+        }
+        loop {
+            let pending = std::mem::take(&mut self.pending);
+            let waiting = pending.len();
+            pending.into_iter().for_each(|op| self.ingest(op, true));
+            if self.pending.len() == waiting {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn ingest(&mut self, op: Op, patch: bool) {
+        if self.seen.contains(op.id()) {
+            return;
+        }
+        self.clock = self.clock.max(op.id().0);
+        let counters = &self.counters;
+        let outcome = match &op {
+            Op::Set { id, path, value } => apply_set(&mut self.root, counters, id, path, value),
+            Op::Remove { id, path } => apply_remove(&mut self.root, id, path),
+            Op::Insert { id, path, after, value } => apply_insert(&mut self.root, counters, id, path, after.as_ref(), value),
+            Op::Counter { path, replica, increments, decrements, .. } => {
+                apply_counter(&mut self.root, path, replica, *increments, *decrements)
+            }
+        };
+        match outcome {
+            Outcome::Pending => self.pending.push(op),
+            Outcome::Applied(change) => {
+                let Id(counter, replica, _) = op.id();
+                self.seen.insert(replica, *counter, *counter);
+                if let (true, Some(change)) = (patch, change) {
+                    self.patch(change);
+                }
+            }
+        }
+    }
+
+    /// Applies a change to the materialized state, falling back to a full
+    /// rebuild if the two ever disagree on the container's shape.
+    fn patch(&mut self, patch: Patch) {
+        let (Patch::Write { parent, .. } | Patch::Delete { parent, .. } | Patch::Insert { parent, .. }) = &patch;
+        let Some(segments) = visible_path(&self.root, parent) else {
+            return;
+        };
+        let patched = match (patch, self.materialized.get_mut(&segments)) {
+            (Patch::Write { slot: Slot::Key(key), value, .. }, Some(StateTree::Object(object))) => {
+                object.insert(Rc::from(key.as_str()), value);
+                true
+            }
+            (Patch::Write { slot: Slot::Index(index), value, .. }, Some(StateTree::Array(items))) if index < items.len() => {
+                items.set(index, value);
+                true
+            }
+            (Patch::Delete { slot: Slot::Key(key), .. }, Some(StateTree::Object(object))) => {
+                object.remove(key.as_str());
+                true
+            }
+            (Patch::Delete { slot: Slot::Index(index), .. }, Some(StateTree::Array(items))) if index < items.len() => {
+                items.remove(index);
+                true
+            }
+            (Patch::Insert { index, value, .. }, Some(StateTree::Array(items))) if index <= items.len() => {
+                items.insert(index, value);
+                true
+            }
+            _ => false,
+        };
+        if !patched {
+            self.materialized = materialize(&self.root);
+        }
+    }
+}
+
+struct Generator<'a> {
+    replica: &'a str,
+    clock: &'a mut u64,
+    ops: Vec<Op>,
+}
+
+impl Generator<'_> {
+    fn next_id(&mut self) -> Id {
+        *self.clock += 1;
+        Id(*self.clock, self.replica.to_string(), 0)
+    }
+
+    fn map(
+        &mut self,
+        path: &[Step],
+        entries: &BTreeMap<String, Entry>,
+        old: &OrdMap<Rc<str>, StateTree>,
+        object: &OrdMap<Rc<str>, StateTree>,
+    ) {
+        for (key, target) in object {
+            let previous = old.get(key);
+            if previous == Some(target) {
+                continue;
+            }
+            let child = extend(path, Step::Key(key.to_string()));
+            match (entries.get(key.as_ref()).and_then(|entry| entry.node.as_ref()), previous) {
+                (Some(node), Some(previous)) => self.value(child, node, previous, target),
+                _ => {
+                    let id = self.next_id();
+                    self.ops.push(Op::Set { id, path: child, value: target.to_value() });
+                }
+            }
+        }
+        for key in old.keys() {
+            if !object.contains_key(key) {
+                let id = self.next_id();
+                self.ops.push(Op::Remove { id, path: extend(path, Step::Key(key.to_string())) });
+            }
+        }
+    }
+
+    /// `previous` is the materialized value of `node`.
+    fn value(&mut self, path: Vec<Step>, node: &Node, previous: &StateTree, target: &StateTree) {
+        if previous == target {
+            return;
+        }
+        match (node, previous, target) {
+            (Node::Map(entries), StateTree::Object(old), StateTree::Object(object)) => self.map(&path, entries, old, object),
+            (Node::Seq(elements), StateTree::Array(old), StateTree::Array(items)) => self.sequence(&path, elements, old, items),
+            (Node::Counter(totals), _, StateTree::Number(number)) if number.is_i64() || number.is_u64() => {
+                let Some(target) = number.as_i64() else {
+                    return;
+                };
+                let delta = target - counter_value(totals);
+                if delta == 0 {
+                    return;
+                }
+                let (increments, decrements) = totals.get(self.replica).copied().unwrap_or_default();
+                let (increments, decrements) = if delta > 0 {
+                    (increments + delta, decrements)
+                } else {
+                    (increments, decrements - delta)
+                };
+                let id = self.next_id();
+                let replica = self.replica.to_string();
+                self.ops.push(Op::Counter { id, path, replica, increments, decrements });
+            }
+            (Node::Leaf(value), _, target) if target == value => {}
+            _ => {
+                let id = self.next_id();
+                self.ops.push(Op::Set { id, path, value: target.to_value() });
+            }
+        }
+    }
+
+    /// Keeps the common prefix and suffix, edits equal-length middles in
+    /// place and otherwise replaces the middle with removes and inserts.
+    /// Live elements line up with `old`, the sequence's materialized items.
+    fn sequence(&mut self, path: &[Step], elements: &[Element], old: &Vector<StateTree>, items: &Vector<StateTree>) {
+        let live: Vec<&Element> = elements.iter().filter(|element| !element.deleted).collect();
+        let rebuilt: Vector<StateTree>;
+        let old = if old.len() == live.len() {
+            old
+        } else {
+            rebuilt = live.iter().map(|element| materialize(&element.node)).collect();
+            &rebuilt
+        };
+        let prefix = old.iter().zip(items.iter()).take_while(|(old, item)| old == item).count();
+        let limit = live.len().min(items.len()) - prefix;
+        let suffix = old
+            .iter()
+            .rev()
+            .zip(items.iter().rev())
+            .take(limit)
+            .take_while(|(old, item)| old == item)
+            .count();
+
+        let old_middle = &live[prefix..live.len() - suffix];
+        let new_middle: Vec<&StateTree> = items.iter().skip(prefix).take(items.len() - suffix - prefix).collect();
+        if old_middle.len() == new_middle.len() {
+            for ((element, previous), item) in old_middle.iter().zip(old.iter().skip(prefix)).zip(new_middle) {
+                self.value(extend(path, Step::Elem(element.id.clone())), &element.node, previous, item);
+            }
+            return;
+        }
+
+        for element in old_middle {
+            let id = self.next_id();
+            self.ops.push(Op::Remove { id, path: extend(path, Step::Elem(element.id.clone())) });
+        }
+        let mut after = prefix.checked_sub(1).map(|index| live[index].id.clone());
+        for item in new_middle {
+            let id = self.next_id();
+            self.ops.push(Op::Insert {
+                id: id.clone(),
+                path: path.to_vec(),
+                after: after.replace(id),
+                value: item.to_value(),
+            });
+        }
+    }
+}
+
+fn extend(path: &[Step], step: Step) -> Vec<Step> {
+    let mut path = path.to_vec();
+    path.push(step);
+    path
+}
+
+fn key_path(path: &[Step]) -> Vec<String> {
+    path.iter()
+        .filter_map(|step| match step {
+            Step::Key(key) => Some(key.clone()),
+            Step::Elem(_) => None,
+        })
+        .collect()
+}
+
+fn navigate<'a>(root: &'a mut Node, path: &[Step]) -> Target<'a> {
+    let mut node = root;
+    for step in path {
+        node = match (node, step) {
+            (Node::Map(entries), Step::Key(key)) => match entries.get_mut(key) {
+                Some(Entry { node: Some(child), .. }) => child,
+                Some(Entry { node: None, .. }) => return Target::Gone,
+                None => return Target::Pending,
+            },
+            (Node::Seq(elements), Step::Elem(id)) => match elements.iter_mut().find(|element| element.id == *id) {
+                Some(element) => &mut element.node,
+                None => return Target::Pending,
+            },
+            _ => return Target::Gone,
+        };
+    }
+    Target::Found(node)
+}
+
+/// The plain-state path of the node at `path`, or `None` while any step of
+/// it is removed or not delivered.
+fn visible_path(root: &Node, path: &[Step]) -> Option<Vec<String>> {
+    let mut node = root;
+    let mut segments = Vec::with_capacity(path.len());
+    for step in path {
+        node = match (node, step) {
+            (Node::Map(entries), Step::Key(key)) => {
+                segments.push(key.clone());
+                entries.get(key)?.node.as_ref()?
+            }
+            (Node::Seq(elements), Step::Elem(id)) => {
+                segments.push(live_index(elements, id)?.to_string());
+                &elements.iter().find(|element| element.id == *id)?.node
+            }
+            _ => return None,
+        };
+    }
+    Some(segments)
+}
+
+/// Index of a live element among the live elements of its sequence.
+fn live_index(elements: &[Element], id: &Id) -> Option<usize> {
+    let mut index = 0;
+    for element in elements {
+        if element.id == *id {
+            return (!element.deleted).then_some(index);
+        }
+        if !element.deleted {
+            index += 1;
+        }
+    }
+    None
+}
+
+/// Builds the node for a written value. Nested entries and elements get ids
+/// derived from the operation's, so every replica builds identical nodes.
+fn build(value: &Value, id: &Id, next: &mut u32, keys: &mut Vec<String>, counters: &[Vec<String>]) -> Node {
+    let child_id = |next: &mut u32| {
+        *next += 1;
+        Id(id.0, id.1.clone(), *next)
+    };
+    match value {
+        Value::Object(object) => Node::Map(
+            object
+                .iter()
+                .map(|(key, child)| {
+                    let entry_id = child_id(next);
+                    keys.push(key.clone());
+                    let node = build(child, id, next, keys, counters);
+                    keys.pop();
+                    (key.clone(), Entry { id: entry_id, node: Some(node) })
+                })
+                .collect(),
+        ),
+        Value::Array(items) => {
+            let mut after = None;
+            Node::Seq(
+                items
+                    .iter()
+                    .map(|item| {
+                        let element_id = child_id(next);
+                        Element {
+                            id: element_id.clone(),
+                            value_id: element_id.clone(),
+                            after: after.replace(element_id),
+                            node: build(item, id, next, keys, counters),
+                            deleted: false,
+                        }
+                    })
+                    .collect(),
+            )
+        }
+        Value::Number(number) if counters.contains(keys) && number.as_i64().is_some() => {
+            let count = number.as_i64().unwrap_or_default();
+            let totals = if count >= 0 { (count, 0) } else { (0, -count) };
+            Node::Counter(BTreeMap::from([(id.1.clone(), totals)]))
+        }
+        leaf => Node::Leaf(leaf.clone()),
+    }
+}
+
+fn build_at(path: &[Step], value: &Value, id: &Id, counters: &[Vec<String>]) -> Node {
+    build(value, id, &mut 0, &mut key_path(path), counters)
+}
+
+fn apply_set(root: &mut Node, counters: &[Vec<String>], id: &Id, path: &[Step], value: &Value) -> Outcome {
+    let Some((last, parent_path)) = path.split_last() else {
+        return Outcome::Applied(None);
+    };
+    let parent = match navigate(root, parent_path) {
+        Target::Found(parent) => parent,
+        Target::Pending => return Outcome::Pending,
+        Target::Gone => return Outcome::Applied(None),
+    };
+    match (parent, last) {
+        (Node::Map(entries), Step::Key(key)) => {
+            if entries.get(key).is_some_and(|entry| entry.id >= *id) {
+                return Outcome::Applied(None);
+            }
+            let node = build_at(path, value, id, counters);
+            let value = materialize(&node);
+            entries.insert(key.clone(), Entry { id: id.clone(), node: Some(node) });
+            Outcome::Applied(Some(Patch::Write {
+                parent: parent_path.to_vec(),
+                slot: Slot::Key(key.clone()),
+                value,
+            }))
+        }
+        (Node::Seq(elements), Step::Elem(element_id)) => {
+            let index = live_index(elements, element_id);
+            let Some(element) = elements.iter_mut().find(|element| element.id == *element_id) else {
+                return Outcome::Pending;
+            };
+            if element.value_id >= *id {
+                return Outcome::Applied(None);
+            }
+            element.node = build_at(path, value, id, counters);
+            element.value_id = id.clone();
+            Outcome::Applied(index.map(|index| Patch::Write {
+                parent: parent_path.to_vec(),
+                slot: Slot::Index(index),
+                value: materialize(&element.node),
+            }))
+        }
+        _ => Outcome::Applied(None),
+    }
+}
+
+fn apply_remove(root: &mut Node, id: &Id, path: &[Step]) -> Outcome {
+    let Some((last, parent_path)) = path.split_last() else {
+        return Outcome::Applied(None);
+    };
+    let parent = match navigate(root, parent_path) {
+        Target::Found(parent) => parent,
+        Target::Pending => return Outcome::Pending,
+        Target::Gone => return Outcome::Applied(None),
+    };
+    let slot = match (parent, last) {
+        (Node::Map(entries), Step::Key(key)) => {
+            if entries.get(key).is_some_and(|entry| entry.id >= *id) {
+                return Outcome::Applied(None);
+            }
+            let previous = entries.insert(key.clone(), Entry { id: id.clone(), node: None });
+            previous.and_then(|entry| entry.node).map(|_| Slot::Key(key.clone()))
+        }
+        (Node::Seq(elements), Step::Elem(element_id)) => {
+            let index = live_index(elements, element_id);
+            match elements.iter_mut().find(|element| element.id == *element_id) {
+                Some(element) => {
+                    element.deleted = true;
+                    index.map(Slot::Index)
+                }
+                None => return Outcome::Pending,
+            }
+        }
+        _ => None,
+    };
+    Outcome::Applied(slot.map(|slot| Patch::Delete {
+        parent: parent_path.to_vec(),
+        slot,
+    }))
+}
+
+/// Where an element inserted right of `after` goes: past any elements
+/// inserted there concurrently with a greater id. `None` until `after` exists.
+fn insert_position(elements: &[Element], after: Option<&Id>, id: &Id) -> Option<usize> {
+    let mut position = match after {
+        None => 0,
+        Some(after) => elements.iter().position(|element| element.id == *after)? + 1,
+    };
+    while elements.get(position).is_some_and(|element| element.id > *id) {
+        position += 1;
+    }
+    Some(position)
+}
+
+fn apply_insert(
+    root: &mut Node,
+    counters: &[Vec<String>],
+    id: &Id,
+    path: &[Step],
+    after: Option<&Id>,
+    value: &Value,
+) -> Outcome {
+    let elements = match navigate(root, path) {
+        Target::Found(Node::Seq(elements)) => elements,
+        Target::Found(_) | Target::Gone => return Outcome::Applied(None),
+        Target::Pending => return Outcome::Pending,
+    };
+    if elements.iter().any(|element| element.id == *id) {
+        return Outcome::Applied(None);
+    }
+    let Some(position) = insert_position(elements, after, id) else {
+        return Outcome::Pending;
+    };
+    let element_path = extend(path, Step::Elem(id.clone()));
+    let node = build_at(&element_path, value, id, counters);
+    let value = materialize(&node);
+    elements.insert(position, Element {
+        id: id.clone(),
+        value_id: id.clone(),
+        after: after.cloned(),
+        node,
+        deleted: false,
+    });
+    Outcome::Applied(Some(Patch::Insert {
+        parent: path.to_vec(),
+        index: elements[..position].iter().filter(|element| !element.deleted).count(),
+        value,
+    }))
+}
+
+fn apply_counter(root: &mut Node, path: &[Step], replica: &str, increments: i64, decrements: i64) -> Outcome {
+    let count = match navigate(root, path) {
+        Target::Found(Node::Counter(totals)) => {
+            let current = totals.entry(replica.to_string()).or_default();
+            *current = (current.0.max(increments), current.1.max(decrements));
+            counter_value(totals)
+        }
+        Target::Found(_) | Target::Gone => return Outcome::Applied(None),
+        Target::Pending => return Outcome::Pending,
+    };
+    let Some((last, parent_path)) = path.split_last() else {
+        return Outcome::Applied(None);
+    };
+    let slot = match last {
+        Step::Key(key) => Some(Slot::Key(key.clone())),
+        Step::Elem(id) => match navigate(root, parent_path) {
+            Target::Found(Node::Seq(elements)) => live_index(elements, id).map(Slot::Index),
+            _ => None,
+        },
+    };
+    Outcome::Applied(slot.map(|slot| Patch::Write {
+        parent: parent_path.to_vec(),
+        slot,
+        value: StateTree::Number(count.into()),
+    }))
+}
+
+/// Folds a peer's document into this one with the same rules operations
+/// follow: newer ids win, tombstones stick and counters keep maxima.
+fn merge_node(local: &mut Node, remote: Node) {
+    match (local, remote) {
+        (Node::Map(entries), Node::Map(remote)) => {
+            for (key, theirs) in remote {
+                match entries.get_mut(&key) {
+                    Some(ours) if ours.id > theirs.id => {}
+                    Some(ours) if ours.id == theirs.id => {
+                        if let (Some(node), Some(remote)) = (ours.node.as_mut(), theirs.node) {
+                            merge_node(node, remote);
+                        }
+                    }
+                    _ => {
+                        entries.insert(key, theirs);
+                    }
+                }
+            }
+        }
+        (Node::Seq(elements), Node::Seq(remote)) => {
+            for theirs in remote {
+                match elements.iter_mut().find(|element| element.id == theirs.id) {
+                    Some(ours) => {
+                        ours.deleted |= theirs.deleted;
+                        if ours.value_id < theirs.value_id {
+                            ours.node = theirs.node;
+                            ours.value_id = theirs.value_id;
+                        } else if ours.value_id == theirs.value_id {
+                            merge_node(&mut ours.node, theirs.node);
+                        }
+                    }
+                    None => {
+                        let position = insert_position(elements, theirs.after.as_ref(), &theirs.id).unwrap_or(elements.len());
+                        elements.insert(position, theirs);
+                    }
+                }
+            }
+        }
+        (Node::Counter(totals), Node::Counter(remote)) => {
+            for (replica, (increments, decrements)) in remote {
+                let current = totals.entry(replica).or_default();
+                *current = (current.0.max(increments), current.1.max(decrements));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn counter_value(totals: &BTreeMap<String, (i64, i64)>) -> i64 {
+    totals.values().map(|(increments, decrements)| increments - decrements).sum()
+}
+
+fn materialize(node: &Node) -> StateTree {
+    match node {
+        Node::Leaf(value) => value.clone().into(),
+        Node::Map(entries) => StateTree::Object(
+            entries
+                .iter()
+                .filter_map(|(key, entry)| Some((Rc::from(key.as_str()), materialize(entry.node.as_ref()?))))
+                .collect(),
+        ),
+        Node::Seq(elements) => StateTree::Array(
+            elements
+                .iter()
+                .filter(|element| !element.deleted)
+                .map(|element| materialize(&element.node))
+                .collect(),
+        ),
+        Node::Counter(totals) => StateTree::Number(counter_value(totals).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store_codec::BinaryFormat;
+    use serde_json::json;
+
+    fn replica(id: &str, state: Value) -> CrdtDocument {
+        CrdtDocument::new(id.to_string(), vec![vec!["count".to_string()]], &state.into()).unwrap()
+    }
+
+    /// `b` starts from `a`'s full state, as a peer joining a session would.
+    fn pair(state: Value) -> (CrdtDocument, CrdtDocument) {
+        let mut a = replica("a", state);
+        a.take_update();
+        let mut b = replica("b", json!({}));
+        b.take_update();
+        b.merge(a.full_update()).unwrap();
+        (a, b)
+    }
+
+    fn exchange(a: &mut CrdtDocument, b: &mut CrdtDocument) {
+        let from_a = a.take_update();
+        let from_b = b.take_update();
+        if let Some(update) = from_a {
+            b.merge(update).unwrap();
+        }
+        if let Some(update) = from_b {
+            a.merge(update).unwrap();
+        }
+    }
+
+    #[test]
+    fn concurrent_pushes_and_increments_both_survive() {
+        let (mut a, mut b) = pair(json!({ "count": 0, "todos": ["first"] }));
+        a.reconcile(&json!({ "count": 2, "todos": ["first", "from a"] }).into()).unwrap();
+        b.reconcile(&json!({ "count": 3, "todos": ["first", "from b"] }).into()).unwrap();
+        exchange(&mut a, &mut b);
+
+        assert_eq!(*a.state(), *b.state());
+        assert_eq!(a.state().child("count"), Some(&StateTree::from(json!(5))));
+        let todos = a.state().child("todos").unwrap().to_value();
+        assert_eq!(todos.as_array().unwrap().len(), 3);
+        assert_eq!(todos[0], "first");
+    }
+
+    #[test]
+    fn concurrent_map_writes_keep_distinct_keys_and_converge_on_one_value() {
+        let (mut a, mut b) = pair(json!({ "user": { "name": "x" } }));
+        a.reconcile(&json!({ "user": { "name": "ann", "theme": "dark" } }).into()).unwrap();
+        b.reconcile(&json!({ "user": { "name": "bob", "lang": "ko" } }).into()).unwrap();
+        exchange(&mut a, &mut b);
+
+        assert_eq!(*a.state(), *b.state());
+        let user = a.state().child("user").unwrap();
+        assert_eq!(user.child("theme"), Some(&StateTree::from(json!("dark"))));
+        assert_eq!(user.child("lang"), Some(&StateTree::from(json!("ko"))));
+    }
+
+    #[test]
+    fn binary_updates_merge_idempotently_and_out_of_order() {
+        let mut a = replica("a", json!({ "items": [] }));
+        let first = a.take_update().unwrap();
+        a.reconcile(&json!({ "items": [{ "done": false }] }).into()).unwrap();
+        let second = a.take_update().unwrap();
+        a.reconcile(&json!({ "items": [{ "done": true }] }).into()).unwrap();
+        let third = a.take_update().unwrap();
+
+        let format = BinaryFormat::MessagePack;
+        let encoded: Vec<Vec<u8>> = [first, second, third].iter().map(|update| format.encode(update).unwrap()).collect();
+        let mut b = replica("b", json!({}));
+        for bytes in [&encoded[2], &encoded[1], &encoded[0], &encoded[1]] {
+            b.merge(format.decode(bytes).unwrap()).unwrap();
+        }
+        assert_eq!(*b.state(), json!({ "items": [{ "done": true }] }));
+        assert!(b.pending.is_empty());
+    }
+
+    #[test]
+    fn removed_entries_stay_removed_against_older_writes() {
+        let (mut a, mut b) = pair(json!({ "draft": "text", "keep": 1 }));
+        a.reconcile(&json!({ "keep": 1 }).into()).unwrap();
+        exchange(&mut a, &mut b);
+        b.merge(a.full_update()).unwrap();
+        assert_eq!(*b.state(), json!({ "keep": 1 }));
+        assert!(a.reconcile(&json!([1]).into()).is_err());
+    }
+
+    #[test]
+    fn merges_patch_touched_paths_and_share_the_rest() {
+        let (mut a, mut b) = pair(json!({ "count": 0, "catalog": [{ "sku": 1 }, { "sku": 2 }], "todos": ["a", "b", "c"] }));
+        let catalog = b.state().child("catalog").cloned().unwrap();
+        a.reconcile(&json!({ "count": 4, "catalog": [{ "sku": 1 }, { "sku": 2 }], "todos": ["a", "c", "d"], "user": { "name": "x" } }).into())
+            .unwrap();
+        exchange(&mut a, &mut b);
+
+        assert_eq!(*b.state(), *a.state());
+        assert_eq!(*b.state(), materialize(&b.root));
+        let (StateTree::Array(before), Some(StateTree::Array(after))) = (&catalog, b.state().child("catalog")) else {
+            panic!("catalog should stay an array");
+        };
+        assert!(before.ptr_eq(after));
+    }
+
+    #[test]
+    fn state_updates_bootstrap_without_history_and_seen_runs_collapse() {
+        let (mut a, mut b) = pair(json!({ "todos": [] }));
+        for round in 0..5 {
+            a.reconcile(&json!({ "todos": (0..=round).collect::<Vec<_>>() }).into()).unwrap();
+            b.reconcile(&json!({ "todos": (0..=round).collect::<Vec<_>>(), "b": round }).into()).unwrap();
+            exchange(&mut a, &mut b);
+        }
+        assert_eq!(*a.state(), *b.state());
+
+        let bootstrap = a.full_update();
+        assert!(bootstrap.ops.is_empty());
+        let mut c = replica("c", json!({}));
+        c.take_update();
+        c.merge(bootstrap).unwrap();
+        assert_eq!(*c.state(), *a.state());
+
+        a.reconcile(&json!({ "todos": ["done"], "b": 4 }).into()).unwrap();
+        let update = a.take_update().unwrap();
+        b.merge(update).unwrap();
+        assert_eq!(b.seen.0["a"].len(), 1);
+        assert_eq!(b.seen.0["b"].len(), 1);
+    }
+}
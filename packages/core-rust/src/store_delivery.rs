@@ -0,0 +1,154 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+const DEFAULT_MAX_FAILURES: u32 = 3;
+
+/// What happens once a subscriber callback throws. Every callback in a
+/// delivery is still invoked; the policy only decides what follows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ErrorPolicy {
+    /// Report the error and keep the subscription.
+    Continue,
+    /// Drop a subscription after `max_failures` consecutive failures.
+    Unsubscribe { max_failures: u32 },
+    /// Deliver to everyone, then fail the call with every error combined.
+    Rethrow,
+}
+
+impl ErrorPolicy {
+    /// Parses `{ policy: "continue" | "unsubscribe" | "rethrow", maxFailures? }`.
+    pub(crate) fn parse(options: &Value) -> Result<Self, String> {
+        let max_failures = match options.get("maxFailures") {
+            None | Some(Value::Null) => DEFAULT_MAX_FAILURES,
+            Some(value) => value
+                .as_u64()
+                .filter(|count| *count > 0)
+                .and_then(|count| u32::try_from(count).ok())
+                .ok_or_else(|| format!("maxFailures must be a positive integer, got {value}"))?,
+        };
+        match options.get("policy").and_then(Value::as_str).unwrap_or("continue") {
+            "continue" => Ok(Self::Continue),
+            "unsubscribe" => Ok(Self::Unsubscribe { max_failures }),
+            "rethrow" => Ok(Self::Rethrow),
+            other => Err(format!("Unknown subscriber error policy: {other}")),
+        }
+    }
+}
+
+/// A subscriber callback that threw during one delivery.
+pub(crate) struct DeliveryFailure {
+    pub(crate) subscription_id: String,
+    pub(crate) message: String,
+    pub(crate) failures: u32,
+    pub(crate) unsubscribed: bool,
+}
+
+/// Consecutive failure counts per subscription under the active policy.
+pub(crate) struct SubscriberDelivery {
+    policy: ErrorPolicy,
+    failures: HashMap<String, u32>,
+}
+
+impl SubscriberDelivery {
+    pub(crate) fn new() -> Self {
+        Self {
+            policy: ErrorPolicy::Continue,
+            failures: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn policy(&self) -> ErrorPolicy {
+        self.policy
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: ErrorPolicy) {
+        self.policy = policy;
+        self.failures.clear();
+    }
+
+    pub(crate) fn record_success(&mut self, subscription_id: &str) {
+        self.failures.remove(subscription_id);
+    }
+
+    pub(crate) fn record_failure(&mut self, subscription_id: &str, message: String) -> DeliveryFailure {
+        let failures = self.failures.entry(subscription_id.to_string()).or_default();
+        *failures += 1;
+        let failures = *failures;
+        let unsubscribed = matches!(self.policy, ErrorPolicy::Unsubscribe { max_failures } if failures >= max_failures);
+        if unsubscribed {
+            self.failures.remove(subscription_id);
+        }
+        DeliveryFailure {
+            subscription_id: subscription_id.to_string(),
+            message,
+            failures,
+            unsubscribed,
+        }
+    }
+
+    pub(crate) fn forget(&mut self, subscription_id: &str) {
+        self.failures.remove(subscription_id);
+    }
+}
+
+/// The error a `rethrow` delivery fails with, or `None` when nothing threw.
+pub(crate) fn aggregate_error(failures: &[DeliveryFailure]) -> Option<String> {
+    if failures.is_empty() {
+        return None;
+    }
+    let messages = failures
+        .iter()
+        .map(|failure| format!("{}: {}", failure.subscription_id, failure.message))
+        .collect::<Vec<_>>();
+    Some(format!("Subscriber callbacks failed ({}): {}", failures.len(), messages.join("; ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_policies_with_default_threshold() {
+        assert_eq!(ErrorPolicy::parse(&Value::Null).unwrap(), ErrorPolicy::Continue);
+        assert_eq!(
+            ErrorPolicy::parse(&json!({ "policy": "unsubscribe" })).unwrap(),
+            ErrorPolicy::Unsubscribe { max_failures: 3 }
+        );
+        assert_eq!(ErrorPolicy::parse(&json!({ "policy": "rethrow" })).unwrap(), ErrorPolicy::Rethrow);
+        assert_eq!(
+            ErrorPolicy::parse(&json!({ "policy": "ignore" })).unwrap_err(),
+            "Unknown subscriber error policy: ignore"
+        );
+        assert!(ErrorPolicy::parse(&json!({ "policy": "unsubscribe", "maxFailures": 0 })).is_err());
+    }
+
+    #[test]
+    fn unsubscribes_after_consecutive_failures_only() {
+        let mut delivery = SubscriberDelivery::new();
+        delivery.set_policy(ErrorPolicy::Unsubscribe { max_failures: 2 });
+
+        assert!(!delivery.record_failure("sub_1", "boom".to_string()).unsubscribed);
+        delivery.record_success("sub_1");
+        assert!(!delivery.record_failure("sub_1", "boom".to_string()).unsubscribed);
+        let failure = delivery.record_failure("sub_1", "boom".to_string());
+        assert_eq!(failure.failures, 2);
+        assert!(failure.unsubscribed);
+        assert!(!delivery.record_failure("sub_2", "boom".to_string()).unsubscribed);
+    }
+
+    #[test]
+    fn aggregate_error_lists_every_failure() {
+        let mut delivery = SubscriberDelivery::new();
+        delivery.set_policy(ErrorPolicy::Rethrow);
+        let failures = vec![
+            delivery.record_failure("sub_1", "vue widget".to_string()),
+            delivery.record_failure("sub_2", "svelte store".to_string()),
+        ];
+        assert_eq!(
+            aggregate_error(&failures).unwrap(),
+            "Subscriber callbacks failed (2): sub_1: vue widget; sub_2: svelte store"
+        );
+        assert!(aggregate_error(&[]).is_none());
+    }
+}
@@ -0,0 +1,171 @@
+use serde_json::{json, Value};
+
+use crate::store_tree::StateTree;
+
+/// What the store should do in response to a message from Redux DevTools.
+#[derive(Debug, PartialEq)]
+pub(crate) enum DevtoolsCommand {
+    /// Replace the store state through the `SET` path without echoing it back.
+    SetState(Value),
+    /// Re-send `INIT` with the current state as the new baseline.
+    Reinit,
+    Ignore,
+}
+
+/// Outgoing half of the Redux DevTools protocol for one store.
+///
+/// Messages are queued as `{ type: "INIT", name, state }` and
+/// `{ type: "ACTION", name, action: { type, payload }, state }` until the
+/// store drains them after a commit.
+pub(crate) struct DevtoolsBridge {
+    name: String,
+    outbox: Vec<Value>,
+    muted: bool,
+}
+
+impl DevtoolsBridge {
+    pub(crate) fn new(name: String) -> Self {
+        Self {
+            name,
+            outbox: Vec::new(),
+            muted: false,
+        }
+    }
+
+    pub(crate) fn init(&mut self, state: &StateTree) {
+        self.outbox.push(json!({ "type": "INIT", "name": self.name, "state": state }));
+    }
+
+    pub(crate) fn action(&mut self, action_type: &str, payload: &Value, state: &StateTree) {
+        if self.muted {
+            return;
+        }
+        self.outbox.push(json!({
+            "type": "ACTION",
+            "name": self.name,
+            "action": { "type": action_type, "payload": payload },
+            "state": state,
+        }));
+    }
+
+    /// Suppresses `ACTION` messages while DevTools itself drives the state.
+    pub(crate) fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub(crate) fn take_messages(&mut self) -> Vec<Value> {
+        std::mem::take(&mut self.outbox)
+    }
+}
+
+/// Interprets a DevTools monitor message (`{ type: "DISPATCH", payload, state }`).
+pub(crate) fn parse_command(message: &Value) -> Result<DevtoolsCommand, String> {
+    if message.get("type").and_then(Value::as_str) != Some("DISPATCH") {
+        return Ok(DevtoolsCommand::Ignore);
+    }
+    let payload = message.get("payload").unwrap_or(&Value::Null);
+    match payload.get("type").and_then(Value::as_str) {
+        Some("JUMP_TO_STATE" | "JUMP_TO_ACTION") => {
+            let state = message.get("state").ok_or("DevTools jump requires state")?;
+            Ok(DevtoolsCommand::SetState(decode_state(state)?))
+        }
+        Some("IMPORT_STATE") => {
+            let lifted = payload
+                .get("nextLiftedState")
+                .ok_or("IMPORT_STATE requires nextLiftedState")?;
+            let computed = lifted
+                .get("computedStates")
+                .and_then(Value::as_array)
+                .ok_or("IMPORT_STATE requires computedStates")?;
+            let index = lifted
+                .get("currentStateIndex")
+                .and_then(Value::as_u64)
+                .map(|index| index as usize)
+                .unwrap_or_else(|| computed.len().saturating_sub(1));
+            let state = computed
+                .get(index)
+                .and_then(|entry| entry.get("state"))
+                .ok_or("IMPORT_STATE has no state at currentStateIndex")?;
+            Ok(DevtoolsCommand::SetState(decode_state(state)?))
+        }
+        Some("COMMIT") => Ok(DevtoolsCommand::Reinit),
+        _ => Ok(DevtoolsCommand::Ignore),
+    }
+}
+
+/// The extension sends states as JSON strings; stand-ins may send objects.
+fn decode_state(state: &Value) -> Result<Value, String> {
+    match state {
+        Value::String(encoded) => {
+            serde_json::from_str(encoded).map_err(|error| format!("Invalid DevTools state: {error}"))
+        }
+        other => Ok(other.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal monitor that records what it receives, like the extension.
+    struct Monitor {
+        states: Vec<Value>,
+    }
+
+    impl Monitor {
+        fn receive(&mut self, messages: Vec<Value>) {
+            for message in messages {
+                if message["type"] == "INIT" {
+                    self.states.clear();
+                }
+                self.states.push(message["state"].clone());
+            }
+        }
+
+        fn jump_to(&self, index: usize) -> Value {
+            json!({
+                "type": "DISPATCH",
+                "payload": { "type": "JUMP_TO_STATE", "index": index },
+                "state": self.states[index].to_string(),
+            })
+        }
+    }
+
+    #[test]
+    fn monitor_can_jump_back_to_a_recorded_state() {
+        let mut bridge = DevtoolsBridge::new("cart".to_string());
+        let mut monitor = Monitor { states: Vec::new() };
+
+        bridge.init(&json!({ "count": 0 }).into());
+        bridge.action("MERGE", &json!({ "count": 1 }), &json!({ "count": 1 }).into());
+        bridge.action("MERGE", &json!({ "count": 2 }), &json!({ "count": 2 }).into());
+        monitor.receive(bridge.take_messages());
+        assert_eq!(monitor.states.len(), 3);
+
+        let command = parse_command(&monitor.jump_to(1)).unwrap();
+        assert_eq!(command, DevtoolsCommand::SetState(json!({ "count": 1 })));
+
+        bridge.set_muted(true);
+        bridge.action("SET", &json!({ "count": 1 }), &json!({ "count": 1 }).into());
+        assert!(bridge.take_messages().is_empty());
+    }
+
+    #[test]
+    fn import_and_commit_commands_are_recognised() {
+        let import = json!({
+            "type": "DISPATCH",
+            "payload": {
+                "type": "IMPORT_STATE",
+                "nextLiftedState": {
+                    "computedStates": [{ "state": { "count": 0 } }, { "state": { "count": 5 } }],
+                    "currentStateIndex": 1
+                }
+            }
+        });
+        assert_eq!(parse_command(&import).unwrap(), DevtoolsCommand::SetState(json!({ "count": 5 })));
+
+        let commit = json!({ "type": "DISPATCH", "payload": { "type": "COMMIT" } });
+        assert_eq!(parse_command(&commit).unwrap(), DevtoolsCommand::Reinit);
+        assert_eq!(parse_command(&json!({ "type": "START" })).unwrap(), DevtoolsCommand::Ignore);
+    }
+}
@@ -0,0 +1,233 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+
+use crate::json_patch::{apply_patch, diff, parse_pointer};
+use crate::store_tree::StateTree;
+
+const DEFAULT_HISTORY_DEPTH: usize = 100;
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct HistoryEntry {
+    label: String,
+    timestamp: f64,
+    undo: Vec<Value>,
+    redo: Vec<Value>,
+}
+
+struct HistoryGroup {
+    label: String,
+    base: StateTree,
+}
+
+/// Parses the `maxDepth` history option; absent means the default depth.
+pub(crate) fn parse_max_depth(value: Option<&Value>) -> Result<Option<usize>, String> {
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .filter(|depth| *depth > 0)
+            .map(|depth| Some(depth as usize))
+            .ok_or_else(|| format!("maxDepth must be a positive integer, got {value}")),
+    }
+}
+
+/// Undo/redo history for a JSON store, kept as inverse/forward patch pairs.
+pub(crate) struct StoreHistory {
+    max_depth: usize,
+    exclude: Vec<Vec<String>>,
+    undo_stack: VecDeque<HistoryEntry>,
+    redo_stack: Vec<HistoryEntry>,
+    group: Option<HistoryGroup>,
+}
+
+impl StoreHistory {
+    pub(crate) fn new(max_depth: Option<usize>, exclude: Vec<Vec<String>>) -> Self {
+        Self {
+            max_depth: max_depth.unwrap_or(DEFAULT_HISTORY_DEPTH),
+            exclude,
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            group: None,
+        }
+    }
+
+    pub(crate) fn record(&mut self, before: &StateTree, after: &StateTree, label: &str, timestamp: f64) {
+        if self.group.is_some() {
+            return;
+        }
+        self.push_entry(before, after, label.to_string(), timestamp);
+    }
+
+    pub(crate) fn begin_group(&mut self, base: &StateTree, label: &str) -> Result<(), String> {
+        if self.group.is_some() {
+            return Err("A history group is already open".to_string());
+        }
+        self.group = Some(HistoryGroup {
+            label: label.to_string(),
+            base: base.clone(),
+        });
+        Ok(())
+    }
+
+    pub(crate) fn end_group(&mut self, current: &StateTree, timestamp: f64) -> Result<(), String> {
+        let group = self.group.take().ok_or("No history group is open")?;
+        self.push_entry(&group.base, current, group.label, timestamp);
+        Ok(())
+    }
+
+    /// Applies the most recent inverse patch to `state`.
+    ///
+    /// Returns `Ok(false)` when there is nothing to undo. The entry is kept on
+    /// the undo stack if its patch no longer applies.
+    pub(crate) fn undo(&mut self, state: &mut StateTree) -> Result<bool, String> {
+        self.ensure_no_group()?;
+        let Some(entry) = self.undo_stack.pop_back() else {
+            return Ok(false);
+        };
+        match apply_to_copy(state, &entry.undo) {
+            Ok(()) => {
+                self.redo_stack.push(entry);
+                Ok(true)
+            }
+            Err(error) => {
+                self.undo_stack.push_back(entry);
+                Err(error)
+            }
+        }
+    }
+
+    pub(crate) fn redo(&mut self, state: &mut StateTree) -> Result<bool, String> {
+        self.ensure_no_group()?;
+        let Some(entry) = self.redo_stack.pop() else {
+            return Ok(false);
+        };
+        match apply_to_copy(state, &entry.redo) {
+            Ok(()) => {
+                self.undo_stack.push_back(entry);
+                Ok(true)
+            }
+            Err(error) => {
+                self.redo_stack.push(entry);
+                Err(error)
+            }
+        }
+    }
+
+    pub(crate) fn summary(&self) -> Value {
+        serde_json::json!({
+            "maxDepth": self.max_depth,
+            "canUndo": !self.undo_stack.is_empty(),
+            "canRedo": !self.redo_stack.is_empty(),
+            "grouping": self.group.as_ref().map(|group| group.label.clone()),
+            "undo": self.undo_stack,
+            "redo": self.redo_stack,
+        })
+    }
+
+    fn ensure_no_group(&self) -> Result<(), String> {
+        match self.group {
+            Some(_) => Err("Cannot undo or redo while a history group is open".to_string()),
+            None => Ok(()),
+        }
+    }
+
+    fn push_entry(&mut self, before: &StateTree, after: &StateTree, label: String, timestamp: f64) {
+        let undo = self.filter_excluded(diff(after, before));
+        let redo = self.filter_excluded(diff(before, after));
+        if undo.is_empty() && redo.is_empty() {
+            return;
+        }
+
+        self.undo_stack.push_back(HistoryEntry {
+            label,
+            timestamp,
+            undo,
+            redo,
+        });
+        while self.undo_stack.len() > self.max_depth {
+            self.undo_stack.pop_front();
+        }
+        self.redo_stack.clear();
+    }
+
+    fn filter_excluded(&self, operations: Vec<Value>) -> Vec<Value> {
+        if self.exclude.is_empty() {
+            return operations;
+        }
+        operations
+            .into_iter()
+            .filter(|operation| {
+                let tokens = operation
+                    .get("path")
+                    .and_then(Value::as_str)
+                    .and_then(|path| parse_pointer(path).ok())
+                    .unwrap_or_default();
+                !self.exclude.iter().any(|excluded| tokens.starts_with(excluded))
+            })
+            .collect()
+    }
+}
+
+fn apply_to_copy(state: &mut StateTree, operations: &[Value]) -> Result<(), String> {
+    let mut next = state.clone();
+    apply_patch(&mut next, operations)?;
+    *state = next;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn max_depth_must_be_positive() {
+        assert_eq!(parse_max_depth(None).unwrap(), None);
+        assert_eq!(parse_max_depth(Some(&json!(5))).unwrap(), Some(5));
+        assert_eq!(parse_max_depth(Some(&json!(0))).unwrap_err(), "maxDepth must be a positive integer, got 0");
+        assert!(parse_max_depth(Some(&json!(2.5))).is_err());
+    }
+
+    #[test]
+    fn undo_and_redo_walk_recorded_dispatches() {
+        let mut history = StoreHistory::new(None, vec![]);
+        let first = StateTree::from(json!({ "name": "A" }));
+        let second = StateTree::from(json!({ "name": "B", "age": 7 }));
+        history.record(&first, &second, "MERGE", 1.0);
+
+        let mut state = second.clone();
+        assert!(history.undo(&mut state).unwrap());
+        assert_eq!(state, first);
+        assert!(!history.undo(&mut state).unwrap());
+
+        assert!(history.redo(&mut state).unwrap());
+        assert_eq!(state, second);
+    }
+
+    #[test]
+    fn history_respects_depth_groups_and_excluded_paths() {
+        let mut history = StoreHistory::new(Some(2), vec![vec!["lastUpdated".to_string()]]);
+        history.record(&json!({ "count": 0 }).into(), &json!({ "count": 1 }).into(), "A", 1.0);
+        history.record(&json!({ "count": 1 }).into(), &json!({ "count": 2 }).into(), "B", 2.0);
+        history.record(&json!({ "count": 2 }).into(), &json!({ "count": 3 }).into(), "C", 3.0);
+        history.record(
+            &json!({ "count": 3, "lastUpdated": 1 }).into(),
+            &json!({ "count": 3, "lastUpdated": 2 }).into(),
+            "TOUCH",
+            4.0,
+        );
+        assert_eq!(history.undo_stack.len(), 2);
+        assert_eq!(history.undo_stack[0].label, "B");
+
+        let base = StateTree::from(json!({ "count": 3, "name": "A" }));
+        history.begin_group(&base, "form").unwrap();
+        history.record(&base, &json!({ "count": 4, "name": "A" }).into(), "X", 5.0);
+        history.end_group(&json!({ "count": 5, "name": "B", "lastUpdated": 9 }).into(), 6.0).unwrap();
+
+        let mut state = StateTree::from(json!({ "count": 5, "name": "B", "lastUpdated": 9 }));
+        history.undo(&mut state).unwrap();
+        assert_eq!(state, json!({ "count": 3, "name": "A", "lastUpdated": 9 }));
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::store::{apply_action_mut, apply_counter_steps};
+use crate::store_reducers::ActionReducers;
+use crate::store_tree::StateTree;
+
+pub(crate) const ACTION_LOG_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum EntryKind {
+    /// A regular action replayed through the store's action reducer.
+    #[default]
+    Dispatch,
+    /// A counter dispatch with a `{ delta, count }` payload.
+    Counter,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct JournalEntry {
+    #[serde(rename = "type")]
+    pub(crate) action_type: String,
+    #[serde(default)]
+    pub(crate) kind: EntryKind,
+    #[serde(default)]
+    pub(crate) payload: Value,
+    pub(crate) timestamp: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) framework: Option<String>,
+    pub(crate) state_hash: String,
+}
+
+/// Exported journal: the state recording started from plus every entry since.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ActionLog {
+    pub(crate) version: u32,
+    #[serde(default)]
+    pub(crate) store_id: String,
+    pub(crate) initial_state: Value,
+    pub(crate) entries: Vec<JournalEntry>,
+}
+
+/// Entries a journal keeps unless `enable_action_log` is given `maxEntries`.
+pub(crate) const DEFAULT_MAX_ENTRIES: usize = 10_000;
+
+/// Per-store action journal, bounded to `max_entries`: once full, the oldest
+/// entry is folded into the initial state. Each entry keeps the state it
+/// produced, shared structurally with the store, and its hash is computed on
+/// export so replays can detect divergence.
+pub(crate) struct ActionJournal {
+    initial_state: StateTree,
+    entries: VecDeque<(JournalEntry, StateTree)>,
+    max_entries: usize,
+}
+
+impl ActionJournal {
+    pub(crate) fn new(initial_state: StateTree, max_entries: usize) -> Self {
+        Self {
+            initial_state,
+            entries: VecDeque::new(),
+            max_entries,
+        }
+    }
+
+    /// Adopts an imported log; `states` are the replayed states after each
+    /// entry, as returned by [`replay_log`].
+    pub(crate) fn from_log(log: ActionLog, states: Vec<StateTree>, max_entries: usize) -> Self {
+        let mut journal = Self::new(log.initial_state.into(), max_entries);
+        journal.entries = log.entries.into_iter().zip(states).collect();
+        journal.rotate();
+        journal
+    }
+
+    pub(crate) fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        kind: EntryKind,
+        action_type: &str,
+        payload: Value,
+        framework: Option<&str>,
+        timestamp: f64,
+        state: &StateTree,
+    ) {
+        let entry = JournalEntry {
+            action_type: action_type.to_string(),
+            kind,
+            payload,
+            timestamp,
+            framework: framework.map(ToString::to_string),
+            state_hash: String::new(),
+        };
+        self.entries.push_back((entry, state.clone()));
+        self.rotate();
+    }
+
+    fn rotate(&mut self) {
+        while self.entries.len() > self.max_entries {
+            if let Some((_, state)) = self.entries.pop_front() {
+                self.initial_state = state;
+            }
+        }
+    }
+
+    pub(crate) fn export(&self, store_id: &str) -> ActionLog {
+        ActionLog {
+            version: ACTION_LOG_VERSION,
+            store_id: store_id.to_string(),
+            initial_state: self.initial_state.to_value(),
+            entries: self
+                .entries
+                .iter()
+                .map(|(entry, state)| JournalEntry {
+                    state_hash: state_hash(state),
+                    ..entry.clone()
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Rebuilds state from `log.initial_state`, verifying every entry's hash,
+/// and returns the state after each entry.
+pub(crate) fn replay_log(log: &ActionLog, reducers: &ActionReducers) -> Result<Vec<StateTree>, String> {
+    if log.version != ACTION_LOG_VERSION {
+        return Err(format!("Unsupported action log version: {}", log.version));
+    }
+
+    let mut state = StateTree::from(log.initial_state.clone());
+    let mut states = Vec::with_capacity(log.entries.len());
+    for (index, entry) in log.entries.iter().enumerate() {
+        apply_entry(&mut state, entry, reducers).map_err(|error| format!("Replay failed at entry {index}: {error}"))?;
+        let hash = state_hash(&state);
+        if hash != entry.state_hash {
+            return Err(format!(
+                "Replay diverged at entry {index} ({}): expected state hash {}, got {hash}",
+                entry.action_type, entry.state_hash
+            ));
+        }
+        states.push(state.clone());
+    }
+    Ok(states)
+}
+
+fn apply_entry(state: &mut StateTree, entry: &JournalEntry, reducers: &ActionReducers) -> Result<(), String> {
+    match entry.kind {
+        EntryKind::Dispatch => apply_action_mut(state, &entry.action_type, entry.payload.clone(), reducers),
+        EntryKind::Counter => {
+            let field = |key: &str| entry.payload.get(key).and_then(Value::as_i64);
+            let delta = field("delta").and_then(|delta| i32::try_from(delta).ok()).ok_or("Counter entry requires delta")?;
+            let count = field("count").and_then(|count| u32::try_from(count).ok()).unwrap_or(1);
+            let framework = entry.framework.as_deref().unwrap_or_default();
+            apply_counter_steps(state, delta, count, framework, &entry.action_type, entry.timestamp)
+        }
+    }
+}
+
+/// FNV-1a over the canonical JSON encoding; object keys serialize sorted.
+pub(crate) fn state_hash<T: Serialize + ?Sized>(state: &T) -> String {
+    let encoded = serde_json::to_string(state).unwrap_or_default();
+    let hash = encoded.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn replay_rebuilds_recorded_state() {
+        let initial = StateTree::from(json!({ "count": 0, "todos": [] }));
+        let mut journal = ActionJournal::new(initial.clone(), DEFAULT_MAX_ENTRIES);
+        let mut state = initial;
+
+        let reducers = ActionReducers::default();
+        apply_action_mut(&mut state, "PUSH", json!({ "path": "todos", "value": "a" }), &reducers).unwrap();
+        journal.record(EntryKind::Dispatch, "PUSH", json!({ "path": "todos", "value": "a" }), None, 1.0, &state);
+        apply_counter_steps(&mut state, 2, 3, "react", "INCREMENT", 2.0).unwrap();
+        journal.record(EntryKind::Counter, "INCREMENT", json!({ "delta": 2, "count": 3 }), Some("react"), 2.0, &state);
+
+        let log: ActionLog = serde_json::from_value(serde_json::to_value(journal.export("main")).unwrap()).unwrap();
+        assert_eq!(replay_log(&log, &reducers).unwrap().last(), Some(&state));
+        assert_eq!(log.entries[1].framework.as_deref(), Some("react"));
+    }
+
+    #[test]
+    fn replay_reports_divergence() {
+        let mut journal = ActionJournal::new(json!({ "count": 0 }).into(), DEFAULT_MAX_ENTRIES);
+        journal.record(EntryKind::Dispatch, "MERGE", json!({ "count": 1 }), None, 1.0, &json!({ "count": 2 }).into());
+        let error = replay_log(&journal.export("main"), &ActionReducers::default()).unwrap_err();
+        assert!(error.starts_with("Replay diverged at entry 0 (MERGE)"));
+    }
+
+    #[test]
+    fn full_journal_folds_oldest_entry_into_initial_state() {
+        let mut journal = ActionJournal::new(json!({ "count": 0 }).into(), 2);
+        let reducers = ActionReducers::default();
+        let mut state = StateTree::from(json!({ "count": 0 }));
+        for count in 1..=3 {
+            apply_action_mut(&mut state, "MERGE", json!({ "count": count }), &reducers).unwrap();
+            journal.record(EntryKind::Dispatch, "MERGE", json!({ "count": count }), None, f64::from(count), &state);
+        }
+
+        let log = journal.export("main");
+        assert_eq!(log.initial_state, json!({ "count": 1 }));
+        assert_eq!(log.entries.len(), 2);
+        assert_eq!(replay_log(&log, &reducers).unwrap().last(), Some(&state));
+    }
+}
@@ -0,0 +1,452 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::json_number;
+
+const DEFAULT_BUFFER_CAPACITY: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum LaneKind {
+    Int,
+    Float,
+    Bool,
+}
+
+impl LaneKind {
+    pub(crate) fn parse(kind: &str) -> Result<Self, String> {
+        match kind {
+            "i64" | "int" => Ok(Self::Int),
+            "f64" | "float" => Ok(Self::Float),
+            "bool" => Ok(Self::Bool),
+            other => Err(format!("Unknown lane kind: {other}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum LaneValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl LaneValue {
+    /// Reads a lane value from state; a missing or null value starts at zero
+    /// (or `false`), anything else of the wrong type is rejected.
+    pub(crate) fn from_json(kind: LaneKind, value: Option<&Value>) -> Result<Self, String> {
+        let value = value.filter(|value| !value.is_null());
+        match (kind, value) {
+            (LaneKind::Int, None) => Ok(Self::Int(0)),
+            (LaneKind::Float, None) => Ok(Self::Float(0.0)),
+            (LaneKind::Bool, None) => Ok(Self::Bool(false)),
+            (LaneKind::Int, Some(value)) => value.as_i64().map(Self::Int).ok_or("i64 lane requires an integer".to_string()),
+            (LaneKind::Float, Some(value)) => value.as_f64().map(Self::Float).ok_or("f64 lane requires a number".to_string()),
+            (LaneKind::Bool, Some(value)) => value.as_bool().map(Self::Bool).ok_or("bool lane requires a boolean".to_string()),
+        }
+    }
+
+    pub(crate) fn to_json(self) -> Value {
+        match self {
+            Self::Int(value) => Value::from(value),
+            Self::Float(value) => json_number(value),
+            Self::Bool(value) => Value::Bool(value),
+        }
+    }
+
+    /// Numeric view handed back across the wasm boundary; bools are 0 or 1.
+    pub(crate) fn as_f64(self) -> f64 {
+        match self {
+            Self::Int(value) => value as f64,
+            Self::Float(value) => value,
+            Self::Bool(value) => f64::from(u8::from(value)),
+        }
+    }
+
+    fn kind(self) -> LaneKind {
+        match self {
+            Self::Int(_) => LaneKind::Int,
+            Self::Float(_) => LaneKind::Float,
+            Self::Bool(_) => LaneKind::Bool,
+        }
+    }
+}
+
+/// A hot value bound to one store path, updated outside `Store.state` and
+/// written back lazily when the store is next read or dispatched to.
+pub(crate) struct Lane {
+    pub(crate) store_id: String,
+    pub(crate) path: String,
+    pub(crate) segments: Vec<String>,
+    value: LaneValue,
+    pending_updates: u32,
+    pending_dispatches: u32,
+    dirty: bool,
+    slot: Option<usize>,
+    seen_generation: i32,
+}
+
+impl Lane {
+    pub(crate) fn new(store_id: String, path: String, segments: Vec<String>, value: LaneValue) -> Self {
+        Self {
+            store_id,
+            path,
+            segments,
+            value,
+            pending_updates: 0,
+            pending_dispatches: 0,
+            dirty: false,
+            slot: None,
+            seen_generation: 0,
+        }
+    }
+
+    pub(crate) fn kind(&self) -> LaneKind {
+        self.value.kind()
+    }
+
+    pub(crate) fn value(&self) -> LaneValue {
+        self.value
+    }
+
+    /// Integer fast path used by counter handles.
+    pub(crate) fn add_int(&mut self, delta: i64, count: u32) -> Option<i64> {
+        let LaneValue::Int(value) = &mut self.value else {
+            return None;
+        };
+        *value += delta * i64::from(count);
+        let value = *value;
+        self.touch(count);
+        Some(value)
+    }
+
+    pub(crate) fn add(&mut self, delta: f64, count: u32) -> Result<f64, String> {
+        match &mut self.value {
+            LaneValue::Int(value) => {
+                if delta.fract() != 0.0 {
+                    return Err("i64 lane requires an integer delta".to_string());
+                }
+                *value += delta as i64 * i64::from(count);
+            }
+            LaneValue::Float(value) => *value += delta * f64::from(count),
+            LaneValue::Bool(_) => return Err("Cannot add to a bool lane".to_string()),
+        }
+        self.touch(count);
+        Ok(self.value.as_f64())
+    }
+
+    pub(crate) fn set(&mut self, next: f64) -> Result<f64, String> {
+        self.value = match self.value {
+            LaneValue::Int(_) => LaneValue::Int(whole_i64(next).ok_or("i64 lane requires an integer value")?),
+            LaneValue::Float(_) => LaneValue::Float(next),
+            LaneValue::Bool(_) => LaneValue::Bool(next != 0.0),
+        };
+        self.touch(1);
+        Ok(self.value.as_f64())
+    }
+
+    pub(crate) fn toggle(&mut self) -> Result<bool, String> {
+        let LaneValue::Bool(value) = &mut self.value else {
+            return Err("Only bool lanes can be toggled".to_string());
+        };
+        *value = !*value;
+        let value = *value;
+        self.touch(1);
+        Ok(value)
+    }
+
+    /// Returns the value to write back if it changed since the last flush.
+    pub(crate) fn take_dirty(&mut self) -> Option<Value> {
+        std::mem::take(&mut self.dirty).then(|| self.value.to_json())
+    }
+
+    /// Returns and clears the `(updates, dispatches)` not yet counted in
+    /// the store metrics.
+    pub(crate) fn take_pending(&mut self) -> (u32, u32) {
+        let pending = (self.pending_updates, self.pending_dispatches);
+        self.pending_updates = 0;
+        self.pending_dispatches = 0;
+        pending
+    }
+
+    pub(crate) fn pending(&self) -> (u32, u32) {
+        (self.pending_updates, self.pending_dispatches)
+    }
+
+    /// Re-reads the lane from committed state, dropping pending work.
+    pub(crate) fn reset(&mut self, state: Option<&Value>) {
+        if let Ok(value) = LaneValue::from_json(self.kind(), state) {
+            self.value = value;
+        }
+        self.pending_updates = 0;
+        self.pending_dispatches = 0;
+        self.dirty = false;
+    }
+
+    fn touch(&mut self, count: u32) {
+        self.pending_updates = self.pending_updates.saturating_add(count);
+        self.pending_dispatches = self.pending_dispatches.saturating_add(count);
+        self.dirty = true;
+    }
+}
+
+/// Lane values mirrored into linear memory so JS can read and write them
+/// through typed-array views without crossing the wasm boundary.
+///
+/// Slot `i` of the `Float64Array` at `values_ptr` holds the lane value (bools
+/// as 0/1, i64 exact up to 2^53) and slot `i` of the `Int32Array` at
+/// `generations_ptr` is bumped on every write. Writers on either side bump
+/// the generation; the other side reconciles when it sees a new one. Both
+/// vectors are allocated once, so the pointers stay valid until the buffer
+/// is reconfigured.
+pub(crate) struct LaneBuffer {
+    values: Vec<f64>,
+    generations: Vec<i32>,
+    used: Vec<bool>,
+}
+
+impl LaneBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            values: vec![0.0; capacity],
+            generations: vec![0; capacity],
+            used: vec![false; capacity],
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.values.len()
+    }
+
+    pub(crate) fn values_ptr(&self) -> usize {
+        self.values.as_ptr() as usize
+    }
+
+    pub(crate) fn generations_ptr(&self) -> usize {
+        self.generations.as_ptr() as usize
+    }
+
+    fn allocate(&mut self) -> Option<usize> {
+        let slot = self.used.iter().position(|used| !used)?;
+        self.used[slot] = true;
+        Some(slot)
+    }
+
+    fn release(&mut self, slot: usize) {
+        self.used[slot] = false;
+        self.values[slot] = 0.0;
+        self.generations[slot] = self.generations[slot].wrapping_add(1);
+    }
+
+    /// Adopts a value written from JS since the lane last looked. A value an
+    /// i64 lane cannot hold (fractional, NaN or out of range) is rejected as
+    /// `lane_set_fast` would reject it: the last good value is written back.
+    fn pull(&mut self, lane: &mut Lane) {
+        let Some(slot) = lane.slot else {
+            return;
+        };
+        let generation = self.generations[slot];
+        if generation == lane.seen_generation {
+            return;
+        }
+        let raw = self.values[slot];
+        lane.value = match lane.value {
+            LaneValue::Int(_) => match whole_i64(raw) {
+                Some(value) => LaneValue::Int(value),
+                None => {
+                    self.publish(lane);
+                    return;
+                }
+            },
+            LaneValue::Float(_) => LaneValue::Float(raw),
+            LaneValue::Bool(_) => LaneValue::Bool(raw != 0.0),
+        };
+        lane.seen_generation = generation;
+        lane.touch(1);
+    }
+
+    fn publish(&mut self, lane: &mut Lane) {
+        let Some(slot) = lane.slot else {
+            return;
+        };
+        self.values[slot] = lane.value.as_f64();
+        self.generations[slot] = self.generations[slot].wrapping_add(1);
+        lane.seen_generation = self.generations[slot];
+    }
+}
+
+/// `value` as an i64 lane value, when it is whole and in range.
+fn whole_i64(value: f64) -> Option<i64> {
+    (value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64).then_some(value as i64)
+}
+
+/// All lanes by handle, together with their shared-memory buffer.
+pub(crate) struct LaneTable {
+    lanes: HashMap<u32, Lane>,
+    buffer: LaneBuffer,
+    next_handle: u32,
+}
+
+impl LaneTable {
+    pub(crate) fn new() -> Self {
+        Self {
+            lanes: HashMap::new(),
+            buffer: LaneBuffer::new(DEFAULT_BUFFER_CAPACITY),
+            next_handle: 1,
+        }
+    }
+
+    pub(crate) fn buffer(&self) -> &LaneBuffer {
+        &self.buffer
+    }
+
+    /// Reallocates the buffer; only allowed while no lane holds a slot.
+    pub(crate) fn set_buffer_capacity(&mut self, capacity: usize) -> Result<(), String> {
+        if self.lanes.values().any(|lane| lane.slot.is_some()) {
+            return Err("Cannot resize the lane buffer while lanes are bound".to_string());
+        }
+        self.buffer = LaneBuffer::new(capacity);
+        Ok(())
+    }
+
+    /// Registers `lane`, giving it a buffer slot when one is free.
+    pub(crate) fn insert(&mut self, mut lane: Lane) -> u32 {
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.saturating_add(1).max(1);
+        lane.slot = self.buffer.allocate();
+        self.buffer.publish(&mut lane);
+        self.lanes.insert(handle, lane);
+        handle
+    }
+
+    pub(crate) fn remove(&mut self, handle: u32) -> Option<Lane> {
+        let mut lane = self.lanes.remove(&handle)?;
+        self.buffer.pull(&mut lane);
+        if let Some(slot) = lane.slot.take() {
+            self.buffer.release(slot);
+        }
+        Some(lane)
+    }
+
+    pub(crate) fn retain(&mut self, keep: impl Fn(&Lane) -> bool) {
+        let removed: Vec<u32> = self
+            .lanes
+            .iter()
+            .filter(|(_, lane)| !keep(lane))
+            .map(|(handle, _)| *handle)
+            .collect();
+        for handle in removed {
+            self.remove(handle);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.retain(|_| false);
+    }
+
+    pub(crate) fn get(&self, handle: u32) -> Option<&Lane> {
+        self.lanes.get(&handle)
+    }
+
+    pub(crate) fn slot(&self, handle: u32) -> Option<usize> {
+        self.lanes.get(&handle)?.slot
+    }
+
+    /// Runs `update` on a lane after adopting any JS-side write, then
+    /// republishes the value if it changed.
+    pub(crate) fn with_lane<R>(&mut self, handle: u32, update: impl FnOnce(&mut Lane) -> R) -> Option<R> {
+        let lane = self.lanes.get_mut(&handle)?;
+        self.buffer.pull(lane);
+        let before = lane.value;
+        let result = update(lane);
+        if lane.value != before {
+            self.buffer.publish(lane);
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lane(kind: LaneKind, value: Value) -> Lane {
+        let value = LaneValue::from_json(kind, Some(&value)).unwrap();
+        Lane::new("main".to_string(), "ui.value".to_string(), vec!["ui".to_string(), "value".to_string()], value)
+    }
+
+    #[test]
+    fn typed_lanes_update_and_report_dirty_values() {
+        let mut scroll = lane(LaneKind::Float, json!(1.5));
+        assert_eq!(scroll.add(0.25, 2).unwrap(), 2.0);
+        assert_eq!(scroll.take_dirty(), Some(json!(2.0)));
+        assert_eq!(scroll.take_dirty(), None);
+        assert_eq!(scroll.take_pending(), (2, 2));
+
+        let mut cursor = lane(LaneKind::Int, json!(3));
+        assert_eq!(cursor.add_int(2, 3), Some(9));
+        assert!(cursor.add(0.5, 1).is_err());
+        assert_eq!(cursor.set(4.0).unwrap(), 4.0);
+        assert!(cursor.set(4.5).is_err());
+        assert_eq!(cursor.take_dirty(), Some(json!(4)));
+
+        let mut muted = lane(LaneKind::Bool, Value::Null);
+        assert!(muted.toggle().unwrap());
+        assert!(muted.add(1.0, 1).is_err());
+        assert_eq!(muted.value().as_f64(), 1.0);
+    }
+
+    #[test]
+    fn buffer_writes_are_reconciled_by_generation() {
+        let mut table = LaneTable::new();
+        let handle = table.insert(lane(LaneKind::Float, json!(1.0)));
+        let slot = table.slot(handle).unwrap();
+        let generation = table.buffer.generations[slot];
+        assert_eq!(table.buffer.values[slot], 1.0);
+
+        table.buffer.values[slot] = 4.5;
+        table.buffer.generations[slot] += 1;
+        let value = table.with_lane(handle, |lane| lane.value()).unwrap();
+        assert_eq!(value, LaneValue::Float(4.5));
+
+        table.with_lane(handle, |lane| lane.add(0.5, 1)).unwrap().unwrap();
+        assert_eq!(table.buffer.values[slot], 5.0);
+        assert_eq!(table.buffer.generations[slot], generation + 2);
+        assert_eq!(table.with_lane(handle, |lane| lane.take_dirty()).unwrap(), Some(json!(5.0)));
+
+        assert!(table.set_buffer_capacity(8).is_err());
+        table.remove(handle);
+        assert!(table.set_buffer_capacity(8).is_ok());
+        assert_eq!(table.buffer().capacity(), 8);
+    }
+
+    #[test]
+    fn invalid_buffer_write_to_i64_lane_keeps_last_value() {
+        let mut table = LaneTable::new();
+        let handle = table.insert(lane(LaneKind::Int, json!(3)));
+        let slot = table.slot(handle).unwrap();
+
+        for raw in [2.5, f64::NAN, 1e20] {
+            table.buffer.values[slot] = raw;
+            table.buffer.generations[slot] += 1;
+            assert_eq!(table.with_lane(handle, |lane| lane.value()).unwrap(), LaneValue::Int(3));
+            assert_eq!(table.buffer.values[slot], 3.0);
+        }
+        assert_eq!(table.with_lane(handle, |lane| lane.take_dirty()).unwrap(), None);
+
+        table.buffer.values[slot] = 7.0;
+        table.buffer.generations[slot] += 1;
+        assert_eq!(table.with_lane(handle, |lane| lane.value()).unwrap(), LaneValue::Int(7));
+        assert!(table.with_lane(handle, |lane| lane.set(1e20)).unwrap().is_err());
+    }
+
+    #[test]
+    fn reset_reloads_committed_state() {
+        let mut volume = lane(LaneKind::Float, json!(0.5));
+        volume.add(0.1, 1).unwrap();
+        volume.reset(Some(&json!(0.8)));
+        assert_eq!(volume.value(), LaneValue::Float(0.8));
+        assert_eq!(volume.take_dirty(), None);
+        assert!(LaneValue::from_json(LaneKind::Bool, Some(&json!(1))).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+
+use crate::compatibility::{compare_versions, version_satisfies};
+use crate::store::parse_path;
+use crate::store_tree::StateTree;
+
+/// One versioned transform between two schema versions of a store.
+///
+/// `up` runs declarative ops (`rename`/`move`, `default`, `set`, `delete`)
+/// followed by the optional JS callback. A migration can be walked backwards
+/// when it has `down` ops, a down callback, or only `rename`/`move` ops.
+#[derive(Clone)]
+pub(crate) struct Migration {
+    from: String,
+    to: String,
+    up: Vec<Value>,
+    down: Option<Vec<Value>>,
+    up_callback: Option<js_sys::Function>,
+    down_callback: Option<js_sys::Function>,
+}
+
+pub(crate) struct MigrationStep {
+    pub(crate) from: String,
+    pub(crate) to: String,
+    pub(crate) ops: Vec<Value>,
+    pub(crate) callback: Option<js_sys::Function>,
+}
+
+impl Migration {
+    pub(crate) fn from_spec(
+        spec: &Value,
+        up_callback: Option<js_sys::Function>,
+        down_callback: Option<js_sys::Function>,
+    ) -> Result<Self, String> {
+        let version = |key: &str| {
+            spec.get(key)
+                .and_then(Value::as_str)
+                .map(ToString::to_string)
+                .ok_or_else(|| format!("Migration requires {key}"))
+        };
+        let ops = |key: &str| spec.get(key).and_then(Value::as_array).cloned();
+
+        let migration = Self {
+            from: version("from")?,
+            to: version("to")?,
+            up: ops("up").unwrap_or_default(),
+            down: ops("down"),
+            up_callback,
+            down_callback,
+        };
+        for op in migration.up.iter().chain(migration.down.iter().flatten()) {
+            validate_op(op)?;
+        }
+        Ok(migration)
+    }
+
+    pub(crate) fn same_edge(&self, other: &Migration) -> bool {
+        self.from == other.from && self.to == other.to
+    }
+
+    fn down_ops(&self) -> Option<Vec<Value>> {
+        if let Some(down) = &self.down {
+            return Some(down.clone());
+        }
+        let inverted: Option<Vec<Value>> = self
+            .up
+            .iter()
+            .rev()
+            .map(|op| match op.get("op").and_then(Value::as_str) {
+                Some("rename" | "move") => Some(serde_json::json!({
+                    "op": "move",
+                    "from": op.get("to")?,
+                    "to": op.get("from")?,
+                })),
+                _ => None,
+            })
+            .collect();
+        match inverted {
+            Some(ops) if self.up_callback.is_none() || self.down_callback.is_some() => Some(ops),
+            None if self.down_callback.is_some() => Some(Vec::new()),
+            _ => None,
+        }
+    }
+
+    fn up_step(&self) -> MigrationStep {
+        MigrationStep {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            ops: self.up.clone(),
+            callback: self.up_callback.clone(),
+        }
+    }
+
+    fn down_step(&self) -> Option<MigrationStep> {
+        Some(MigrationStep {
+            from: self.to.clone(),
+            to: self.from.clone(),
+            ops: self.down_ops()?,
+            callback: self.down_callback.clone(),
+        })
+    }
+}
+
+/// Finds the shortest chain of migrations from `from` to `target`.
+///
+/// `target` is either an exact version or a range accepted by
+/// `version_satisfies`; for ranges the highest reachable version wins.
+pub(crate) fn plan_migration(migrations: &[Migration], from: &str, target: &str) -> Result<Vec<MigrationStep>, String> {
+    let mut previous: HashMap<String, (String, usize, bool)> = HashMap::new();
+    let mut queue = VecDeque::from([from.to_string()]);
+    let mut reachable = vec![from.to_string()];
+
+    while let Some(version) = queue.pop_front() {
+        for (index, migration) in migrations.iter().enumerate() {
+            let edge = if migration.from == version {
+                Some((migration.to.clone(), true))
+            } else if migration.to == version && migration.down_ops().is_some() {
+                Some((migration.from.clone(), false))
+            } else {
+                None
+            };
+            let Some((next, upgrade)) = edge else {
+                continue;
+            };
+            if next == from || previous.contains_key(&next) {
+                continue;
+            }
+            previous.insert(next.clone(), (version.clone(), index, upgrade));
+            reachable.push(next.clone());
+            queue.push_back(next);
+        }
+    }
+
+    let destination = if reachable.iter().any(|version| version == target) {
+        target.to_string()
+    } else {
+        reachable
+            .into_iter()
+            .filter(|version| version_satisfies(version, target))
+            .max_by(|left, right| compare_versions(left, right))
+            .ok_or_else(|| format!("No migration path from {from} to {target}"))?
+    };
+
+    let mut steps = Vec::new();
+    let mut current = destination;
+    while let Some((parent, index, upgrade)) = previous.get(&current) {
+        let migration = &migrations[*index];
+        let step = if *upgrade {
+            migration.up_step()
+        } else {
+            migration.down_step().ok_or("Migration cannot be reversed")?
+        };
+        steps.push(step);
+        current = parent.clone();
+    }
+    steps.reverse();
+    Ok(steps)
+}
+
+pub(crate) fn apply_migration_ops(state: &mut StateTree, ops: &[Value]) -> Result<(), String> {
+    for op in ops {
+        let path = |key: &str| {
+            op.get(key)
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("Migration op requires {key}"))
+                .and_then(parse_path)
+        };
+        match op.get("op").and_then(Value::as_str).unwrap_or("") {
+            "rename" | "move" => {
+                let from = path("from")?;
+                let Some(value) = state.get(&from).cloned() else {
+                    continue;
+                };
+                state.remove(&from);
+                state.set(&path("to")?, value)?;
+            }
+            "default" => {
+                let target = path("path")?;
+                if state.get(&target).is_none() {
+                    state.set(&target, op.get("value").cloned().unwrap_or(Value::Null).into())?;
+                }
+            }
+            "set" => state.set(&path("path")?, op.get("value").cloned().unwrap_or(Value::Null).into())?,
+            "delete" => state.remove(&path("path")?),
+            other => return Err(format!("Unknown migration op: {other}")),
+        }
+    }
+    Ok(())
+}
+
+fn validate_op(op: &Value) -> Result<(), String> {
+    match op.get("op").and_then(Value::as_str) {
+        Some("rename" | "move" | "default" | "set" | "delete") => Ok(()),
+        Some(other) => Err(format!("Unknown migration op: {other}")),
+        None => Err("Migration op requires op".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn migration(spec: Value) -> Migration {
+        Migration::from_spec(&spec, None, None).unwrap()
+    }
+
+    #[test]
+    fn upgrade_chains_declarative_ops() {
+        let migrations = vec![
+            migration(json!({
+                "from": "1.2.0",
+                "to": "2.0.0",
+                "up": [
+                    { "op": "rename", "from": "items", "to": "lines" },
+                    { "op": "default", "path": "currency", "value": "KRW" }
+                ]
+            })),
+            migration(json!({
+                "from": "2.0.0",
+                "to": "2.1.0",
+                "up": [{ "op": "delete", "path": "legacy" }]
+            })),
+        ];
+
+        let steps = plan_migration(&migrations, "1.2.0", "^2.0.0").unwrap();
+        assert_eq!(steps.last().unwrap().to, "2.1.0");
+
+        let mut state = StateTree::from(json!({ "items": [1], "legacy": true }));
+        for step in &steps {
+            apply_migration_ops(&mut state, &step.ops).unwrap();
+        }
+        assert_eq!(state, json!({ "lines": [1], "currency": "KRW" }));
+    }
+
+    #[test]
+    fn rename_only_migration_can_downgrade() {
+        let migrations = vec![
+            migration(json!({
+                "from": "1.0.0",
+                "to": "2.0.0",
+                "up": [{ "op": "rename", "from": "user.name", "to": "user.fullName" }]
+            })),
+            migration(json!({
+                "from": "2.0.0",
+                "to": "3.0.0",
+                "up": [{ "op": "set", "path": "flag", "value": true }]
+            })),
+        ];
+
+        let steps = plan_migration(&migrations, "2.0.0", "1.0.0").unwrap();
+        let mut state = StateTree::from(json!({ "user": { "fullName": "A" } }));
+        apply_migration_ops(&mut state, &steps[0].ops).unwrap();
+        assert_eq!(state, json!({ "user": { "name": "A" } }));
+
+        assert!(plan_migration(&migrations, "3.0.0", "2.0.0").is_err());
+    }
+}
@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::json_patch::{apply_patch, diff};
+use crate::store_tree::StateTree;
+
+const DEFAULT_THROTTLE_MS: f64 = 1000.0;
+const DEFAULT_COMPACT_EVERY: u32 = 50;
+
+/// One write handed to the storage adapter. A `base` record supersedes every
+/// earlier record for its key; `delta` records apply on top in sequence.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub(crate) enum PersistenceRecord {
+    Base {
+        key: String,
+        sequence: u64,
+        timestamp: f64,
+        state: StateTree,
+    },
+    Delta {
+        key: String,
+        sequence: u64,
+        timestamp: f64,
+        patch: Vec<Value>,
+    },
+}
+
+impl PersistenceRecord {
+    fn sequence(&self) -> u64 {
+        match self {
+            Self::Base { sequence, .. } | Self::Delta { sequence, .. } => *sequence,
+        }
+    }
+}
+
+/// Write-behind tracker for a persistent store. Changes are diffed against
+/// the last written projection, so commits inside one throttle window
+/// coalesce into a single delta, written when the window closes.
+pub(crate) struct StorePersistence {
+    key: String,
+    paths: Vec<Vec<String>>,
+    throttle_ms: f64,
+    compact_every: u32,
+    persisted: Option<StateTree>,
+    sequence: u64,
+    deltas_since_base: u32,
+    last_write: Option<f64>,
+    deferred: bool,
+    trailing_due: Option<f64>,
+}
+
+impl StorePersistence {
+    pub(crate) fn new(key: String, paths: Vec<Vec<String>>, throttle_ms: Option<f64>, compact_every: Option<u32>) -> Self {
+        Self {
+            key,
+            paths,
+            throttle_ms: throttle_ms.unwrap_or(DEFAULT_THROTTLE_MS).max(0.0),
+            compact_every: compact_every.unwrap_or(DEFAULT_COMPACT_EVERY).max(1),
+            persisted: None,
+            sequence: 0,
+            deltas_since_base: 0,
+            last_write: None,
+            deferred: false,
+            trailing_due: None,
+        }
+    }
+
+    /// Returns the next record to write, or `None` when nothing changed or
+    /// the throttle window is still open. `force` ignores the window and is
+    /// how the trailing write runs.
+    pub(crate) fn poll(&mut self, state: &StateTree, now: f64, force: bool) -> Option<PersistenceRecord> {
+        if force {
+            self.trailing_due = None;
+        } else if self.last_write.is_some_and(|last| now - last < self.throttle_ms) {
+            self.deferred = true;
+            return None;
+        }
+        self.deferred = false;
+        let projected = self.project(state);
+        let patch = match self.persisted.as_ref() {
+            Some(persisted) if *persisted == projected => return None,
+            Some(persisted) if self.deltas_since_base < self.compact_every => Some(diff(persisted, &projected)),
+            _ => None,
+        };
+        Some(self.emit(projected, patch, now))
+    }
+
+    /// Milliseconds until a change held back by the throttle window can be
+    /// written, when a trailing write has to be scheduled for it; `None`
+    /// when nothing is held back or a trailing write is already pending.
+    pub(crate) fn take_trailing_delay(&mut self, now: f64) -> Option<f64> {
+        if !self.deferred || self.trailing_due.is_some() {
+            return None;
+        }
+        let due = self.last_write? + self.throttle_ms;
+        self.trailing_due = Some(due);
+        Some((due - now).max(0.0))
+    }
+
+    /// Writes a fresh base record so the adapter can drop older deltas.
+    pub(crate) fn compact(&mut self, state: &StateTree, now: f64) -> PersistenceRecord {
+        let projected = self.project(state);
+        self.emit(projected, None, now)
+    }
+
+    /// Rebuilds the persisted projection from `records` and lays it over
+    /// `state`. Later writes continue the stored sequence.
+    pub(crate) fn rehydrate(&mut self, state: &StateTree, records: &[PersistenceRecord]) -> Result<StateTree, String> {
+        let (persisted, sequence, deltas) = replay_records(&self.key, records)?;
+        let mut next = state.clone();
+        if self.paths.is_empty() {
+            next = persisted.clone();
+        } else {
+            for segments in &self.paths {
+                if let Some(value) = persisted.get(segments) {
+                    next.set(segments, value.clone())?;
+                }
+            }
+        }
+        self.persisted = Some(self.project(&next));
+        self.sequence = sequence;
+        self.deltas_since_base = deltas;
+        Ok(next)
+    }
+
+    fn emit(&mut self, projected: StateTree, patch: Option<Vec<Value>>, now: f64) -> PersistenceRecord {
+        self.deferred = false;
+        self.trailing_due = None;
+        self.sequence += 1;
+        self.last_write = Some(now);
+        let record = match patch {
+            Some(patch) => {
+                self.deltas_since_base += 1;
+                PersistenceRecord::Delta {
+                    key: self.key.clone(),
+                    sequence: self.sequence,
+                    timestamp: now,
+                    patch,
+                }
+            }
+            None => {
+                self.deltas_since_base = 0;
+                PersistenceRecord::Base {
+                    key: self.key.clone(),
+                    sequence: self.sequence,
+                    timestamp: now,
+                    state: projected.clone(),
+                }
+            }
+        };
+        self.persisted = Some(projected);
+        record
+    }
+
+    /// The part of `state` covered by the path allowlist, sharing its nodes.
+    fn project(&self, state: &StateTree) -> StateTree {
+        if self.paths.is_empty() {
+            return state.clone();
+        }
+        let mut projected = StateTree::object();
+        for segments in &self.paths {
+            if let Some(value) = state.get(segments) {
+                let _ = projected.set(segments, value.clone());
+            }
+        }
+        projected
+    }
+}
+
+/// Applies the last base record for `key` and every delta after it,
+/// returning the state, its sequence and how many deltas followed the base.
+fn replay_records(key: &str, records: &[PersistenceRecord]) -> Result<(StateTree, u64, u32), String> {
+    let mut records: Vec<&PersistenceRecord> = records
+        .iter()
+        .filter(|record| match record {
+            PersistenceRecord::Base { key: record_key, .. } | PersistenceRecord::Delta { key: record_key, .. } => {
+                record_key == key
+            }
+        })
+        .collect();
+    records.sort_by_key(|record| record.sequence());
+    let base_index = records
+        .iter()
+        .rposition(|record| matches!(record, PersistenceRecord::Base { .. }))
+        .ok_or_else(|| format!("No base record stored for {key}"))?;
+
+    let PersistenceRecord::Base { state, sequence, .. } = records[base_index] else {
+        unreachable!("rposition matched a base record");
+    };
+    let mut state = state.clone();
+    let mut sequence = *sequence;
+    let mut deltas = 0;
+    for record in &records[base_index + 1..] {
+        let PersistenceRecord::Delta { sequence: next, patch, .. } = record else {
+            continue;
+        };
+        if *next != sequence + 1 {
+            return Err(format!("Persistence records for {key} skip from {sequence} to {next}"));
+        }
+        apply_patch(&mut state, patch)?;
+        sequence = *next;
+        deltas += 1;
+    }
+    Ok((state, sequence, deltas))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Stand-in for a storage adapter: a base record replaces everything.
+    #[derive(Default)]
+    struct MemoryAdapter {
+        records: Vec<PersistenceRecord>,
+    }
+
+    impl MemoryAdapter {
+        fn write(&mut self, record: PersistenceRecord) {
+            if matches!(record, PersistenceRecord::Base { .. }) {
+                self.records.clear();
+            }
+            let encoded = serde_json::to_value(&record).unwrap();
+            self.records.push(serde_json::from_value(encoded).unwrap());
+        }
+    }
+
+    fn paths(paths: &[&str]) -> Vec<Vec<String>> {
+        paths.iter().map(|path| path.split('.').map(ToString::to_string).collect()).collect()
+    }
+
+    #[test]
+    fn throttled_changes_coalesce_into_one_delta() {
+        let mut persistence = StorePersistence::new("main".to_string(), Vec::new(), Some(100.0), None);
+        let mut adapter = MemoryAdapter::default();
+        let state = StateTree::from(json!({ "count": 0, "todos": [] }));
+        adapter.write(persistence.poll(&state, 0.0, false).unwrap());
+
+        let state = StateTree::from(json!({ "count": 1, "todos": [] }));
+        assert!(persistence.poll(&state, 50.0, false).is_none());
+        let state = StateTree::from(json!({ "count": 2, "todos": ["a"] }));
+        let record = persistence.poll(&state, 120.0, false).unwrap();
+        let PersistenceRecord::Delta { sequence, ref patch, .. } = record else {
+            panic!("expected a delta record");
+        };
+        assert_eq!(sequence, 2);
+        assert_eq!(patch.len(), 2);
+        adapter.write(record);
+        assert!(persistence.poll(&state, 500.0, false).is_none());
+
+        let mut restored = StorePersistence::new("main".to_string(), Vec::new(), None, None);
+        let rehydrated = restored.rehydrate(&StateTree::object(), &adapter.records).unwrap();
+        assert_eq!(rehydrated, json!({ "count": 2, "todos": ["a"] }));
+        let next = StateTree::from(json!({ "count": 3, "todos": ["a"] }));
+        assert!(matches!(restored.poll(&next, 0.0, false), Some(PersistenceRecord::Delta { sequence: 3, .. })));
+    }
+
+    #[test]
+    fn change_inside_the_window_is_written_when_it_closes() {
+        let mut persistence = StorePersistence::new("main".to_string(), Vec::new(), Some(100.0), None);
+        let mut adapter = MemoryAdapter::default();
+        adapter.write(persistence.poll(&json!({ "count": 0 }).into(), 0.0, false).unwrap());
+        assert!(persistence.take_trailing_delay(0.0).is_none());
+
+        let last = StateTree::from(json!({ "count": 1 }));
+        assert!(persistence.poll(&last, 30.0, false).is_none());
+        assert_eq!(persistence.take_trailing_delay(30.0), Some(70.0));
+        assert!(persistence.poll(&last, 60.0, false).is_none());
+        assert!(persistence.take_trailing_delay(60.0).is_none());
+
+        adapter.write(persistence.poll(&last, 100.0, true).unwrap());
+        assert!(persistence.take_trailing_delay(100.0).is_none());
+        let mut restored = StorePersistence::new("main".to_string(), Vec::new(), None, None);
+        assert_eq!(restored.rehydrate(&StateTree::object(), &adapter.records).unwrap(), json!({ "count": 1 }));
+    }
+
+    #[test]
+    fn each_window_gets_its_own_trailing_write() {
+        let mut persistence = StorePersistence::new("main".to_string(), Vec::new(), Some(100.0), None);
+        persistence.poll(&json!({ "count": 0 }).into(), 0.0, false).unwrap();
+        assert!(persistence.poll(&json!({ "count": 1 }).into(), 10.0, false).is_none());
+        assert_eq!(persistence.take_trailing_delay(10.0), Some(90.0));
+
+        persistence.poll(&json!({ "count": 2 }).into(), 120.0, false).unwrap();
+        assert!(persistence.poll(&json!({ "count": 3 }).into(), 130.0, false).is_none());
+        assert_eq!(persistence.take_trailing_delay(130.0), Some(90.0));
+    }
+
+    #[test]
+    fn allowlist_limits_written_and_restored_paths() {
+        let mut persistence = StorePersistence::new("prefs".to_string(), paths(&["user.theme"]), Some(0.0), None);
+        let mut adapter = MemoryAdapter::default();
+        let state = StateTree::from(json!({ "user": { "theme": "dark", "token": "secret" }, "count": 1 }));
+        let record = persistence.poll(&state, 0.0, false).unwrap();
+        let PersistenceRecord::Base { state: ref written, .. } = record else {
+            panic!("expected a base record");
+        };
+        assert_eq!(*written, json!({ "user": { "theme": "dark" } }));
+        adapter.write(record);
+
+        let changed = StateTree::from(json!({ "user": { "theme": "dark", "token": "rotated" }, "count": 2 }));
+        assert!(persistence.poll(&changed, 1.0, false).is_none());
+
+        let mut restored = StorePersistence::new("prefs".to_string(), paths(&["user.theme"]), None, None);
+        let current = StateTree::from(json!({ "user": { "theme": "light", "token": "fresh" }, "count": 0 }));
+        let rehydrated = restored.rehydrate(&current, &adapter.records).unwrap();
+        assert_eq!(rehydrated, json!({ "user": { "theme": "dark", "token": "fresh" }, "count": 0 }));
+    }
+
+    #[test]
+    fn compaction_writes_a_base_after_enough_deltas() {
+        let mut persistence = StorePersistence::new("main".to_string(), Vec::new(), Some(0.0), Some(2));
+        let mut adapter = MemoryAdapter::default();
+        for count in 0..4 {
+            adapter.write(persistence.poll(&json!({ "count": count }).into(), f64::from(count), false).unwrap());
+        }
+        assert_eq!(adapter.records.len(), 1);
+        assert!(matches!(adapter.records[0], PersistenceRecord::Base { sequence: 4, .. }));
+        assert_eq!(replay_records("main", &adapter.records).unwrap().0, json!({ "count": 3 }));
+    }
+
+    #[test]
+    fn sequence_gaps_are_rejected() {
+        let mut persistence = StorePersistence::new("main".to_string(), Vec::new(), Some(0.0), None);
+        let base = persistence.poll(&json!({ "count": 0 }).into(), 0.0, false).unwrap();
+        persistence.poll(&json!({ "count": 1 }).into(), 1.0, false).unwrap();
+        let late = persistence.poll(&json!({ "count": 2 }).into(), 2.0, false).unwrap();
+
+        let error = replay_records("main", &[base, late]).unwrap_err();
+        assert_eq!(error, "Persistence records for main skip from 1 to 3");
+        assert_eq!(replay_records("other", &[]).unwrap_err(), "No base record stored for other");
+    }
+}
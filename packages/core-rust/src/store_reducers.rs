@@ -0,0 +1,259 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::store::{parse_path, select_value, ArrayMergeStrategy};
+use crate::store_tree::StateTree;
+use crate::{from_js, json_number, to_js};
+
+const PAYLOAD_REF: &str = "$payload";
+
+enum OpKind {
+    Set,
+    Inc,
+    Toggle,
+    Push,
+}
+
+/// A literal value, or a reference into the action payload written as
+/// `"$payload"` or `"$payload.some.path"`.
+enum Operand {
+    Literal(Value),
+    Payload(Vec<String>),
+}
+
+impl Operand {
+    fn parse(value: Option<&Value>) -> Result<Option<Self>, String> {
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        let operand = match value.as_str() {
+            Some(PAYLOAD_REF) => Operand::Payload(Vec::new()),
+            Some(reference) if reference.starts_with("$payload.") => {
+                Operand::Payload(parse_path(&reference[PAYLOAD_REF.len() + 1..])?)
+            }
+            _ => Operand::Literal(value.clone()),
+        };
+        Ok(Some(operand))
+    }
+
+    fn resolve(&self, payload: &Value) -> Value {
+        match self {
+            Operand::Literal(value) => value.clone(),
+            Operand::Payload(segments) => select_value(payload, segments).cloned().unwrap_or(Value::Null),
+        }
+    }
+}
+
+struct ReducerOp {
+    kind: OpKind,
+    path: Vec<String>,
+    operand: Option<Operand>,
+}
+
+enum Reducer {
+    Ops(Vec<ReducerOp>),
+    Function(js_sys::Function),
+}
+
+/// Named reducers registered on a store, plus whether unknown actions fail
+/// and how `DEEP_MERGE` combines arrays.
+#[derive(Default)]
+pub(crate) struct ActionReducers {
+    reducers: HashMap<String, Reducer>,
+    strict: bool,
+    array_merge: ArrayMergeStrategy,
+}
+
+impl ActionReducers {
+    /// Registers `spec` (`[{ op, path, value?, by? }]` or `{ ops: [...] }`)
+    /// for `action_type`, or `function` when given. Functions receive
+    /// `(state, payload)` and return the next state. A plain dispatch runs
+    /// them with the stores released, so they may read any store; inside a
+    /// BATCH, transaction or replay, calling back into a store fails with a
+    /// "Stores are busy" error.
+    pub(crate) fn register(
+        &mut self,
+        action_type: &str,
+        spec: &Value,
+        function: Option<js_sys::Function>,
+    ) -> Result<(), String> {
+        let reducer = match function {
+            Some(function) => Reducer::Function(function),
+            None => Reducer::Ops(compile_ops(spec)?),
+        };
+        self.reducers.insert(action_type.to_string(), reducer);
+        Ok(())
+    }
+
+    pub(crate) fn remove(&mut self, action_type: &str) -> bool {
+        self.reducers.remove(action_type).is_some()
+    }
+
+    pub(crate) fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub(crate) fn set_array_merge(&mut self, strategy: ArrayMergeStrategy) {
+        self.array_merge = strategy;
+    }
+
+    pub(crate) fn array_merge(&self) -> &ArrayMergeStrategy {
+        &self.array_merge
+    }
+
+    /// The JS function registered for `action_type`, if that is what it is.
+    pub(crate) fn function(&self, action_type: &str) -> Option<js_sys::Function> {
+        match self.reducers.get(action_type) {
+            Some(Reducer::Function(function)) => Some(function.clone()),
+            _ => None,
+        }
+    }
+
+    /// Runs the reducer registered for `action_type`. Unknown actions are a
+    /// no-op unless the store is strict.
+    pub(crate) fn apply(&self, state: &mut StateTree, action_type: &str, payload: &Value) -> Result<(), String> {
+        match self.reducers.get(action_type) {
+            Some(Reducer::Ops(ops)) => ops.iter().try_for_each(|op| apply_op(state, op, payload)),
+            Some(Reducer::Function(function)) => {
+                *state = call_function(function, action_type, state, payload)?;
+                Ok(())
+            }
+            None if self.strict => Err(format!("Unknown action type: {action_type}")),
+            None => Ok(()),
+        }
+    }
+}
+
+pub(crate) fn call_function(
+    function: &js_sys::Function,
+    action_type: &str,
+    state: &StateTree,
+    payload: &Value,
+) -> Result<StateTree, String> {
+    let state_js = to_js(state).map_err(|error| format!("{:?}", error))?;
+    let payload_js = to_js(payload).map_err(|error| format!("{:?}", error))?;
+    let next = function
+        .call2(&wasm_bindgen::JsValue::NULL, &state_js, &payload_js)
+        .map_err(|error| format!("Reducer {action_type} failed: {:?}", error))?;
+    Ok(from_js(next).map_err(|error| format!("{:?}", error))?.into())
+}
+
+fn compile_ops(spec: &Value) -> Result<Vec<ReducerOp>, String> {
+    let ops = spec
+        .as_array()
+        .or_else(|| spec.get("ops").and_then(Value::as_array))
+        .ok_or("Reducer spec requires an ops array")?;
+
+    ops.iter()
+        .map(|op| {
+            let kind = match op.get("op").and_then(Value::as_str) {
+                Some("set") => OpKind::Set,
+                Some("inc") => OpKind::Inc,
+                Some("toggle") => OpKind::Toggle,
+                Some("push") => OpKind::Push,
+                Some(other) => return Err(format!("Unknown reducer op: {other}")),
+                None => return Err("Reducer op requires op".to_string()),
+            };
+            let path = op
+                .get("path")
+                .and_then(Value::as_str)
+                .ok_or("Reducer op requires path")
+                .map_err(ToString::to_string)
+                .and_then(parse_path)?;
+            let operand = Operand::parse(op.get("value").or_else(|| op.get("by")))?;
+            if matches!(kind, OpKind::Set | OpKind::Push) && operand.is_none() {
+                return Err("set and push require value".to_string());
+            }
+            Ok(ReducerOp { kind, path, operand })
+        })
+        .collect()
+}
+
+fn apply_op(state: &mut StateTree, op: &ReducerOp, payload: &Value) -> Result<(), String> {
+    let current = state.get(&op.path);
+    let operand = op.operand.as_ref().map(|operand| operand.resolve(payload));
+    let next = match op.kind {
+        OpKind::Set => operand.unwrap_or(Value::Null).into(),
+        OpKind::Inc => {
+            let by = operand.unwrap_or(Value::from(1));
+            let current = match current {
+                Some(StateTree::Number(number)) => Value::Number(number.clone()),
+                None => Value::from(0),
+                Some(_) => return Err("inc requires numbers".to_string()),
+            };
+            let next = match (current.as_i64(), by.as_i64()) {
+                (Some(current), Some(by)) => Value::from(current.checked_add(by).ok_or("inc overflows a 64-bit integer")?),
+                _ => match (current.as_f64(), by.as_f64()) {
+                    (Some(current), Some(by)) => json_number(current + by),
+                    _ => return Err("inc requires numbers".to_string()),
+                },
+            };
+            next.into()
+        }
+        OpKind::Toggle => match current {
+            Some(StateTree::Bool(flag)) => StateTree::Bool(!flag),
+            None | Some(StateTree::Null) => StateTree::Bool(true),
+            Some(_) => return Err("toggle requires a boolean".to_string()),
+        },
+        OpKind::Push => {
+            let mut items = match current {
+                Some(StateTree::Array(items)) => items.clone(),
+                None | Some(StateTree::Null) => Default::default(),
+                Some(_) => return Err("push requires an array".to_string()),
+            };
+            items.push_back(operand.unwrap_or(Value::Null).into());
+            StateTree::Array(items)
+        }
+    };
+    state.set(&op.path, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn declarative_reducer_applies_ops_with_payload_refs() {
+        let mut reducers = ActionReducers::default();
+        reducers
+            .register("ADD_TODO", &json!([
+                { "op": "push", "path": "todos", "value": "$payload.todo" },
+                { "op": "inc", "path": "stats.total" },
+                { "op": "inc", "path": "stats.points", "by": "$payload.points" },
+                { "op": "toggle", "path": "dirty" },
+                { "op": "set", "path": "lastAdded", "value": "$payload.todo.title" }
+            ]), None)
+            .unwrap();
+
+        let mut state = StateTree::from(json!({ "todos": [], "stats": { "total": 1, "points": 0.5 } }));
+        let payload = json!({ "todo": { "title": "A" }, "points": 2 });
+        reducers.apply(&mut state, "ADD_TODO", &payload).unwrap();
+        assert_eq!(state, json!({
+            "todos": [{ "title": "A" }],
+            "stats": { "total": 2, "points": 2.5 },
+            "dirty": true,
+            "lastAdded": "A"
+        }));
+    }
+
+    #[test]
+    fn unknown_actions_fail_only_in_strict_mode() {
+        let mut reducers = ActionReducers::default();
+        let mut state = StateTree::object();
+        assert!(reducers.apply(&mut state, "TYPO", &Value::Null).is_ok());
+
+        reducers.set_strict(true);
+        assert_eq!(reducers.apply(&mut state, "TYPO", &Value::Null).unwrap_err(), "Unknown action type: TYPO");
+        assert!(reducers.register("BAD", &json!([{ "op": "mul", "path": "x" }]), None).is_err());
+    }
+
+    #[test]
+    fn inc_rejects_integer_overflow() {
+        let mut reducers = ActionReducers::default();
+        reducers.register("BUMP", &json!([{ "op": "inc", "path": "count" }]), None).unwrap();
+        let mut state = StateTree::from(json!({ "count": i64::MAX }));
+        assert!(reducers.apply(&mut state, "BUMP", &Value::Null).unwrap_err().contains("overflow"));
+        assert_eq!(state, json!({ "count": i64::MAX }));
+    }
+}
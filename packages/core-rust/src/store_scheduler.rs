@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use crate::store_tree::StateTree;
+
+/// When subscriber callbacks run relative to the commit that triggered them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum NotificationMode {
+    /// Inside the committing call, as each commit lands.
+    Sync,
+    /// Queued until `flush_notifications` is called.
+    Batched,
+    /// Queued, with a flush handed to a JS scheduler such as `queueMicrotask`.
+    Deferred,
+}
+
+impl NotificationMode {
+    pub(crate) fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "sync" => Ok(Self::Sync),
+            "batched" => Ok(Self::Batched),
+            "deferred" => Ok(Self::Deferred),
+            other => Err(format!("Unknown notification mode: {other}")),
+        }
+    }
+}
+
+pub(crate) struct PendingNotification<C> {
+    pub(crate) callback: C,
+    pub(crate) next: StateTree,
+    pub(crate) previous: StateTree,
+}
+
+/// Subscriber notifications waiting for a flush, one per subscription. A
+/// repeat keeps the value the subscriber last saw as `previous` and takes
+/// the newest `next`, so a burst of commits delivers only the final value.
+pub(crate) struct NotificationQueue<C> {
+    order: Vec<String>,
+    pending: HashMap<String, PendingNotification<C>>,
+    scheduled: bool,
+}
+
+impl<C> NotificationQueue<C> {
+    pub(crate) fn new() -> Self {
+        Self {
+            order: Vec::new(),
+            pending: HashMap::new(),
+            scheduled: false,
+        }
+    }
+
+    /// Returns `true` when no flush is scheduled yet, i.e. the caller should
+    /// schedule one.
+    pub(crate) fn push(&mut self, subscription_id: String, callback: C, next: StateTree, previous: StateTree) -> bool {
+        match self.pending.get_mut(&subscription_id) {
+            Some(pending) => {
+                pending.callback = callback;
+                pending.next = next;
+            }
+            None => {
+                self.order.push(subscription_id.clone());
+                self.pending.insert(subscription_id, PendingNotification { callback, next, previous });
+            }
+        }
+        !std::mem::replace(&mut self.scheduled, true)
+    }
+
+    /// Forgets the scheduled flush after the scheduler failed to take it, so
+    /// the next push schedules again.
+    pub(crate) fn unschedule(&mut self) {
+        self.scheduled = false;
+    }
+
+    /// Empties the queue in first-queued order, keeping the notifications
+    /// `deliver` accepts. It sees each coalesced notification once, at flush
+    /// time, so it can apply the subscription's current equality and state.
+    pub(crate) fn drain(
+        &mut self,
+        mut deliver: impl FnMut(&str, &PendingNotification<C>) -> bool,
+    ) -> Vec<(String, PendingNotification<C>)> {
+        self.scheduled = false;
+        let mut pending = std::mem::take(&mut self.pending);
+        std::mem::take(&mut self.order)
+            .into_iter()
+            .filter_map(|subscription_id| {
+                let notification = pending.remove(&subscription_id)?;
+                deliver(&subscription_id, &notification).then_some((subscription_id, notification))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store_selector::Equality;
+    use serde_json::json;
+
+    fn tree(value: serde_json::Value) -> StateTree {
+        StateTree::from(value)
+    }
+
+    #[test]
+    fn burst_coalesces_to_final_value_per_subscription() {
+        let mut queue = NotificationQueue::new();
+        assert!(queue.push("sub_a".to_string(), "a", tree(json!(1)), tree(json!(0))));
+        assert!(!queue.push("sub_b".to_string(), "b", tree(json!("x")), tree(json!(null))));
+        for count in 2..=50 {
+            queue.push("sub_a".to_string(), "a", tree(json!(count)), tree(json!(count - 1)));
+        }
+
+        let drained = queue.drain(|_, _| true);
+        assert_eq!(drained.len(), 2);
+        assert_eq!(drained[0].0, "sub_a");
+        assert_eq!(drained[0].1.previous, json!(0));
+        assert_eq!(drained[0].1.next, json!(50));
+        assert_eq!(drained[1].0, "sub_b");
+        assert!(queue.drain(|_, _| true).is_empty());
+        assert!(queue.push("sub_a".to_string(), "a", tree(json!(51)), tree(json!(50))));
+    }
+
+    #[test]
+    fn drain_filter_sees_the_coalesced_change() {
+        let mut queue = NotificationQueue::new();
+        queue.push("sub_a".to_string(), (), tree(json!({ "open": true })), tree(json!({ "open": false })));
+        queue.push("sub_a".to_string(), (), tree(json!({ "open": false })), tree(json!({ "open": true })));
+        queue.push("sub_b".to_string(), (), tree(json!({ "id": 1, "at": 2 })), tree(json!({ "id": 1, "at": 1 })));
+        let keys = Equality::Keys(vec![vec!["id".to_string()]]);
+        let drained = queue.drain(|_, pending| !keys.equal(&pending.previous, &pending.next));
+        assert!(drained.is_empty());
+    }
+
+    #[test]
+    fn failed_schedule_is_retried_on_next_push() {
+        let mut queue = NotificationQueue::new();
+        assert!(queue.push("sub_a".to_string(), (), tree(json!(1)), tree(json!(0))));
+        assert!(!queue.push("sub_a".to_string(), (), tree(json!(2)), tree(json!(0))));
+        queue.unschedule();
+        assert!(queue.push("sub_b".to_string(), (), tree(json!(1)), tree(json!(0))));
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!(NotificationMode::parse("batched").unwrap(), NotificationMode::Batched);
+        assert_eq!(NotificationMode::parse("later").unwrap_err(), "Unknown notification mode: later");
+    }
+}
@@ -0,0 +1,142 @@
+use imbl::Vector;
+use serde_json::Value;
+
+use crate::store_tree::StateTree;
+
+/// What a subscription watches: one path, or several delivered together as
+/// an array in the order they were given.
+pub(crate) enum Selection {
+    Path(Vec<String>),
+    Tuple(Vec<Vec<String>>),
+}
+
+impl Selection {
+    /// Reads the selection, with missing paths as `null`.
+    pub(crate) fn resolve<'a>(&self, read: impl Fn(&[String]) -> Option<&'a StateTree>) -> StateTree {
+        match self {
+            Self::Path(segments) => read(segments).cloned().unwrap_or_default(),
+            Self::Tuple(paths) => StateTree::Array(
+                paths
+                    .iter()
+                    .map(|segments| read(segments).cloned().unwrap_or_default())
+                    .collect::<Vector<_>>(),
+            ),
+        }
+    }
+}
+
+/// How a new selection is compared with the last delivered one.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Equality {
+    /// Structural equality; slices still shared with the previous state
+    /// compare by pointer.
+    Deep,
+    /// Equal when every top-level entry is the same node, as with zustand's
+    /// `shallow`. A replaced but identical object counts as a change.
+    Shallow,
+    /// Equal when each of these paths, relative to the selection, is.
+    Keys(Vec<Vec<String>>),
+}
+
+impl Equality {
+    /// Parses `"deep"`, `"shallow"` or `{ keys: [path, ...] }`; absent means deep.
+    pub(crate) fn parse(value: &Value, parse_path: impl Fn(&str) -> Result<Vec<String>, String>) -> Result<Self, String> {
+        match value {
+            Value::Null => Ok(Self::Deep),
+            Value::String(mode) if mode == "deep" => Ok(Self::Deep),
+            Value::String(mode) if mode == "shallow" => Ok(Self::Shallow),
+            Value::Object(object) => {
+                let keys = object
+                    .get("keys")
+                    .and_then(Value::as_array)
+                    .ok_or("Key equality needs a keys array")?;
+                keys.iter()
+                    .map(|key| match key.as_str() {
+                        Some(key) => parse_path(key),
+                        None => Err(format!("Equality keys must be paths, got {key}")),
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(Self::Keys)
+            }
+            other => Err(format!("Unknown subscription equality: {other}")),
+        }
+    }
+
+    pub(crate) fn equal(&self, left: &StateTree, right: &StateTree) -> bool {
+        match self {
+            Self::Deep => left == right,
+            Self::Shallow => shallow_equal(left, right),
+            Self::Keys(paths) => paths.iter().all(|segments| left.get(segments) == right.get(segments)),
+        }
+    }
+}
+
+fn shallow_equal(left: &StateTree, right: &StateTree) -> bool {
+    match (left, right) {
+        (StateTree::Array(left), StateTree::Array(right)) => {
+            left.ptr_eq(right) || (left.len() == right.len() && left.iter().zip(right).all(|(left, right)| same_node(left, right)))
+        }
+        (StateTree::Object(left), StateTree::Object(right)) => {
+            left.ptr_eq(right)
+                || (left.len() == right.len()
+                    && left
+                        .iter()
+                        .zip(right.iter())
+                        .all(|((left_key, left), (right_key, right))| left_key == right_key && same_node(left, right)))
+        }
+        _ => same_node(left, right),
+    }
+}
+
+/// Identity for containers, value equality for scalars.
+fn same_node(left: &StateTree, right: &StateTree) -> bool {
+    match (left, right) {
+        (StateTree::Array(left), StateTree::Array(right)) => left.ptr_eq(right),
+        (StateTree::Object(left), StateTree::Object(right)) => left.ptr_eq(right),
+        _ => left == right,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn path(path: &str) -> Result<Vec<String>, String> {
+        Ok(path.split('.').map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn tuple_selection_reads_each_path_in_order() {
+        let state = StateTree::from(json!({ "user": { "name": "A" }, "count": 2 }));
+        let selection = Selection::Tuple(vec![path("count").unwrap(), path("user.name").unwrap(), path("missing").unwrap()]);
+        assert_eq!(selection.resolve(|segments| state.get(segments)), json!([2, "A", null]));
+    }
+
+    #[test]
+    fn shallow_compares_entries_by_identity() {
+        let mut state = StateTree::from(json!({ "todos": [{ "done": false }], "filter": "all", "count": 0 }));
+        let selection = Selection::Tuple(vec![path("todos").unwrap(), path("filter").unwrap()]);
+        let before = selection.resolve(|segments| state.get(segments));
+
+        state.set(&path("count").unwrap(), json!(1).into()).unwrap();
+        let after = selection.resolve(|segments| state.get(segments));
+        assert!(Equality::Shallow.equal(&before, &after));
+
+        state.set(&path("todos").unwrap(), json!([{ "done": false }]).into()).unwrap();
+        let replaced = selection.resolve(|segments| state.get(segments));
+        assert!(!Equality::Shallow.equal(&before, &replaced));
+        assert!(Equality::Deep.equal(&before, &replaced));
+    }
+
+    #[test]
+    fn key_equality_ignores_other_fields() {
+        let equality = Equality::parse(&json!({ "keys": ["id", "status"] }), path).unwrap();
+        let left = StateTree::from(json!({ "id": 1, "status": "open", "updatedAt": 10 }));
+        let right = StateTree::from(json!({ "id": 1, "status": "open", "updatedAt": 11 }));
+        assert!(equality.equal(&left, &right));
+        assert!(!equality.equal(&left, &json!({ "id": 1, "status": "closed" }).into()));
+        assert_eq!(Equality::parse(&json!("shallow"), path).unwrap(), Equality::Shallow);
+        assert!(Equality::parse(&json!("strict"), path).is_err());
+    }
+}
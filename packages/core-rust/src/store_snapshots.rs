@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::store_tree::StateTree;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotEntry {
+    state: StateTree,
+    label: Option<String>,
+    metadata: Value,
+    created_at: f64,
+    sequence: u64,
+    last_access: u64,
+}
+
+/// Named snapshots of a store with an optional LRU-evicted capacity.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct StoreSnapshots {
+    entries: HashMap<String, SnapshotEntry>,
+    limit: Option<usize>,
+    clock: u64,
+}
+
+impl StoreSnapshots {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            limit: None,
+            clock: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        snapshot_id: String,
+        state: StateTree,
+        label: Option<String>,
+        metadata: Value,
+        created_at: f64,
+    ) {
+        let tick = self.tick();
+        self.entries.insert(snapshot_id, SnapshotEntry {
+            state,
+            label,
+            metadata,
+            created_at,
+            sequence: tick,
+            last_access: tick,
+        });
+        self.evict();
+    }
+
+    /// Returns a snapshot's state and marks it as recently used.
+    pub(crate) fn get(&mut self, snapshot_id: &str) -> Option<&StateTree> {
+        let tick = self.tick();
+        let entry = self.entries.get_mut(snapshot_id)?;
+        entry.last_access = tick;
+        Some(&entry.state)
+    }
+
+    pub(crate) fn remove(&mut self, snapshot_id: &str) -> bool {
+        self.entries.remove(snapshot_id).is_some()
+    }
+
+    /// Caps the number of snapshots; `None` removes the cap.
+    pub(crate) fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+        self.evict();
+    }
+
+    pub(crate) fn list(&self) -> Vec<Value> {
+        let mut entries: Vec<(&String, &SnapshotEntry)> = self.entries.iter().collect();
+        entries.sort_by_key(|(_, entry)| entry.sequence);
+        entries
+            .into_iter()
+            .map(|(snapshot_id, entry)| serde_json::json!({
+                "id": snapshot_id,
+                "label": entry.label,
+                "metadata": entry.metadata,
+                "createdAt": entry.created_at,
+            }))
+            .collect()
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn evict(&mut self) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+        while self.entries.len() > limit {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(snapshot_id, _)| snapshot_id.clone())
+            else {
+                return;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn least_recently_used_snapshot_is_evicted_at_limit() {
+        let mut snapshots = StoreSnapshots::new();
+        snapshots.set_limit(Some(2));
+        snapshots.insert("a".to_string(), json!(1).into(), Some("draft".to_string()), json!({}), 1.0);
+        snapshots.insert("b".to_string(), json!(2).into(), None, Value::Null, 2.0);
+        assert_eq!(snapshots.get("a").unwrap(), &json!(1));
+
+        snapshots.insert("c".to_string(), json!(3).into(), None, Value::Null, 3.0);
+        let ids: Vec<Value> = snapshots.list().into_iter().map(|entry| entry["id"].clone()).collect();
+        assert_eq!(ids, vec![json!("a"), json!("c")]);
+        assert_eq!(snapshots.list()[0]["label"], "draft");
+
+        assert!(snapshots.remove("a"));
+        assert!(!snapshots.remove("a"));
+        assert_eq!(snapshots.len(), 1);
+    }
+}
@@ -0,0 +1,502 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::json_patch::{apply_patch, diff, escape_token, parse_pointer};
+use crate::store_tree::StateTree;
+
+pub(crate) const SYNC_PROTOCOL_VERSION: u32 = 1;
+const LOCAL_LOG_LIMIT: usize = 256;
+
+/// Envelope shared by every message on a sync channel.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SyncMessage {
+    pub(crate) version: u32,
+    pub(crate) store_id: String,
+    pub(crate) origin: String,
+    #[serde(flatten)]
+    pub(crate) body: SyncBody,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub(crate) enum SyncBody {
+    /// A local change: `sequence` is the origin's own counter and `clock`
+    /// the origin's view of every replica when the change was made.
+    Change {
+        sequence: u64,
+        clock: BTreeMap<String, u64>,
+        patch: Vec<Value>,
+    },
+    /// Asks `target` for a full snapshot. Without a target, only the peer
+    /// with the greatest id it knows of answers.
+    #[serde(rename = "resyncRequest")]
+    ResyncRequest {
+        #[serde(default)]
+        target: Option<String>,
+    },
+    Snapshot {
+        target: String,
+        clock: BTreeMap<String, u64>,
+        state: StateTree,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum SyncStatus {
+    Applied,
+    Ignored,
+    Duplicate,
+    /// A sequence gap or a patch that no longer applies; a resync request
+    /// was produced.
+    ResyncRequested,
+    Resynced,
+    SnapshotSent,
+}
+
+#[derive(Debug)]
+pub(crate) struct SyncOutcome {
+    pub(crate) status: SyncStatus,
+    /// State to commit, when the message changed it.
+    pub(crate) state: Option<StateTree>,
+    /// Patch applied for a `change`, for action logs.
+    pub(crate) applied: Vec<Value>,
+    /// JSON Pointers of remote operations that overlapped concurrent local
+    /// changes.
+    pub(crate) conflicts: Vec<String>,
+    /// Whether the remote side won those conflicts.
+    pub(crate) remote_won: bool,
+    pub(crate) reply: Option<SyncMessage>,
+}
+
+impl SyncOutcome {
+    fn status(status: SyncStatus) -> Self {
+        Self {
+            status,
+            state: None,
+            applied: Vec::new(),
+            conflicts: Vec::new(),
+            remote_won: false,
+            reply: None,
+        }
+    }
+}
+
+/// One replica of a synchronized store.
+///
+/// Local changes are diffed against the last synchronized state; edits
+/// inside an array are sent as a replacement of the whole array, because
+/// index-based operations from concurrent replicas don't commute. Remote
+/// changes that touch paths changed locally since the origin last heard from
+/// this replica are conflicts; the replica with the greater id wins them.
+/// When the winner's change sits below a subtree the loser replaced, the
+/// loser can't rebuild the winner's value and resyncs from it instead.
+#[derive(Clone)]
+pub(crate) struct StoreSync {
+    store_id: String,
+    replica_id: String,
+    clock: BTreeMap<String, u64>,
+    synced: StateTree,
+    local_log: VecDeque<(u64, Vec<Value>)>,
+    /// Set while a resync request is outstanding; only the first snapshot
+    /// answering it is applied.
+    awaiting_snapshot: bool,
+}
+
+impl StoreSync {
+    pub(crate) fn new(store_id: String, replica_id: String, state: &StateTree) -> Self {
+        Self {
+            store_id,
+            replica_id,
+            clock: BTreeMap::new(),
+            synced: state.clone(),
+            local_log: VecDeque::new(),
+            awaiting_snapshot: false,
+        }
+    }
+
+    fn sequence(&self) -> u64 {
+        self.clock.get(&self.replica_id).copied().unwrap_or(0)
+    }
+
+    fn message(&self, body: SyncBody) -> SyncMessage {
+        SyncMessage {
+            version: SYNC_PROTOCOL_VERSION,
+            store_id: self.store_id.clone(),
+            origin: self.replica_id.clone(),
+            body,
+        }
+    }
+
+    /// Returns a `change` message for everything committed since the last
+    /// synchronized state, if anything changed.
+    pub(crate) fn capture(&mut self, state: &StateTree) -> Option<SyncMessage> {
+        if *state == self.synced {
+            return None;
+        }
+        let patch = replace_array_edits(&self.synced, state, diff(&self.synced, state));
+        self.synced = state.clone();
+        if patch.is_empty() {
+            return None;
+        }
+
+        let sequence = self.sequence() + 1;
+        self.clock.insert(self.replica_id.clone(), sequence);
+        self.local_log.push_back((sequence, patch.clone()));
+        if self.local_log.len() > LOCAL_LOG_LIMIT {
+            self.local_log.pop_front();
+        }
+        Some(self.message(SyncBody::Change {
+            sequence,
+            clock: self.clock.clone(),
+            patch,
+        }))
+    }
+
+    /// Asks peers for a snapshot, e.g. right after joining a channel.
+    pub(crate) fn resync_request(&mut self, target: Option<String>) -> SyncMessage {
+        self.awaiting_snapshot = true;
+        self.message(SyncBody::ResyncRequest { target })
+    }
+
+    /// Handles a message from the channel against the current `state`.
+    /// Callers capture pending local changes first.
+    pub(crate) fn receive(&mut self, state: &StateTree, message: SyncMessage) -> Result<SyncOutcome, String> {
+        if message.version != SYNC_PROTOCOL_VERSION {
+            return Err(format!("Unsupported sync protocol version: {}", message.version));
+        }
+        if message.store_id != self.store_id || message.origin == self.replica_id {
+            return Ok(SyncOutcome::status(SyncStatus::Ignored));
+        }
+
+        match message.body {
+            SyncBody::Change { sequence, clock, patch } => Ok(self.receive_change(state, message.origin, sequence, &clock, patch)),
+            SyncBody::ResyncRequest { target } => {
+                let responder = match target {
+                    Some(target) => target == self.replica_id,
+                    None => self
+                        .clock
+                        .keys()
+                        .filter(|replica| **replica != message.origin)
+                        .all(|replica| *replica <= self.replica_id),
+                };
+                if !responder {
+                    return Ok(SyncOutcome::status(SyncStatus::Ignored));
+                }
+                let mut outcome = SyncOutcome::status(SyncStatus::SnapshotSent);
+                outcome.reply = Some(self.message(SyncBody::Snapshot {
+                    target: message.origin,
+                    clock: self.clock.clone(),
+                    state: state.clone(),
+                }));
+                Ok(outcome)
+            }
+            SyncBody::Snapshot { target, clock, state: snapshot } => {
+                if target != self.replica_id || !self.awaiting_snapshot {
+                    return Ok(SyncOutcome::status(SyncStatus::Ignored));
+                }
+                self.awaiting_snapshot = false;
+                Ok(self.receive_snapshot(state, clock, snapshot))
+            }
+        }
+    }
+
+    fn receive_change(
+        &mut self,
+        state: &StateTree,
+        origin: String,
+        sequence: u64,
+        clock: &BTreeMap<String, u64>,
+        patch: Vec<Value>,
+    ) -> SyncOutcome {
+        let seen = self.clock.get(&origin).copied().unwrap_or(0);
+        if sequence <= seen {
+            return SyncOutcome::status(SyncStatus::Duplicate);
+        }
+        if sequence > seen + 1 {
+            return self.request_resync(origin);
+        }
+
+        let known = clock.get(&self.replica_id).copied().unwrap_or(0);
+        let concurrent: Vec<Vec<String>> = self
+            .local_log
+            .iter()
+            .filter(|(local_sequence, _)| *local_sequence > known)
+            .flat_map(|(_, patch)| patch_paths(patch))
+            .collect();
+        let remote_wins = origin > self.replica_id;
+        let mut conflicts = Vec::new();
+        let mut applied = Vec::new();
+        for operation in patch {
+            let pointer = operation.get("path").and_then(Value::as_str).unwrap_or_default().to_string();
+            let segments = parse_pointer(&pointer).unwrap_or_default();
+            let overlapping: Vec<&Vec<String>> = concurrent.iter().filter(|local| overlaps(local, &segments)).collect();
+            if overlapping.is_empty() {
+                applied.push(operation);
+                continue;
+            }
+            if remote_wins && overlapping.iter().any(|local| local.len() < segments.len()) {
+                return self.request_resync(origin);
+            }
+            conflicts.push(pointer);
+            if remote_wins {
+                applied.push(operation);
+            }
+        }
+
+        let mut next = state.clone();
+        if apply_patch(&mut next, &applied).is_err() {
+            return self.request_resync(origin);
+        }
+        self.clock.insert(origin, sequence);
+        self.synced = next.clone();
+        SyncOutcome {
+            status: SyncStatus::Applied,
+            state: Some(next),
+            applied,
+            conflicts,
+            remote_won: remote_wins,
+            reply: None,
+        }
+    }
+
+    /// Replaces the state with `snapshot`, then lays over it the local
+    /// changes the responder had not seen and anything not yet captured, so
+    /// no local edit is lost.
+    fn receive_snapshot(&mut self, state: &StateTree, clock: BTreeMap<String, u64>, snapshot: StateTree) -> SyncOutcome {
+        let uncaptured = replace_array_edits(&self.synced, state, diff(&self.synced, state));
+        let acknowledged = clock.get(&self.replica_id).copied().unwrap_or(0);
+        for (replica, sequence) in clock {
+            if replica != self.replica_id {
+                let seen = self.clock.entry(replica).or_insert(0);
+                *seen = (*seen).max(sequence);
+            }
+        }
+
+        let mut next = snapshot;
+        for (_, patch) in self.local_log.iter().filter(|(sequence, _)| *sequence > acknowledged) {
+            apply_if_valid(&mut next, patch);
+        }
+        self.synced = next.clone();
+        apply_if_valid(&mut next, &uncaptured);
+        let mut outcome = SyncOutcome::status(SyncStatus::Resynced);
+        outcome.state = Some(next);
+        outcome
+    }
+
+    fn request_resync(&mut self, origin: String) -> SyncOutcome {
+        let mut outcome = SyncOutcome::status(SyncStatus::ResyncRequested);
+        outcome.reply = Some(self.resync_request(Some(origin)));
+        outcome
+    }
+}
+
+/// Rewrites operations inside an array as one replacement of the outermost
+/// array containing them.
+fn replace_array_edits(before: &StateTree, after: &StateTree, patch: Vec<Value>) -> Vec<Value> {
+    let mut rewritten = Vec::new();
+    let mut replaced: Vec<Vec<String>> = Vec::new();
+    for operation in patch {
+        let segments = operation
+            .get("path")
+            .and_then(Value::as_str)
+            .and_then(|pointer| parse_pointer(pointer).ok())
+            .unwrap_or_default();
+        let array_depth = (0..segments.len()).find(|depth| matches!(before.get(&segments[..*depth]), Some(StateTree::Array(_))));
+        let Some(depth) = array_depth else {
+            rewritten.push(operation);
+            continue;
+        };
+        let root = &segments[..depth];
+        if replaced.iter().any(|path| path == root) {
+            continue;
+        }
+        replaced.push(root.to_vec());
+        let pointer: String = root.iter().map(|token| format!("/{}", escape_token(token))).collect();
+        rewritten.push(json!({ "op": "replace", "path": pointer, "value": after.get(root).cloned().unwrap_or_default() }));
+    }
+    rewritten
+}
+
+fn apply_if_valid(state: &mut StateTree, patch: &[Value]) {
+    let mut next = state.clone();
+    if apply_patch(&mut next, patch).is_ok() {
+        *state = next;
+    }
+}
+
+fn patch_paths(patch: &[Value]) -> Vec<Vec<String>> {
+    patch
+        .iter()
+        .filter_map(|operation| operation.get("path").and_then(Value::as_str))
+        .filter_map(|pointer| parse_pointer(pointer).ok())
+        .collect()
+}
+
+/// Whether one path is a prefix of the other.
+fn overlaps(left: &[String], right: &[String]) -> bool {
+    left.iter().zip(right).all(|(left, right)| left == right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// In-process loopback channel: every message reaches every other replica.
+    struct Loopback {
+        replicas: Vec<(StoreSync, StateTree)>,
+        queue: VecDeque<SyncMessage>,
+    }
+
+    impl Loopback {
+        fn new(ids: &[&str], state: serde_json::Value) -> Self {
+            let state = StateTree::from(state);
+            Self {
+                replicas: ids
+                    .iter()
+                    .map(|id| (StoreSync::new("main".to_string(), id.to_string(), &state), state.clone()))
+                    .collect(),
+                queue: VecDeque::new(),
+            }
+        }
+
+        fn commit(&mut self, replica: usize, state: serde_json::Value) {
+            let (sync, current) = &mut self.replicas[replica];
+            *current = state.into();
+            self.queue.extend(sync.capture(current));
+        }
+
+        fn deliver_all(&mut self) {
+            while let Some(message) = self.queue.pop_front() {
+                self.deliver(message);
+            }
+        }
+
+        fn deliver(&mut self, message: SyncMessage) {
+            for (sync, state) in &mut self.replicas {
+                let encoded = serde_json::to_value(&message).unwrap();
+                let outcome = sync.receive(state, serde_json::from_value(encoded).unwrap()).unwrap();
+                if let Some(next) = outcome.state {
+                    *state = next;
+                }
+                self.queue.extend(outcome.reply);
+            }
+        }
+    }
+
+    #[test]
+    fn changes_propagate_without_echo() {
+        let mut channel = Loopback::new(&["a", "b"], json!({ "count": 0, "todos": [] }));
+        channel.commit(0, json!({ "count": 1, "todos": ["x"] }));
+        channel.deliver_all();
+        assert_eq!(channel.replicas[1].1, json!({ "count": 1, "todos": ["x"] }));
+
+        let (sync, state) = &mut channel.replicas[1];
+        assert!(sync.capture(state).is_none());
+    }
+
+    #[test]
+    fn concurrent_writes_converge_on_the_greater_replica() {
+        let mut channel = Loopback::new(&["a", "b"], json!({ "title": "draft", "count": 0 }));
+        channel.commit(0, json!({ "title": "from a", "count": 1 }));
+        channel.commit(1, json!({ "title": "from b", "count": 0 }));
+        channel.deliver_all();
+
+        assert_eq!(channel.replicas[0].1, json!({ "title": "from b", "count": 1 }));
+        assert_eq!(channel.replicas[1].1, json!({ "title": "from b", "count": 1 }));
+    }
+
+    #[test]
+    fn concurrent_pushes_to_one_array_converge() {
+        let mut channel = Loopback::new(&["a", "b"], json!({ "todos": [] }));
+        channel.commit(0, json!({ "todos": ["x"] }));
+        channel.commit(1, json!({ "todos": ["y"] }));
+        channel.deliver_all();
+
+        assert_eq!(channel.replicas[0].1, json!({ "todos": ["y"] }));
+        assert_eq!(channel.replicas[1].1, json!({ "todos": ["y"] }));
+    }
+
+    #[test]
+    fn concurrent_removals_at_different_indices_converge() {
+        let mut channel = Loopback::new(&["a", "b"], json!({ "todos": ["p", "q", "r"] }));
+        channel.commit(0, json!({ "todos": ["q", "r"] }));
+        channel.commit(1, json!({ "todos": ["p", "q"] }));
+        channel.deliver_all();
+
+        assert_eq!(channel.replicas[0].1, json!({ "todos": ["p", "q"] }));
+        assert_eq!(channel.replicas[1].1, json!({ "todos": ["p", "q"] }));
+    }
+
+    #[test]
+    fn winning_change_below_a_replaced_subtree_resyncs_the_loser() {
+        let mut channel = Loopback::new(&["a", "b"], json!({ "user": { "name": "A", "age": 1 } }));
+        channel.commit(0, json!({ "user": "anonymous" }));
+        channel.commit(1, json!({ "user": { "name": "B", "age": 1 } }));
+        channel.deliver_all();
+
+        assert_eq!(channel.replicas[0].1, json!({ "user": { "name": "B", "age": 1 } }));
+        assert_eq!(channel.replicas[1].1, json!({ "user": { "name": "B", "age": 1 } }));
+    }
+
+    #[test]
+    fn untargeted_resync_has_one_responder_and_keeps_local_changes() {
+        let mut channel = Loopback::new(&["a", "b", "c"], json!({ "count": 0, "title": "" }));
+        channel.commit(0, json!({ "count": 1, "title": "" }));
+        channel.commit(1, json!({ "count": 1, "title": "b" }));
+        channel.deliver_all();
+
+        let (sync, state) = &mut channel.replicas[2];
+        *state = json!({ "count": 1, "title": "b", "draft": true }).into();
+        let request = sync.resync_request(None);
+        let replies: Vec<SyncMessage> = channel
+            .replicas
+            .iter_mut()
+            .take(2)
+            .filter_map(|(sync, state)| sync.receive(state, request.clone()).unwrap().reply)
+            .collect();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].origin, "b");
+
+        channel.deliver(replies[0].clone());
+        assert_eq!(channel.replicas[2].1, json!({ "count": 1, "title": "b", "draft": true }));
+        channel.commit(2, json!({ "count": 1, "title": "b", "draft": true }));
+        channel.deliver_all();
+        assert_eq!(channel.replicas[0].1, json!({ "count": 1, "title": "b", "draft": true }));
+    }
+
+    #[test]
+    fn sequence_gap_triggers_snapshot_resync() {
+        let mut channel = Loopback::new(&["a", "b"], json!({ "count": 0 }));
+        channel.commit(0, json!({ "count": 1 }));
+        channel.queue.clear();
+        channel.commit(0, json!({ "count": 2 }));
+
+        let message = channel.queue.pop_front().unwrap();
+        let (sync, state) = &mut channel.replicas[1];
+        let outcome = sync.receive(state, message).unwrap();
+        assert_eq!(outcome.status, SyncStatus::ResyncRequested);
+        channel.queue.extend(outcome.reply);
+        channel.deliver_all();
+
+        assert_eq!(channel.replicas[1].1, json!({ "count": 2 }));
+        channel.commit(0, json!({ "count": 3 }));
+        channel.deliver_all();
+        assert_eq!(channel.replicas[1].1, json!({ "count": 3 }));
+    }
+
+    #[test]
+    fn messages_for_other_stores_or_versions_are_not_applied() {
+        let state = StateTree::from(json!({}));
+        let mut sync = StoreSync::new("main".to_string(), "a".to_string(), &state);
+        let mut message = StoreSync::new("other".to_string(), "b".to_string(), &state).resync_request(None);
+        assert_eq!(sync.receive(&state, message.clone()).unwrap().status, SyncStatus::Ignored);
+
+        message.store_id = "main".to_string();
+        message.version = 2;
+        assert_eq!(sync.receive(&state, message).unwrap_err(), "Unsupported sync protocol version: 2");
+    }
+}
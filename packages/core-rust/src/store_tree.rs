@@ -0,0 +1,393 @@
+use imbl::{OrdMap, Vector};
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde_json::{Map, Number, Value};
+use std::fmt;
+use std::rc::Rc;
+
+/// Persistent JSON value holding store state.
+///
+/// Objects are persistent ordered maps and arrays RRB vectors, so a clone is
+/// O(1) and a write copies only the nodes on the path to the change. The
+/// snapshots, history bases and subscription values cloned from a state keep
+/// sharing every untouched subtree with it, which lets comparisons and diffs
+/// skip shared subtrees by pointer.
+#[derive(Clone, Debug, Default)]
+pub(crate) enum StateTree {
+    #[default]
+    Null,
+    Bool(bool),
+    Number(Number),
+    String(Rc<str>),
+    Array(Vector<StateTree>),
+    Object(OrdMap<Rc<str>, StateTree>),
+}
+
+impl StateTree {
+    pub(crate) fn object() -> Self {
+        StateTree::Object(OrdMap::new())
+    }
+
+    pub(crate) fn to_value(&self) -> Value {
+        match self {
+            StateTree::Null => Value::Null,
+            StateTree::Bool(flag) => Value::Bool(*flag),
+            StateTree::Number(number) => Value::Number(number.clone()),
+            StateTree::String(text) => Value::String(text.to_string()),
+            StateTree::Array(items) => Value::Array(items.iter().map(StateTree::to_value).collect()),
+            StateTree::Object(object) => Value::Object(
+                object
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_value()))
+                    .collect::<Map<String, Value>>(),
+            ),
+        }
+    }
+
+    pub(crate) fn is_null(&self) -> bool {
+        matches!(self, StateTree::Null)
+    }
+
+    pub(crate) fn is_object(&self) -> bool {
+        matches!(self, StateTree::Object(_))
+    }
+
+    pub(crate) fn as_i64(&self) -> Option<i64> {
+        match self {
+            StateTree::Number(number) => number.as_i64(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_object_mut(&mut self) -> Option<&mut OrdMap<Rc<str>, StateTree>> {
+        match self {
+            StateTree::Object(object) => Some(object),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array_mut(&mut self) -> Option<&mut Vector<StateTree>> {
+        match self {
+            StateTree::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Object member `key`, or the element at index `key` of an array.
+    pub(crate) fn child(&self, key: &str) -> Option<&StateTree> {
+        match self {
+            StateTree::Object(object) => object.get(key),
+            StateTree::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => None,
+        }
+    }
+
+    fn child_mut(&mut self, key: &str) -> Option<&mut StateTree> {
+        match self {
+            StateTree::Object(object) => object.get_mut(key),
+            StateTree::Array(items) => items.get_mut(key.parse::<usize>().ok()?),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get(&self, segments: &[String]) -> Option<&StateTree> {
+        segments.iter().try_fold(self, |current, segment| current.child(segment))
+    }
+
+    pub(crate) fn get_mut(&mut self, segments: &[String]) -> Option<&mut StateTree> {
+        segments.iter().try_fold(self, |current, segment| current.child_mut(segment))
+    }
+
+    /// Writes `value` at `segments`, creating missing objects on the way.
+    ///
+    /// An array index may address an existing element or append at the end;
+    /// anything further out of bounds fails. Only missing or `null` values
+    /// are replaced by objects; writing a key into an existing array or
+    /// scalar fails.
+    pub(crate) fn set(&mut self, segments: &[String], value: StateTree) -> Result<(), String> {
+        let Some((last, parents)) = segments.split_last() else {
+            *self = value;
+            return Ok(());
+        };
+
+        let mut current = self;
+        for (depth, segment) in parents.iter().enumerate() {
+            current = current.child_entry(segment, &segments[..depth])?;
+        }
+
+        if let (StateTree::Array(items), Ok(index)) = (&mut *current, last.parse::<usize>()) {
+            match index.cmp(&items.len()) {
+                std::cmp::Ordering::Less => {
+                    items.set(index, value);
+                }
+                std::cmp::Ordering::Equal => items.push_back(value),
+                std::cmp::Ordering::Greater => return Err(format!("Array index out of bounds: {last}")),
+            }
+            return Ok(());
+        }
+
+        if current.is_null() {
+            *current = StateTree::object();
+        }
+        match current {
+            StateTree::Object(object) => {
+                object.insert(Rc::from(last.as_str()), value);
+                Ok(())
+            }
+            _ => Err(not_an_object(parents)),
+        }
+    }
+
+    fn child_entry(&mut self, part: &str, at: &[String]) -> Result<&mut StateTree, String> {
+        if self.is_null() {
+            *self = StateTree::object();
+        }
+
+        match self {
+            StateTree::Array(items) => {
+                let index = part.parse::<usize>().map_err(|_| not_an_object(at))?;
+                if index == items.len() {
+                    items.push_back(StateTree::object());
+                }
+                items
+                    .get_mut(index)
+                    .ok_or_else(|| format!("Array index out of bounds: {part}"))
+            }
+            StateTree::Object(object) => Ok(object.entry(Rc::from(part)).or_insert_with(StateTree::object)),
+            _ => Err(not_an_object(at)),
+        }
+    }
+
+    /// Removes the value at `segments`; removing the root leaves `null`.
+    pub(crate) fn remove(&mut self, segments: &[String]) {
+        let Some((last, parents)) = segments.split_last() else {
+            *self = StateTree::Null;
+            return;
+        };
+
+        match self.get_mut(parents) {
+            Some(StateTree::Object(object)) => {
+                object.remove(last.as_str());
+            }
+            Some(StateTree::Array(items)) => {
+                if let Some(index) = last.parse::<usize>().ok().filter(|index| *index < items.len()) {
+                    items.remove(index);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn not_an_object(segments: &[String]) -> String {
+    if segments.is_empty() {
+        return "Path is not an object: (root)".to_string();
+    }
+    format!("Path is not an object: {}", segments.join("."))
+}
+
+impl From<Value> for StateTree {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => StateTree::Null,
+            Value::Bool(flag) => StateTree::Bool(flag),
+            Value::Number(number) => StateTree::Number(number),
+            Value::String(text) => StateTree::String(Rc::from(text)),
+            Value::Array(items) => StateTree::Array(items.into_iter().map(StateTree::from).collect()),
+            Value::Object(object) => StateTree::Object(
+                object
+                    .into_iter()
+                    .map(|(key, value)| (Rc::from(key), StateTree::from(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Structural equality that returns early for containers sharing their root.
+impl PartialEq for StateTree {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (StateTree::Null, StateTree::Null) => true,
+            (StateTree::Bool(left), StateTree::Bool(right)) => left == right,
+            (StateTree::Number(left), StateTree::Number(right)) => left == right,
+            (StateTree::String(left), StateTree::String(right)) => left == right,
+            (StateTree::Array(left), StateTree::Array(right)) => left.ptr_eq(right) || left == right,
+            (StateTree::Object(left), StateTree::Object(right)) => left.ptr_eq(right) || left == right,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for StateTree {}
+
+impl PartialEq<Value> for StateTree {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (StateTree::Null, Value::Null) => true,
+            (StateTree::Bool(left), Value::Bool(right)) => left == right,
+            (StateTree::Number(left), Value::Number(right)) => left == right,
+            (StateTree::String(left), Value::String(right)) => **left == **right,
+            (StateTree::Array(left), Value::Array(right)) => {
+                left.len() == right.len() && left.iter().zip(right).all(|(left, right)| left == right)
+            }
+            (StateTree::Object(left), Value::Object(right)) => {
+                left.len() == right.len()
+                    && left
+                        .iter()
+                        .all(|(key, value)| right.get(key.as_ref()).is_some_and(|other| value == other))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Serializes exactly like the equivalent `serde_json::Value`, keys sorted.
+impl Serialize for StateTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            StateTree::Null => serializer.serialize_unit(),
+            StateTree::Bool(flag) => serializer.serialize_bool(*flag),
+            StateTree::Number(number) => number.serialize(serializer),
+            StateTree::String(text) => serializer.serialize_str(text),
+            StateTree::Array(items) => {
+                let mut sequence = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    sequence.serialize_element(item)?;
+                }
+                sequence.end()
+            }
+            StateTree::Object(object) => {
+                let mut map = serializer.serialize_map(Some(object.len()))?;
+                for (key, value) in object {
+                    map.serialize_entry(key.as_ref(), value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// Builds the tree straight from any self-describing format, so binary
+/// payloads skip the intermediate `serde_json::Value`.
+impl<'de> Deserialize<'de> for StateTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(StateTreeVisitor)
+    }
+}
+
+struct StateTreeVisitor;
+
+impl<'de> Visitor<'de> for StateTreeVisitor {
+    type Value = StateTree;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON-compatible value")
+    }
+
+    fn visit_unit<E>(self) -> Result<StateTree, E> {
+        Ok(StateTree::Null)
+    }
+
+    fn visit_none<E>(self) -> Result<StateTree, E> {
+        Ok(StateTree::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<StateTree, D::Error> {
+        StateTree::deserialize(deserializer)
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<StateTree, E> {
+        Ok(StateTree::Bool(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<StateTree, E> {
+        Ok(StateTree::Number(value.into()))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<StateTree, E> {
+        Ok(StateTree::Number(value.into()))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<StateTree, E> {
+        Ok(Number::from_f64(value).map_or(StateTree::Null, StateTree::Number))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<StateTree, E> {
+        Ok(StateTree::String(Rc::from(value)))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut sequence: A) -> Result<StateTree, A::Error> {
+        let mut items = Vector::new();
+        while let Some(item) = sequence.next_element()? {
+            items.push_back(item);
+        }
+        Ok(StateTree::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<StateTree, A::Error> {
+        let mut object = OrdMap::new();
+        while let Some((key, value)) = map.next_entry::<String, StateTree>()? {
+            object.insert(Rc::from(key), value);
+        }
+        Ok(StateTree::Object(object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn segments(path: &str) -> Vec<String> {
+        path.split('.').map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn converts_and_serializes_like_json_values() {
+        let value = json!({ "b": [1, 2.5, "x", null], "a": { "flag": true } });
+        let tree = StateTree::from(value.clone());
+        assert_eq!(tree, value);
+        assert_eq!(tree.to_value(), value);
+        assert_eq!(serde_json::to_string(&tree).unwrap(), serde_json::to_string(&value).unwrap());
+    }
+
+    #[test]
+    fn writes_leave_clones_and_untouched_subtrees_shared() {
+        let before = StateTree::from(json!({ "user": { "name": "A" }, "todos": [{ "done": false }] }));
+        let mut after = before.clone();
+        after.set(&segments("user.name"), StateTree::from(json!("B"))).unwrap();
+
+        assert_eq!(after.get(&segments("user.name")).unwrap(), &json!("B"));
+        let (Some(StateTree::Array(old)), Some(StateTree::Array(new))) =
+            (before.get(&segments("todos")), after.get(&segments("todos")))
+        else {
+            panic!("todos should be arrays");
+        };
+        assert!(old.ptr_eq(new));
+        assert_eq!(before, json!({ "user": { "name": "A" }, "todos": [{ "done": false }] }));
+    }
+
+    #[test]
+    fn set_and_remove_follow_store_path_rules() {
+        let mut tree = StateTree::from(json!({ "todos": ["a"] }));
+        tree.set(&segments("todos.1"), json!("b").into()).unwrap();
+        tree.set(&segments("profile.name"), json!("A").into()).unwrap();
+        assert!(tree.set(&segments("todos.5"), StateTree::Null).is_err());
+
+        tree.remove(&segments("todos.0"));
+        tree.remove(&segments("missing.key"));
+        assert_eq!(tree, json!({ "todos": ["b"], "profile": { "name": "A" } }));
+    }
+
+    #[test]
+    fn set_refuses_to_replace_arrays_and_scalars_with_objects() {
+        let mut tree = StateTree::from(json!({ "todos": ["a"], "count": 1, "user": null }));
+        assert_eq!(tree.set(&segments("todos.title"), json!("x").into()).unwrap_err(), "Path is not an object: todos");
+        assert_eq!(tree.set(&segments("count.value"), json!(2).into()).unwrap_err(), "Path is not an object: count");
+        assert_eq!(tree.set(&segments("count.a.b"), json!(2).into()).unwrap_err(), "Path is not an object: count");
+        tree.set(&segments("user.name"), json!("A").into()).unwrap();
+        assert_eq!(tree, json!({ "todos": ["a"], "count": 1, "user": { "name": "A" } }));
+        assert!(StateTree::from(json!([1])).set(&segments("key"), StateTree::Null).is_err());
+    }
+}
@@ -4,6 +4,7 @@ import initWasm, * as wasm from 'gaesup-state-core-rust/web';
 
 export type DependencyConflictPolicy = 'reject' | 'isolate' | 'migrate' | 'readonly';
 export type AcceleratorKind = 'cpu' | 'webgpu' | 'cuda';
+export type BinaryFormat = 'msgpack' | 'cbor';
 
 export interface Action<T = any> {
   type: string;
@@ -44,6 +45,15 @@ export interface RegisteredStoreSchema {
   schemaId: string;
   schemaVersion: string;
   compatRange?: string;
+  jsonSchema?: Record<string, any>;
+  validationMode?: 'reject' | 'warn';
+}
+
+export interface NativeReducerOp {
+  op: 'set' | 'inc' | 'toggle' | 'push';
+  path: string;
+  value?: any;
+  by?: number | string;
 }
 
 export interface HostCompatibilityConfig {
@@ -80,6 +90,14 @@ export interface ValidationResult {
   errors: ValidationIssue[];
   warnings: ValidationIssue[];
   isolatedStores: string[];
+  migrations: StoreMigrationRequirement[];
+}
+
+export interface StoreMigrationRequirement {
+  storeId: string;
+  schemaId: string;
+  fromVersion: string;
+  targetVersion: string;
 }
 
 export interface ContainerConfig {
@@ -97,6 +115,87 @@ export interface ContainerConfig {
   };
 }
 
+export interface RuntimeFilterOptions {
+  include?: string[];
+  exclude?: string[];
+}
+
+export interface RuntimeDocument {
+  format: 'gaesup-runtime';
+  version: number;
+  checksum: string;
+  stores: Record<string, { state: any; schema?: RegisteredStoreSchema | null; snapshots?: any }>;
+  renderStores: Record<string, any>;
+  containers: Record<string, any>;
+}
+
+export type PersistenceRecord =
+  | { kind: 'base'; key: string; sequence: number; timestamp: number; state: any }
+  | { kind: 'delta'; key: string; sequence: number; timestamp: number; patch: any[] };
+
+export interface PersistenceAdapter {
+  write(record: PersistenceRecord): void | Promise<void>;
+  read(key: string): PersistenceRecord[] | Promise<PersistenceRecord[]>;
+}
+
+export interface PersistenceOptions {
+  key?: string;
+  throttleMs?: number;
+  paths?: string[];
+  compactEvery?: number;
+  onWriteError?: (error: unknown, record: PersistenceRecord) => void;
+}
+
+export interface SyncChannel {
+  postMessage(message: any): void;
+  addEventListener(type: 'message', listener: (event: { data: any }) => void): void;
+  removeEventListener(type: 'message', listener: (event: { data: any }) => void): void;
+}
+
+export interface SyncOptions {
+  replicaId?: string;
+  resync?: boolean;
+}
+
+export type NotificationMode = 'sync' | 'batched' | 'deferred';
+export type SubscriptionEquality = 'deep' | 'shallow' | { keys: string[] };
+
+export interface SelectorSubscriptionOptions {
+  equality?: SubscriptionEquality;
+  fireImmediately?: boolean;
+}
+
+export interface SubscriptionHandle {
+  readonly id: string;
+  readonly paused: boolean;
+  pause(): void;
+  resume(): void;
+  unsubscribe(): void;
+}
+
+export interface SubscriberErrorPolicy {
+  policy?: 'continue' | 'unsubscribe' | 'rethrow';
+  maxFailures?: number;
+}
+
+export interface SubscriberErrorInfo {
+  subscriptionId: string;
+  message: string;
+  failures: number;
+  unsubscribed: boolean;
+}
+
+export interface CrdtOptions {
+  replicaId?: string;
+  counters?: string[];
+}
+
+export interface SyncReport {
+  status: 'applied' | 'ignored' | 'duplicate' | 'resyncRequested' | 'resynced' | 'snapshotSent';
+  conflicts: string[];
+  remoteWon: boolean;
+}
+
 export interface DispatchPipelineOptions {
   autoFlush?: boolean;
 }
@@ -158,23 +257,25 @@ export const GaesupCore = {
     await ensureReady();
     wasm.cleanup_store(storeId);
     dispatchListeners.delete(storeId);
+    nativeActions.delete(storeId);
   },
   async garbageCollect() {
     await ensureReady();
     wasm.garbage_collect();
     wasm.cleanup_containers();
     dispatchListeners.clear();
+    nativeActions.clear();
   },
-  async dispatch(storeIdOrActionType: string, actionTypeOrPayload?: any, payload?: any) {
+  async dispatch(storeIdOrActionType: string, actionTypeOrPayload?: any, payload?: any, framework?: string) {
     await ensureReady();
     const legacy = payload === undefined && typeof actionTypeOrPayload !== 'string';
     const storeId = legacy ? 'main' : storeIdOrActionType;
     const actionType = legacy ? storeIdOrActionType : actionTypeOrPayload;
     const actionPayload = legacy ? actionTypeOrPayload : payload;
     const action = { type: actionType, payload: actionPayload };
-    const state = reducers.has(storeId) && !isNativeAction(actionType)
-      ? await dispatchThroughReducers(storeId, action)
-      : await wasm.dispatch(storeId, actionType, actionPayload);
+    const state = reducers.has(storeId) && !isNativeAction(storeId, actionType)
+      ? await dispatchThroughReducers(storeId, action, framework)
+      : await wasm.dispatch(storeId, actionType, actionPayload, framework);
     dispatchListeners.get(storeId)?.forEach((listener) => listener({ type: actionType, payload: actionPayload }, state));
     return state;
   },
@@ -222,13 +323,64 @@ export const GaesupCore = {
     requireReady();
     return (wasm as any).dispatch_counter_handle_batch_fast_unchecked(handle, delta, count) as number;
   },
+  async createLaneHandle(storeId: string, path: string, kind: 'i64' | 'f64' | 'bool') {
+    await ensureReady();
+    return (wasm as any).create_lane_handle(storeId, path, kind) as number;
+  },
+  releaseLaneHandle(handle: number) {
+    requireReady();
+    return (wasm as any).release_lane_handle(handle);
+  },
+  laneAddFast(handle: number, delta: number) {
+    requireReady();
+    return (wasm as any).lane_add_fast(handle, delta) as number;
+  },
+  laneSetFast(handle: number, value: number | boolean) {
+    requireReady();
+    return (wasm as any).lane_set_fast(handle, Number(value)) as number;
+  },
+  laneToggleFast(handle: number) {
+    requireReady();
+    return (wasm as any).lane_toggle_fast(handle) as boolean;
+  },
+  laneValue(handle: number) {
+    requireReady();
+    return (wasm as any).lane_value(handle) as number;
+  },
+  laneBufferView() {
+    requireReady();
+    const info = (wasm as any).lane_buffer_info() as { valuesPtr: number; generationsPtr: number; capacity: number };
+    const memory = (wasm as any).lane_memory() as WebAssembly.Memory;
+    // Growing wasm memory detaches the old buffer, so the views are rebuilt
+    // whenever it changes; read them through the getters on every access.
+    let buffer: ArrayBuffer | null = null;
+    let values = new Float64Array(0);
+    let generations = new Int32Array(0);
+    const refresh = () => {
+      if (memory.buffer === buffer) return;
+      buffer = memory.buffer;
+      values = new Float64Array(buffer, info.valuesPtr, info.capacity);
+      generations = new Int32Array(buffer, info.generationsPtr, info.capacity);
+    };
+    return {
+      get values() {
+        refresh();
+        return values;
+      },
+      get generations() {
+        refresh();
+        return generations;
+      },
+      slot: (handle: number) => (wasm as any).lane_slot(handle) as number
+    };
+  },
   select(storeIdOrPath = 'main', maybePath?: string) {
     requireReady();
     const storeId = maybePath === undefined ? 'main' : storeIdOrPath;
     const path = maybePath === undefined ? storeIdOrPath : maybePath;
     return wasm.select(storeId, path || '');
   },
-  subscribe(storeId: string, path: string, callbackOrId: string | ((state: any) => void)) {
+  subscribe(storeId: string, path: string, callbackOrId: string | ((state: any, previous?: any) => void)) {
     requireReady();
     const callback = typeof callbackOrId === 'function' ? callbackOrId : callbackRegistry.get(callbackOrId);
     if (!callback) throw new Error(`Callback not registered: ${callbackOrId}`);
@@ -237,7 +389,42 @@ export const GaesupCore = {
   unsubscribe(subscriptionId: string) {
     wasm.unsubscribe(subscriptionId);
   },
-  registerCallback(callbackId: string, callback: (state?: any) => void) {
+  subscribeWithSelector<T = any>(
+    storeId: string,
+    paths: string | string[],
+    callback: (selected: T, previous: T) => void,
+    options: SelectorSubscriptionOptions = {}
+  ): SubscriptionHandle {
+    requireReady();
+    const handle = (wasm as any).subscribe_with_selector(storeId, paths, callback, options);
+    return {
+      get id() { return handle.id as string; },
+      get paused() { return handle.paused as boolean; },
+      pause: () => handle.pause(),
+      resume: () => handle.resume(),
+      unsubscribe: () => {
+        handle.unsubscribe();
+        handle.free();
+      }
+    };
+  },
+  setSubscriberErrorPolicy(policy: SubscriberErrorPolicy) {
+    requireReady();
+    (wasm as any).set_subscriber_error_policy(policy);
+  },
+  onSubscriberError(hook: ((error: unknown, info: SubscriberErrorInfo) => void) | null) {
+    requireReady();
+    (wasm as any).set_subscriber_error_hook(hook ?? undefined);
+  },
+  setNotificationMode(mode: NotificationMode, scheduler?: (flush: () => void) => void) {
+    requireReady();
+    (wasm as any).set_notification_mode(mode, mode === 'deferred' ? scheduler ?? ((flush) => queueMicrotask(flush)) : undefined);
+  },
+  flushNotifications(): number {
+    requireReady();
+    return (wasm as any).flush_notifications();
+  },
+  registerCallback(callbackId: string, callback: (state?: any, previous?: any) => void) {
     callbackRegistry.set(callbackId, callback);
   },
   unregisterCallback(callbackId: string) {
@@ -273,6 +460,30 @@ export const GaesupCore = {
       execute: () => batch.execute()
     };
   },
+  connectDevtools(storeId: string, name?: string) {
+    requireReady();
+    const extension = (globalThis as any).__REDUX_DEVTOOLS_EXTENSION__;
+    if (!extension) return () => {};
+    const connection = extension.connect({ name: name ?? storeId });
+    wasm.connect_devtools(storeId, (message: any) => {
+      if (message.type === 'INIT') connection.init(message.state);
+      else connection.send(message.action, message.state);
+    }, name);
+    const unsubscribe = connection.subscribe((message: any) => wasm.devtools_receive(storeId, message));
+    return () => {
+      unsubscribe?.();
+      wasm.disconnect_devtools(storeId);
+    };
+  },
+  createTransaction() {
+    requireReady();
+    const transaction = new wasm.Transaction();
+    return {
+      add: (storeId: string, actionType: string, payload: any) => transaction.add(storeId, actionType, payload),
+      commit: (): Record<string, any> => transaction.commit(),
+      rollback: () => transaction.rollback()
+    };
+  },
   createPipeline(storeId: string, options: DispatchPipelineOptions = {}) {
     return createDispatchPipeline(storeId, options);
   },
@@ -285,6 +496,67 @@ export const GaesupCore = {
     dispatchListeners.set(storeId, listeners);
     return () => listeners.delete(listener);
   },
+  registerNativeReducer(storeId: string, actionType: string, spec: NativeReducerOp[] | ((state: any, payload: any) => any)) {
+    requireReady();
+    const result = typeof spec === 'function'
+      ? wasm.register_reducer(storeId, actionType, null, spec)
+      : wasm.register_reducer(storeId, actionType, spec);
+    const storeActions = nativeActions.get(storeId) ?? new Set<string>();
+    storeActions.add(actionType);
+    nativeActions.set(storeId, storeActions);
+    return result;
+  },
+  unregisterNativeReducer(storeId: string, actionType: string): boolean {
+    requireReady();
+    const storeActions = nativeActions.get(storeId);
+    storeActions?.delete(actionType);
+    if (storeActions?.size === 0) nativeActions.delete(storeId);
+    return (wasm as any).unregister_reducer(storeId, actionType);
+  },
+  setStrictActions(storeId: string, strict: boolean) {
+    requireReady();
+    return wasm.set_store_strict(storeId, strict);
+  },
+  setArrayMergeStrategy(storeId: string, arrayStrategy: 'replace' | 'concat' | 'mergeById', idKey?: string) {
+    requireReady();
+    return (wasm as any).set_array_merge_strategy(storeId, { arrayStrategy, idKey });
+  },
+  selectBytes(storeId: string, path = '', format: BinaryFormat = 'msgpack'): Uint8Array {
+    requireReady();
+    return (wasm as any).select_bytes(storeId, path, format);
+  },
+  async dispatchBytes(storeId: string, actionType: string, payload: Uint8Array = new Uint8Array(), format: BinaryFormat = 'msgpack', framework?: string): Promise<Uint8Array> {
+    await ensureReady();
+    return (wasm as any).dispatch_bytes(storeId, actionType, payload, format, framework);
+  },
+  exportStoreBytes(storeId: string, format: BinaryFormat = 'msgpack'): Uint8Array {
+    requireReady();
+    return (wasm as any).export_store_bytes(storeId, format);
+  },
+  importStoreBytes(storeId: string, bytes: Uint8Array, format: BinaryFormat = 'msgpack') {
+    requireReady();
+    return (wasm as any).import_store_bytes(storeId, bytes, format);
+  },
+  enableCrdt(storeId: string, options: CrdtOptions = {}): string {
+    requireReady();
+    return (wasm as any).enable_crdt(storeId, options);
+  },
+  disableCrdt(storeId: string) {
+    requireReady();
+    (wasm as any).disable_crdt(storeId);
+  },
+  crdtTakeUpdate(storeId: string, format: BinaryFormat = 'msgpack'): Uint8Array {
+    requireReady();
+    return (wasm as any).crdt_take_update(storeId, false, format);
+  },
+  crdtStateUpdate(storeId: string, format: BinaryFormat = 'msgpack'): Uint8Array {
+    requireReady();
+    return (wasm as any).crdt_take_update(storeId, true, format);
+  },
+  crdtMergeUpdate(storeId: string, update: Uint8Array, format: BinaryFormat = 'msgpack') {
+    requireReady();
+    (wasm as any).crdt_merge_update(storeId, update, format);
+  },
   registerReducer(storeId: string, reducer: (state: any, action: Action) => any) {
     const storeReducers = reducers.get(storeId) || new Set();
     storeReducers.add(reducer);
@@ -294,6 +566,80 @@ export const GaesupCore = {
       if (storeReducers.size === 0) reducers.delete(storeId);
     };
   },
+  exportRuntime(options: RuntimeFilterOptions = {}): RuntimeDocument {
+    requireReady();
+    return (wasm as any).export_runtime(options);
+  },
+  importRuntime(document: RuntimeDocument, options: RuntimeFilterOptions = {}): { stores: string[]; renderStores: string[]; containers: string[] } {
+    requireReady();
+    return (wasm as any).import_runtime(document, options);
+  },
+  async enablePersistence(storeId: string, adapter: PersistenceAdapter, options: PersistenceOptions = {}) {
+    await ensureReady();
+    const { onWriteError, ...trackerOptions } = options;
+    const key = options.key ?? storeId;
+    const reportWriteError = onWriteError ?? ((error: unknown, record: PersistenceRecord) => {
+      console.error(`Persistence write failed for ${record.key} (sequence ${record.sequence})`, error);
+    });
+    // Tracking starts only once the stored session is loaded: a record
+    // written before hydration would be a base of the initial state and
+    // replace what the adapter holds.
+    const records = await adapter.read(key);
+    let timer: ReturnType<typeof setTimeout> | undefined;
+    const write = (record: PersistenceRecord) => {
+      try {
+        Promise.resolve(adapter.write(record)).catch((error) => reportWriteError(error, record));
+      } catch (error) {
+        reportWriteError(error, record);
+      }
+    };
+    // The core asks for one trailing flush per throttle window so the last
+    // change of a burst is written once the window closes.
+    const schedule = (delay: number) => {
+      timer = setTimeout(() => {
+        timer = undefined;
+        (wasm as any).flush_store_persistence(storeId, false);
+      }, delay);
+    };
+    (wasm as any).enable_store_persistence(storeId, { ...trackerOptions, key }, write, schedule);
+    let state;
+    try {
+      state = records.length > 0 ? (wasm as any).hydrate_store_persistence(storeId, records) : undefined;
+    } catch (error) {
+      (wasm as any).disable_store_persistence(storeId);
+      throw error;
+    }
+    persistenceTimers.get(storeId)?.();
+    persistenceTimers.set(storeId, () => clearTimeout(timer));
+    return state;
+  },
+  disablePersistence(storeId: string) {
+    requireReady();
+    persistenceTimers.get(storeId)?.();
+    persistenceTimers.delete(storeId);
+    return (wasm as any).disable_store_persistence(storeId);
+  },
+  flushPersistence(storeId: string, compact = false): boolean {
+    requireReady();
+    return (wasm as any).flush_store_persistence(storeId, compact);
+  },
+  connectSync(storeId: string, channel: SyncChannel, options: SyncOptions & { onReport?: (report: SyncReport) => void } = {}) {
+    requireReady();
+    const { onReport, ...syncOptions } = options;
+    const listener = (event: { data: any }) => {
+      const report = (wasm as any).sync_receive(storeId, event.data) as SyncReport;
+      onReport?.(report);
+    };
+    channel.addEventListener('message', listener);
+    const replicaId = (wasm as any).connect_store_sync(storeId, (message: any) => channel.postMessage(message), syncOptions) as string;
+    return {
+      replicaId,
+      disconnect: () => {
+        channel.removeEventListener('message', listener);
+        (wasm as any).disconnect_store_sync(storeId);
+      }
+    };
+  },
   async persistStore(storeId: string, storageKey = storeId) {
     await ensureReady();
     const state = wasm.select(storeId, '');
@@ -313,7 +659,35 @@ export const GaesupCore = {
   }
 };
 
-const callbackRegistry = new Map<string, (state?: any) => void>();
+const callbackRegistry = new Map<string, (state?: any, previous?: any) => void>();
+const persistenceTimers = new Map<string, () => void>();
+
+/** Pair of in-process channels where each side receives what the other posts. */
+export function createLoopbackSyncChannels(): [SyncChannel, SyncChannel] {
+  const listeners = [new Set<(event: { data: any }) => void>(), new Set<(event: { data: any }) => void>()];
+  const side = (own: number, peer: number): SyncChannel => ({
+    postMessage: (message) => queueMicrotask(() => listeners[peer].forEach((listener) => listener({ data: clonePlain(message) }))),
+    addEventListener: (_type, listener) => { listeners[own].add(listener); },
+    removeEventListener: (_type, listener) => { listeners[own].delete(listener); }
+  });
+  return [side(0, 1), side(1, 0)];
+}
+
+/** In-memory adapter: a `base` record replaces everything stored for its key. */
+export function createMemoryPersistenceAdapter(): PersistenceAdapter & { records: Map<string, PersistenceRecord[]> } {
+  const records = new Map<string, PersistenceRecord[]>();
+  return {
+    records,
+    write(record) {
+      const stored = record.kind === 'base' ? [] : records.get(record.key) ?? [];
+      stored.push(clonePlain(record));
+      records.set(record.key, stored);
+    },
+    read(key) {
+      return records.get(key) ?? [];
+    }
+  };
+}
 
 export class CompatibilityGuard {
   constructor(private readonly host: HostCompatibilityConfig = {}) {}
@@ -1480,14 +1854,18 @@ function clonePlain<T>(value: T): T {
   return JSON.parse(JSON.stringify(value));
 }
 
-function isNativeAction(actionType: string) {
-  return actionType === 'SET' || actionType === 'MERGE' || actionType === 'UPDATE' || actionType === 'DELETE' || actionType === 'BATCH';
+const ARRAY_ACTIONS = new Set(['PUSH', 'INSERT', 'SPLICE', 'MOVE', 'REMOVE_WHERE']);
+
+const nativeActions = new Map<string, Set<string>>();
+
+function isNativeAction(storeId: string, actionType: string) {
+  return nativeActions.get(storeId)?.has(actionType) || actionType === 'SET' || actionType === 'MERGE' || actionType === 'DEEP_MERGE' || actionType === 'UPDATE' || actionType === 'DELETE' || actionType === 'PATCH' || actionType === 'BATCH' || ARRAY_ACTIONS.has(actionType);
 }
 
-async function dispatchThroughReducers(storeId: string, action: Action) {
+async function dispatchThroughReducers(storeId: string, action: Action, framework?: string) {
   const storeReducers = reducers.get(storeId);
   if (!storeReducers || storeReducers.size === 0) {
-    return wasm.dispatch(storeId, action.type, action.payload);
+    return wasm.dispatch(storeId, action.type, action.payload, framework);
   }
 
   let nextState = clonePlain(wasm.select(storeId, ''));
@@ -1497,7 +1875,7 @@ async function dispatchThroughReducers(storeId: string, action: Action) {
     nextState = result === undefined ? draft : result;
   }
 
-  return wasm.dispatch(storeId, 'SET', nextState);
+  return wasm.dispatch(storeId, 'SET', nextState, framework);
 }
 
 function writePersistedState(storageKey: string, state: any) {
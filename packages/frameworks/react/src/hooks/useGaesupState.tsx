@@ -65,7 +65,7 @@ export function useGaesupState<T = any>(
 
   // dispatch 함수 (Redux와 동일)
   const dispatch = useCallback((action: Action) => {
-    GaesupCore.dispatch(storeId, action.type, action.payload);
+    GaesupCore.dispatch(storeId, action.type, action.payload, 'react');
   }, [storeId]);
 
   // Redux DevTools 연동
@@ -88,7 +88,7 @@ export function gaesupStore<T = any>(
 
   // dispatch 메서드 (Redux 스타일 액션)
   const dispatch = (action: { type: string; payload?: any }) => {
-    GaesupCore.dispatch(storeId, action.type, action.payload);
+    GaesupCore.dispatch(storeId, action.type, action.payload, 'svelte');
   };
 
   // 스냅샷 생성
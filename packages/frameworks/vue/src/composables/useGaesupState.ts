@@ -210,7 +210,7 @@ export function createVuexCompatibleStore<S>(storeId: string, module: VuexModule
           commit: (type: string, payload?: any) => {
             if (module.mutations && module.mutations[type]) {
               module.mutations[type](this.$state, payload);
-              GaesupCore.dispatch(storeId, `mutation/${type}`, payload);
+              GaesupCore.dispatch(storeId, `mutation/${type}`, payload, 'vue');
             }
           },
           dispatch: (type: string, payload?: any) => {
@@ -233,7 +233,7 @@ export function useStore(storeId: string) {
   return {
     state: computed(() => GaesupCore.select(storeId, '')),
     dispatch: (action: string, payload?: any) => {
-      GaesupCore.dispatch(storeId, action, payload);
+      GaesupCore.dispatch(storeId, action, payload, 'vue');
     },
     select: <T = any>(path: string): ComputedRef<T> => {
       return computed(() => GaesupCore.select(storeId, path) as T);